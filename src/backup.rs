@@ -0,0 +1,151 @@
+use crate::db::Db;
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+const FILE_NAME_FORMAT: &str = "db-%Y-%m-%d-%H%M%S.sqlite";
+
+/// A folder of database backups, each named after the local time it was made.
+pub struct Backups {
+    dir: PathBuf,
+}
+
+impl Backups {
+    pub fn new(dir: PathBuf) -> Self {
+        Backups { dir }
+    }
+
+    pub fn get_default_dir() -> PathBuf {
+        let mut dir = Db::get_data_dir();
+        dir.push("backups");
+        dir
+    }
+
+    pub fn create(&self, db: &Db) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let mut path = self.dir.clone();
+        path.push(Local::now().format(FILE_NAME_FORMAT).to_string());
+        db.backup(&path)?;
+        Ok(path)
+    }
+
+    /// Returns the backups and the time they were made, oldest first. Other files are ignored.
+    pub fn list(&self) -> Result<Vec<(NaiveDateTime, PathBuf)>, Box<dyn Error>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let time = path.file_name()
+                .and_then(|name| NaiveDateTime::parse_from_str(&name.to_string_lossy(), FILE_NAME_FORMAT).ok());
+            if let Some(time) = time {
+                backups.push((time, path));
+            }
+        }
+        backups.sort();
+        Ok(backups)
+    }
+
+    /// Makes a backup if the newest one is at least a day old, then deletes all but the `keep`
+    /// newest ones. Returns the new backup if one was made.
+    pub fn create_daily(&self, db: &Db, keep: usize) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let is_due = match self.list()?.last() {
+            Some((time, _)) => Local::now().naive_local() - *time >= TimeDelta::days(1),
+            None => true,
+        };
+        if !is_due {
+            return Ok(None);
+        }
+        let path = self.create(db)?;
+        self.keep_latest(keep)?;
+        Ok(Some(path))
+    }
+
+    /// Deletes all but the `keep` newest backups and returns how many were deleted.
+    pub fn keep_latest(&self, keep: usize) -> Result<usize, Box<dyn Error>> {
+        let backups = self.list()?;
+        let count = backups.len().saturating_sub(keep);
+        for (_, path) in &backups[..count] {
+            fs::remove_file(path)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datetime::Calendar;
+    use crate::db::Interval;
+    use tempfile::TempDir;
+
+    fn add_interval(db: &Db, time_from: i64) {
+        db.add_interval(&Interval {
+            rowid: 0,
+            window_name: "code.exe".to_string(),
+            title: None,
+            project: None,
+            time_from,
+            time_to: time_from + 60_000,
+            utc_offset: Some(0),
+            manual: true,
+        }, &Calendar::default()).unwrap();
+    }
+
+    /// A backup as if it had been made on the day of June 2024.
+    fn backup_of(db: &Db, backups: &Backups, day: u32) -> PathBuf {
+        let path = backups.dir.join(format!("db-2024-06-{day:02}-120000.sqlite"));
+        db.backup(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_backups() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path().to_path_buf());
+        let db = Db::open_in_memory().unwrap();
+        // made in another order than they are named in
+        let paths: Vec<PathBuf> = [12, 3, 21, 9, 15].into_iter().map(|day| backup_of(&db, &backups, day)).collect();
+        fs::write(dir.path().join("notes.txt"), "not a backup").unwrap();
+
+        assert_eq!(backups.keep_latest(3).unwrap(), 2);
+        let kept: Vec<PathBuf> = backups.list().unwrap().into_iter().map(|(_, path)| path).collect();
+        assert_eq!(kept, vec![paths[0].clone(), paths[4].clone(), paths[2].clone()]);
+        assert!(dir.path().join("notes.txt").exists());
+        assert_eq!(backups.keep_latest(3).unwrap(), 0);
+    }
+
+    #[test]
+    fn daily_backup_is_made_once_a_day() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path().to_path_buf());
+        let db = Db::open_in_memory().unwrap();
+        for day in 1..=3 {
+            backup_of(&db, &backups, day);
+        }
+        let path = backups.create_daily(&db, 2).unwrap().unwrap();
+        let list = backups.list().unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.last().unwrap().1, path);
+        assert_eq!(backups.create_daily(&db, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn restore_brings_back_the_backup() {
+        let dir = TempDir::new().unwrap();
+        let backups = Backups::new(dir.path().to_path_buf());
+        let mut db = Db::open_in_memory().unwrap();
+        add_interval(&db, 0);
+        let path = backups.create(&db).unwrap();
+        add_interval(&db, 600_000);
+        assert_eq!(db.get_intervals(None).unwrap().len(), 2);
+
+        db.restore(&path).unwrap();
+        let intervals = db.get_intervals(None).unwrap();
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].time_from, 0);
+        assert!(db.restore(&dir.path().join("missing.sqlite")).is_err());
+    }
+}
@@ -0,0 +1,483 @@
+use chrono::{DateTime, Days, Local, NaiveDate};
+use common::backup::Backups;
+use common::config::Config;
+use common::datetime::{format_duration, format_local_time, parse_local_time, DateTimeExtensions};
+use common::db::{Db, Interval};
+use common::digest::Digest;
+use common::privacy::PrivacyFilter;
+use common::project::ProjectMatcher;
+use common::query::{GroupBy, StatsQuery};
+use common::sync::SyncFolder;
+use common::theme::parse_color;
+use common::timesheet::{RoundingMode, Timesheet};
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "Usage: cli <command>
+
+Commands:
+  purge                        apply the privacy rules from config.toml to already recorded activity
+  projects                     apply the project rules from config.toml to already recorded activity
+  report [options]             print the time spent per app
+    --from <yyyy-mm-dd>        first day of the report
+    --to <yyyy-mm-dd>          last day of the report
+    --app <name>               only count this app, can be repeated
+    --category <name>          only count apps of this category, can be repeated
+    --project <name>           only count time attributed to this project, can be repeated
+    --device <name>            only count time recorded on this device, can be repeated
+    --group-by <key>           app, title, category, project, device, day or hour
+    --per-day                  split every row into the days the time was spent on
+    --min-duration <seconds>   leave out rows with less time
+    --limit <rows>             print at most this many rows
+  timesheet [options]          print the rounded time per project and day of a week
+    --week <yyyy-mm-dd>        any day of the week, the current one by default
+    --group-by <key>           project, app or category, project by default
+    --format <format>          text, csv or markdown
+    --increment <minutes>      round to a multiple of it, instead of the one in config.toml
+    --rounding <mode>          nearest, up or down
+    --minimum <minutes>        bill any time at least this long
+  digest [options]             write a summary of a week as an HTML file
+    --week <yyyy-mm-dd>        any day of the week, the previous one by default
+    --output <file>            where to write it, into the digests folder by default
+  intervals [options]          list the intervals recorded on this device, the latest first
+    --from <yyyy-mm-dd>        first day to list
+    --to <yyyy-mm-dd>          last day to list
+  add <app> [options]          enter an interval by hand, it must not overlap another one
+    --from <yyyy-mm-dd hh:mm>  start of the interval, required
+    --to <yyyy-mm-dd hh:mm>    end of the interval, required
+    --title <title>            window title, the app by default
+    --project <name>           project, attributed by the rules in config.toml by default
+  edit <id> [options]          change an interval, with the options of add and --app <name>
+  split <id> --at <yyyy-mm-dd hh:mm>
+                               cut an interval in two
+  delete <id>                  delete an interval
+  undo                         revert the last add, edit, split or delete
+  category <app> [<category>]  set the category of an app, or clear it
+  color <app> [<color>]        set the color of an app, e.g. red or #4e79a7, or clear it
+  sync                         exchange intervals with the other devices through the sync folder
+  backup [<file>]              copy the database, into the backups folder by default
+  restore <file>               replace the database with a backup, the tracker has to be exited first
+  prune --older-than <days>    delete what was recorded before the last <days> days, after a backup
+    --rollup                   keep the time of the deleted intervals per app and day";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("purge") => purge(),
+        Some("projects") => projects(),
+        Some("report") => report(&args[1..]),
+        Some("timesheet") => timesheet(&args[1..]),
+        Some("digest") => digest(&args[1..]),
+        Some("intervals") => intervals(&args[1..]),
+        Some("add") if args.len() >= 2 => add(&args[1], &args[2..]),
+        Some("edit") if args.len() >= 2 => edit(&args[1], &args[2..]),
+        Some("split") if args.len() >= 2 => split(&args[1], &args[2..]),
+        Some("delete") if args.len() == 2 => delete(&args[1]),
+        Some("undo") => undo(),
+        Some("category") if args.len() == 2 || args.len() == 3 => category(&args[1], args.get(2)),
+        Some("color") if args.len() == 2 || args.len() == 3 => color(&args[1], args.get(2)),
+        Some("sync") => sync(),
+        Some("backup") if args.len() <= 2 => backup(args.get(1)),
+        Some("restore") if args.len() == 2 => restore(&args[1]),
+        Some("prune") => prune(&args[1..]),
+        _ => {
+            println!("{USAGE}");
+            exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {err}");
+        exit(1);
+    }
+}
+
+/// Splits `--name value` pairs, a repeated option yields a pair per value.
+fn parse_options(args: &[String]) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    args.chunks(2)
+        .map(|pair| match pair {
+            [name, value] if name.starts_with("--") => Ok((name[2..].to_string(), value.clone())),
+            _ => Err(format!("Unexpected argument: {}", pair[0]).into()),
+        })
+        .collect()
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date {value}, expected yyyy-mm-dd").into())
+}
+
+fn parse_time(value: &str) -> Result<i64, Box<dyn Error>> {
+    parse_local_time(value).ok_or(format!("Invalid time {value}, expected yyyy-mm-dd hh:mm").into())
+}
+
+fn parse_rowid(value: &str) -> Result<i64, Box<dyn Error>> {
+    value.parse().map_err(|_| format!("Invalid interval id {value}").into())
+}
+
+fn purge() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let privacy = PrivacyFilter::new(&config.privacy)?;
+    let db = Db::init()?;
+    if privacy.purge(&db, |message| println!("{message}"))? {
+        db.rebuild_summary(&config.calendar)?;
+    }
+    Ok(())
+}
+
+/// Attributes the recorded intervals again, e.g. after a project rule was added. Summaries of
+/// pruned days keep the projects they were rolled up with.
+fn projects() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let projects = ProjectMatcher::new(&config.projects)?;
+    let db = Db::init()?;
+    let mut changed = 0;
+    for (window_name, title) in db.get_samples()? {
+        // the exe name is not stored, the window name stands in for it
+        let title_or_name = title.clone().unwrap_or_else(|| window_name.clone());
+        let project = projects.get_project(&window_name, &title_or_name);
+        changed += db.set_sample_project(&window_name, &title, project.as_ref())?;
+    }
+    if changed > 0 {
+        db.rebuild_summary(&config.calendar)?;
+    }
+    println!("Attributed {changed} intervals to other projects");
+    Ok(())
+}
+
+fn report(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    db.check_summary(&config.calendar)?;
+    let mut query = StatsQuery::new().calendar(config.calendar);
+    if args.iter().any(|arg| arg == "--per-day") {
+        query = query.per_day();
+    }
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--per-day").cloned().collect();
+    let mut from = None;
+    let mut to = None;
+    for (name, value) in parse_options(&args)? {
+        query = match name.as_str() {
+            "from" => {
+                from = Some(parse_date(&value)?);
+                query
+            }
+            "to" => {
+                to = Some(parse_date(&value)?);
+                query
+            }
+            "app" => query.app(value),
+            "category" => query.category(value),
+            "project" => query.project(value),
+            // intervals recorded here are stored without a device
+            "device" if value == config.sync.get_device(&db)? => query.device(None),
+            "device" => query.device(Some(value)),
+            "group-by" => query.group_by(
+                GroupBy::parse(&value).ok_or(format!("Unknown grouping: {value}"))?
+            ),
+            "min-duration" => query.min_millis(value.parse::<i64>()? * 1000),
+            "limit" => query.limit(value.parse()?),
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        };
+    }
+    if from.is_some() || to.is_some() {
+        let today = config.calendar.date_of(&Local::now());
+        let from = match from {
+            Some(from) => from,
+            None => db.get_min_date()?.unwrap_or(today),
+        };
+        let to = to.unwrap_or(today);
+        query = query.period(Some(config.calendar.period(from, to)));
+    }
+
+    let stats = db.get_stats(&query)?;
+    let names: Vec<String> = stats.iter()
+        .map(|stat| match stat.day {
+            Some(day) => format!("{}  {}", day.format("%Y-%m-%d"), stat.key),
+            None => stat.key.to_string(),
+        })
+        .collect();
+    let width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0);
+    for (name, stat) in names.iter().zip(&stats) {
+        println!("{name:<width$}  {}", format_duration(stat.millis));
+    }
+    let total_millis = stats.iter().map(|stat| stat.millis).sum();
+    println!("{:<width$}  {}", "Total", format_duration(total_millis));
+    // downtime is not part of any app's time, it is listed so it is not mistaken for idling
+    for downtime in db.get_downtime(query.get_period())? {
+        println!("{:<width$}  {}", downtime.kind.as_title(), format_duration(downtime.millis));
+    }
+    Ok(())
+}
+
+fn timesheet(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let mut rounding = config.timesheet;
+    let mut week = config.calendar.date_of(&Local::now());
+    let mut group_by = GroupBy::Project;
+    let mut format = "text".to_string();
+    for (name, value) in parse_options(args)? {
+        match name.as_str() {
+            "week" => week = parse_date(&value)?,
+            "group-by" => group_by = GroupBy::parse(&value).ok_or(format!("Unknown grouping: {value}"))?,
+            "format" => format = value,
+            "increment" => rounding.increment = value.parse()?,
+            "rounding" => rounding.mode = RoundingMode::parse(&value).ok_or(format!("Unknown rounding: {value}"))?,
+            "minimum" => rounding.minimum = value.parse()?,
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    let key_title = match group_by {
+        GroupBy::Project => "Project",
+        GroupBy::App => "App",
+        GroupBy::Category => "Category",
+        _ => return Err("A timesheet can only be grouped by project, app or category".into()),
+    };
+    let db = Db::init()?;
+    db.check_summary(&config.calendar)?;
+    let first_day = config.calendar.week_of(week);
+    let days: Vec<NaiveDate> = first_day.iter_days().take(7).collect();
+    let query = StatsQuery::new()
+        .period(Some(config.calendar.period(first_day, first_day + Days::new(6))))
+        .group_by(group_by)
+        .per_day()
+        .calendar(config.calendar);
+    let timesheet = Timesheet::new(key_title, days, &db.get_stats(&query)?, &rounding);
+    let output = match format.as_str() {
+        "text" => timesheet.to_text(),
+        "csv" => timesheet.to_csv(),
+        "markdown" => timesheet.to_markdown(),
+        _ => return Err(format!("Unknown format: {format}").into()),
+    };
+    print!("{output}");
+    Ok(())
+}
+
+fn digest(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let mut week = config.calendar.date_of(&Local::now()) - Days::new(7);
+    let mut output = None;
+    for (name, value) in parse_options(args)? {
+        match name.as_str() {
+            "week" => week = parse_date(&value)?,
+            "output" => output = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    let db = Db::init()?;
+    db.check_summary(&config.calendar)?;
+    let first_day = config.calendar.week_of(week);
+    let path = output.unwrap_or_else(|| Digest::get_default_dir().join(Digest::get_file_name(first_day)));
+    Digest::load(&db, &config.calendar, first_day)?.save(&path)?;
+    println!("Written to {}", path.display());
+    Ok(())
+}
+
+fn intervals(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    let mut from = None;
+    let mut to = None;
+    for (name, value) in parse_options(args)? {
+        match name.as_str() {
+            "from" => from = Some(parse_date(&value)?),
+            "to" => to = Some(parse_date(&value)?),
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    let period = match (from, to) {
+        (None, None) => None,
+        (from, to) => Some(config.calendar.period(
+            from.unwrap_or(NaiveDate::MIN),
+            to.unwrap_or_else(|| config.calendar.date_of(&Local::now())),
+        )),
+    };
+    for interval in db.get_intervals(period)? {
+        println!(
+            "{:>6}  {} - {}  {:<12}  {}{}{}{}",
+            interval.rowid,
+            format_local_time(interval.time_from),
+            format_local_time(interval.time_to),
+            format_duration(interval.time_to - interval.time_from),
+            interval.window_name,
+            interval.title.map(|title| format!("  {title}")).unwrap_or_default(),
+            interval.project.map(|project| format!("  [{project}]")).unwrap_or_default(),
+            if interval.manual { "  (manual)" } else { "" },
+        );
+    }
+    Ok(())
+}
+
+/// Sets the fields given as options, returns whether the project was given.
+fn apply_interval_options(interval: &mut Interval, args: &[String]) -> Result<bool, Box<dyn Error>> {
+    let mut has_project = false;
+    for (name, value) in parse_options(args)? {
+        match name.as_str() {
+            "app" => interval.window_name = value,
+            "from" => {
+                interval.time_from = parse_time(&value)?;
+                // the offset the interval is shown in is the one in effect when it started
+                let start = DateTime::from_timestamp_millis(interval.time_from).unwrap_or_default();
+                interval.utc_offset = Some(start.with_timezone(&Local).offset().local_minus_utc());
+            }
+            "to" => interval.time_to = parse_time(&value)?,
+            "title" => interval.title = Some(value),
+            "project" => {
+                interval.project = Some(value);
+                has_project = true;
+            }
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    Ok(has_project)
+}
+
+fn add(app: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    let mut interval = Interval {
+        rowid: 0,
+        window_name: app.to_string(),
+        title: None,
+        project: None,
+        time_from: 0,
+        time_to: 0,
+        utc_offset: None,
+        manual: true,
+    };
+    let has_project = apply_interval_options(&mut interval, args)?;
+    if interval.utc_offset.is_none() || interval.time_to == 0 {
+        return Err("--from and --to are required".into());
+    }
+    if !has_project {
+        let title = interval.title.clone().unwrap_or_else(|| app.to_string());
+        interval.project = ProjectMatcher::new(&config.projects)?.get_project(app, &title);
+    }
+    let interval = PrivacyFilter::new(&config.privacy)?.apply_to_interval(&interval)
+        .ok_or(format!("{app} is dropped by the privacy rules"))?;
+    let rowid = db.add_interval(&interval, &config.calendar)?;
+    println!("Added interval {rowid}");
+    Ok(())
+}
+
+fn edit(rowid: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    let rowid = parse_rowid(rowid)?;
+    let mut interval = db.get_interval(rowid)?
+        .ok_or(format!("There is no interval {rowid} recorded on this device"))?;
+    apply_interval_options(&mut interval, args)?;
+    let interval = PrivacyFilter::new(&config.privacy)?.apply_to_interval(&interval)
+        .ok_or(format!("{} is dropped by the privacy rules", interval.window_name))?;
+    db.edit_interval(&interval, &config.calendar)?;
+    println!("Changed interval {rowid}");
+    Ok(())
+}
+
+fn split(rowid: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    let mut at = None;
+    for (name, value) in parse_options(args)? {
+        match name.as_str() {
+            "at" => at = Some(parse_time(&value)?),
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    let at = at.ok_or("--at is required")?;
+    let second = db.split_interval(parse_rowid(rowid)?, at, &config.calendar)?;
+    println!("Split off interval {second}");
+    Ok(())
+}
+
+fn delete(rowid: &str) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    db.delete_interval(parse_rowid(rowid)?, &config.calendar)?;
+    println!("Deleted interval {rowid}");
+    Ok(())
+}
+
+fn undo() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    match db.undo(&config.calendar)? {
+        Some(action) => println!("Undid {action}"),
+        None => println!("Nothing to undo"),
+    }
+    Ok(())
+}
+
+fn category(app: &String, category: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let db = Db::init()?;
+    db.set_category(app, category)?;
+    Ok(())
+}
+
+fn color(app: &String, color: Option<&String>) -> Result<(), Box<dyn Error>> {
+    if let Some(color) = color {
+        parse_color(color).ok_or(format!("Unknown color {color}"))?;
+    }
+    let db = Db::init()?;
+    db.set_app_color(app, color)?;
+    Ok(())
+}
+
+fn sync() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let db = Db::init()?;
+    let sync = SyncFolder::from_config(&config, &db)?.ok_or("No sync folder is set in config.toml")?;
+    let (exported, imported) = sync.sync(&db)?;
+    println!("Exported {exported} and imported {imported} intervals as {}", sync.get_device());
+    Ok(())
+}
+
+fn backup(path: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let db = Db::init()?;
+    let path = match path {
+        Some(path) => {
+            db.backup(Path::new(path))?;
+            path.into()
+        }
+        None => Backups::new(Backups::get_default_dir()).create(&db)?,
+    };
+    println!("Backed up to {}", path.display());
+    Ok(())
+}
+
+fn restore(path: &String) -> Result<(), Box<dyn Error>> {
+    let mut db = Db::init()?;
+    // the tracker would keep writing the intervals it has open into the restored database
+    if let Some(state) = db.get_tracker_state()? {
+        if state.is_running(Local::now().timestamp_millis()) {
+            return Err("The tracker is running, exit it from the tray first".into());
+        }
+    }
+    db.restore(Path::new(path))?;
+    println!("Restored {path}");
+    Ok(())
+}
+
+fn prune(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rollup = args.iter().any(|arg| arg == "--rollup");
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--rollup").cloned().collect();
+    let mut days: Option<u64> = None;
+    for (name, value) in parse_options(&args)? {
+        match name.as_str() {
+            "older-than" => days = Some(value.parse().map_err(|_| format!("Invalid number of days {value}"))?),
+            _ => return Err(format!("Unknown option: --{name}").into()),
+        }
+    }
+    let days = days.ok_or("--older-than is required")?;
+    let config = Config::load()?;
+    let before = Local::now().start_of_day_ts(&config.calendar, days)
+        .ok_or(format!("{days} days ago is before the earliest supported date"))?;
+    let db = Db::init()?;
+    let backup = Backups::new(Backups::get_default_dir()).create(&db)?;
+    println!("Backed up to {}", backup.display());
+    let deleted = db.prune(before, rollup, &config.calendar)?;
+    db.vacuum()?;
+    println!("Deleted {deleted} intervals");
+    Ok(())
+}
@@ -0,0 +1,636 @@
+use chrono::{DateTime, Days, Local, NaiveDate};
+use common::backup::Backups;
+use common::config::{BackupConfig, Config, DigestConfig};
+use common::datetime::{Calendar, DateTimeExtensions};
+use common::db::{Db, DowntimeKind, TrackerState, HEARTBEAT_INTERVAL_MILLIS};
+use common::digest::Digest;
+use common::foreground::ForegroundListener;
+use common::privacy::{PrivacyFilter, Sample};
+use common::project::ProjectMatcher;
+use common::session::{SessionEvent, SessionEvents, SessionState};
+use common::sync::SyncFolder;
+use common::window::Window;
+use std::error::Error;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+#[cfg(windows)]
+use {
+    auto_launch::AutoLaunchBuilder,
+    common::session::SessionListener,
+    common::tray::{Events, Tray},
+    std::env::current_exe,
+    std::process::{Child, Command},
+    std::thread,
+};
+#[cfg(target_os = "linux")]
+use common::session::LogindSessionEvents;
+
+#[cfg(windows)]
+pub fn main() {
+    let db_exists = Db::get_path().exists();
+    if !db_exists{
+        enable_auto_launch();
+    }
+    let config = Config::load().unwrap();
+    let (tracker_sender, tracker_receiver) = mpsc::channel::<TrackerEvents>();
+    if config.tracker.foreground_events {
+        let foreground_sender = tracker_sender.clone();
+        ForegroundListener::init(move || {
+            let _ = foreground_sender.send(TrackerEvents::Foreground);
+        });
+    }
+    thread::spawn(move || {
+        Tracker::new(config, tracker_receiver).init();
+        std::process::exit(0);
+    });
+    let session_sender = tracker_sender.clone();
+    SessionListener.listen(move |event, time| {
+        let _ = session_sender.send(TrackerEvents::Session(event, time));
+    }).unwrap();
+    let (s, r) = mpsc::channel::<Events>();
+    let mut tray = Tray::init(s);
+    thread::spawn(move || {
+        let mut ui: Option<Child> = None;
+        if !db_exists {
+            ui = spawn_ui();
+        }
+        r.iter().for_each(|m| {
+            match m {
+                Events::RightClickTrayIcon => {
+                    tray.show_menu();
+                }
+                Events::DoubleClickTrayIcon | Events::Open => {
+                    if let Some(child) = &mut ui {
+                        child.kill().unwrap();
+                    }
+                    ui = Command::new("ui.exe").spawn().unwrap().into();
+                }
+                Events::Exit => {
+                    if let Some(child) = &mut ui {
+                        child.kill().unwrap();
+                    }
+                    // the tracker exits the process once the open interval is written
+                    tracker_sender.send(TrackerEvents::Stop).unwrap();
+                }
+            }
+        })
+    });
+    Tray::handle_win_messages();
+}
+
+/// Without a tray the tracker runs until it is killed, the next start closes what it left open.
+#[cfg(target_os = "linux")]
+pub fn main() {
+    let config = Config::load().unwrap();
+    let (tracker_sender, tracker_receiver) = mpsc::channel::<TrackerEvents>();
+    if config.tracker.foreground_events {
+        let foreground_sender = tracker_sender.clone();
+        ForegroundListener::init(move || {
+            let _ = foreground_sender.send(TrackerEvents::Foreground);
+        });
+    }
+    let session_sender = tracker_sender.clone();
+    if let Err(err) = LogindSessionEvents.listen(move |event, time| {
+        let _ = session_sender.send(TrackerEvents::Session(event, time));
+    }) {
+        println!("Could not follow the session: {err}");
+    }
+    Tracker::new(config, tracker_receiver).init();
+    drop(tracker_sender);
+}
+
+#[cfg(windows)]
+fn enable_auto_launch() -> bool {
+    let current_exe = current_exe().ok()
+        .and_then(|path| path.to_str().map(|s| s.to_string()));
+    if current_exe.is_none() {
+        return false;
+    }
+    AutoLaunchBuilder::new()
+        .set_app_name("AppTime")
+        .set_app_path(&current_exe.unwrap())
+        .build()
+        .and_then(|auto| auto.enable())
+        .ok()
+        .is_some()
+}
+
+#[cfg(windows)]
+fn spawn_ui() -> Option<Child> {
+    Command::new("ui.exe").spawn().unwrap().into()
+}
+
+/// How often the end of the open interval is written while it keeps going, the heartbeat
+/// is written with it.
+const FLUSH_INTERVAL_MILLIS: i64 = HEARTBEAT_INTERVAL_MILLIS;
+/// A longer gap between two ticks means the system was suspended without telling.
+const MAX_TICK_GAP_MILLIS: i64 = 15_000;
+/// How often intervals are exchanged with the other devices.
+const SYNC_INTERVAL_MILLIS: i64 = 5 * 60_000;
+/// How often the tracker checks whether the daily backup or the weekly digest is due.
+const SCHEDULE_CHECK_INTERVAL_MILLIS: i64 = 60 * 60_000;
+/// How long the tracker waits for an event before the next tick.
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What wakes the tracker up before its next tick.
+pub enum TrackerEvents {
+    /// Another window came to the foreground, it is sampled right away.
+    Foreground,
+    /// A lock or power notification and the time it was sent at.
+    Session(SessionEvent, i64),
+    Stop,
+}
+
+/// Where the tracker gets the time and the active window from, and how it waits for events.
+pub trait Host {
+    fn now(&self) -> DateTime<Local>;
+
+    /// The active window before the privacy rules are applied, the default one for the desktop.
+    fn sample(&self) -> Sample;
+
+    fn recv(&self, events: &Receiver<TrackerEvents>, timeout: Duration) -> Result<TrackerEvents, RecvTimeoutError>;
+}
+
+/// The system clock and the window in the foreground.
+pub struct Desktop;
+
+impl Host for Desktop {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn sample(&self) -> Sample {
+        let window = Window::get_active();
+        Sample {
+            exe_name: window.get_exe_name().unwrap_or_default(),
+            title: window.get_title().unwrap_or_default(),
+            display_name: window.get_display_name().unwrap_or_default(),
+        }
+    }
+
+    fn recv(&self, events: &Receiver<TrackerEvents>, timeout: Duration) -> Result<TrackerEvents, RecvTimeoutError> {
+        events.recv_timeout(timeout)
+    }
+}
+
+/// Work done between two ticks every `interval_millis`, and once when the tracker starts.
+pub struct ScheduledTask {
+    pub interval_millis: i64,
+    pub run: Box<dyn Fn(&Tracker)>,
+}
+
+pub struct Tracker {
+    pub db: Db,
+    pub privacy: PrivacyFilter,
+    pub projects: ProjectMatcher,
+    pub calendar: Calendar,
+    pub sync: Option<SyncFolder>,
+    pub backup: BackupConfig,
+    pub digest: DigestConfig,
+    pub tasks: Vec<ScheduledTask>,
+    pub host: Box<dyn Host>,
+    pub events: Receiver<TrackerEvents>,
+}
+
+/// The interval being recorded. Its end is kept in memory and only written every
+/// `FLUSH_INTERVAL_MILLIS`, when the interval is closed, or when the tracker stops.
+struct OpenInterval {
+    rowid: i64,
+    /// Intervals are split at day starts, so the whole interval is in the summary of this day.
+    day: NaiveDate,
+    time_to: i64,
+    flushed_at: i64,
+}
+
+impl OpenInterval {
+    fn new(rowid: i64, day: NaiveDate, time_from: i64) -> Self {
+        OpenInterval { rowid, day, time_to: time_from, flushed_at: time_from }
+    }
+}
+
+/// The window seen on the last tick and the interval it is recorded in.
+struct Recording {
+    display_name: String,
+    title: String,
+    project: Option<String>,
+    time_from: DateTime<Local>,
+    utc_offset: i32,
+    open: Option<OpenInterval>,
+}
+
+impl Tracker {
+    pub fn new(config: Config, events: Receiver<TrackerEvents>) -> Tracker {
+        let db = Db::init().unwrap();
+        Tracker {
+            privacy: PrivacyFilter::new(&config.privacy).unwrap(),
+            projects: ProjectMatcher::new(&config.projects).unwrap(),
+            calendar: config.calendar,
+            sync: SyncFolder::from_config(&config, &db).unwrap(),
+            db,
+            backup: config.backup,
+            digest: config.digest,
+            tasks: vec![
+                ScheduledTask { interval_millis: SYNC_INTERVAL_MILLIS, run: Box::new(Tracker::sync) },
+                ScheduledTask { interval_millis: SCHEDULE_CHECK_INTERVAL_MILLIS, run: Box::new(Tracker::backup) },
+                ScheduledTask { interval_millis: SCHEDULE_CHECK_INTERVAL_MILLIS, run: Box::new(Tracker::digest) },
+            ],
+            host: Box::new(Desktop),
+            events,
+        }
+    }
+
+    pub fn init(&self) {
+        // был раб.стол - пришел раб.стол +
+        // был раб.стол - пришло окно (update time_to) +
+        // было окно - пришло такое же окно (update time_to) +
+        // было окно - пришло окно +
+        // было окно - пришел раб.стол +
+
+        let time_from = self.host.now();
+        let mut recording = Recording {
+            display_name: String::new(),
+            title: String::new(),
+            project: None,
+            time_from,
+            utc_offset: time_from.offset().local_minus_utc(),
+            open: None,
+        };
+        let mut session = SessionState::default();
+        let mut last_beat = TrackerState { heartbeat: 0, open_rowid: None, clean_shutdown: false };
+        self.db.check_summary(&self.calendar).unwrap();
+        self.recover();
+        for task in &self.tasks {
+            (task.run)(self);
+        }
+        let mut last_runs = vec![time_from.timestamp_millis(); self.tasks.len()];
+        let mut last_tick = self.host.now().timestamp_millis();
+        loop {
+            let now = self.host.now();
+            let now_ts = now.timestamp_millis();
+            if !session.is_paused() && now_ts > last_tick + MAX_TICK_GAP_MILLIS {
+                self.close(&mut recording, last_tick);
+                self.db.insert_downtime(DowntimeKind::Sleep, last_tick, now_ts).unwrap();
+            }
+            if !session.is_paused() {
+                let (sample, project) = self.get_sample();
+                self.tick(&mut recording, sample, project, now);
+            }
+            // the heartbeat is written along with the flushes, so it is not behind the end written
+            // for the open interval, and once a minute while nothing is open
+            let open_rowid = recording.open.as_ref().map(|interval| interval.rowid);
+            let is_flushed = recording.open.as_ref().is_some_and(|interval| interval.flushed_at > last_beat.heartbeat);
+            let is_due = open_rowid.is_none() && now_ts - last_beat.heartbeat >= HEARTBEAT_INTERVAL_MILLIS;
+            if open_rowid != last_beat.open_rowid || is_flushed || is_due {
+                last_beat = self.beat(now_ts, open_rowid);
+            }
+            for (task, last_run) in self.tasks.iter().zip(&mut last_runs) {
+                if now_ts - *last_run >= task.interval_millis {
+                    (task.run)(self);
+                    *last_run = now_ts;
+                }
+            }
+            // the gap is measured from here, slow scheduled work is not mistaken for a suspend
+            last_tick = self.host.now().timestamp_millis();
+            match self.wait() {
+                Some(TrackerEvents::Stop) => {
+                    if let Some(mut interval) = recording.open.take() {
+                        let time_to = interval.time_to;
+                        self.db.in_transaction(|| {
+                            self.flush(&recording.display_name, recording.project.as_ref(), &mut interval, time_to)
+                        }).unwrap();
+                    }
+                    self.shut_down(now_ts);
+                    self.sync();
+                    return;
+                }
+                Some(TrackerEvents::Session(event, event_ts)) => {
+                    let ended = session.handle(event, event_ts);
+                    if session.is_paused() {
+                        self.close(&mut recording, event_ts);
+                    }
+                    if let Some((kind, time_from, time_to)) = ended {
+                        self.db.insert_downtime(kind, time_from, time_to).unwrap();
+                    }
+                    last_tick = event_ts;
+                }
+                Some(TrackerEvents::Foreground) | None => {}
+            }
+        }
+    }
+
+    fn tick(&self, recording: &mut Recording, sample: Sample, new_project: Option<String>, now: DateTime<Local>) {
+        let Sample { display_name: new_display_name, title: new_title, .. } = sample;
+        if recording.display_name.is_empty() && new_display_name.is_empty() {
+            return;
+        }
+        let now_ts = now.timestamp_millis();
+        if let Some(interval) = &mut recording.open {
+            // the interval is split at the start of every day it spans
+            for _ in 0..recording.time_from.num_days_between_starts(&self.calendar, now) {
+                let next_date = self.calendar.date_of(&recording.time_from) + Days::new(1);
+                let new_day = self.calendar.day_start(&Local, next_date);
+                let new_day_ts = new_day.timestamp_millis();
+                recording.utc_offset = new_day.offset().local_minus_utc();
+                let project = recording.project.as_ref();
+                self.db.in_transaction(|| {
+                    self.flush(&recording.display_name, project, interval, new_day_ts)?;
+                    *interval = OpenInterval::new(
+                        self.db.insert(&recording.display_name, &recording.title, project, new_day_ts, recording.utc_offset)?,
+                        next_date,
+                        new_day_ts,
+                    );
+                    Ok(())
+                }).unwrap();
+                recording.time_from = new_day;
+            }
+            interval.time_to = now_ts;
+            if now_ts - interval.flushed_at >= FLUSH_INTERVAL_MILLIS {
+                self.db.in_transaction(|| {
+                    self.flush(&recording.display_name, recording.project.as_ref(), interval, now_ts)
+                }).unwrap();
+            }
+        }
+        // a new interval is also started when the title or the offset changes (DST or a timezone
+        // change), so every interval is recorded with the single title and offset it was spent in
+        let offset_changed = now.offset().local_minus_utc() != recording.utc_offset;
+        let sample_changed = !recording.display_name.eq(&new_display_name) || !recording.title.eq(&new_title);
+        if sample_changed || offset_changed {
+            self.db.in_transaction(|| {
+                if let Some(mut interval) = recording.open.take() {
+                    self.flush(&recording.display_name, recording.project.as_ref(), &mut interval, now_ts)?;
+                }
+                if !new_display_name.is_empty() {
+                    recording.utc_offset = now.offset().local_minus_utc();
+                    let rowid = self.db.insert(&new_display_name, &new_title, new_project.as_ref(), now_ts, recording.utc_offset)?;
+                    recording.open = Some(OpenInterval::new(rowid, self.calendar.date_of(&now), now_ts));
+                    recording.time_from = now;
+                }
+                Ok(())
+            }).unwrap();
+        }
+        recording.display_name = new_display_name;
+        recording.title = new_title;
+        recording.project = new_project;
+    }
+
+    /// Ends the open interval at `time_to`, the next tick starts a new one.
+    fn close(&self, recording: &mut Recording, time_to: i64) {
+        if let Some(mut interval) = recording.open.take() {
+            self.db.in_transaction(|| {
+                self.flush(&recording.display_name, recording.project.as_ref(), &mut interval, time_to)
+            }).unwrap();
+        }
+        recording.display_name.clear();
+        recording.title.clear();
+        recording.project = None;
+    }
+
+    /// Closes what the previous run left open. After a crash or a power loss the open interval
+    /// is ended at the last heartbeat; the time until now is recorded as offline either way.
+    fn recover(&self) {
+        let now_ts = self.host.now().timestamp_millis();
+        let Some(state) = self.db.get_tracker_state().unwrap() else {
+            return;
+        };
+        self.db.in_transaction(|| {
+            if let (false, Some(rowid)) = (state.clean_shutdown, state.open_rowid) {
+                // the summary has the interval up to its last flush, which is after the heartbeat
+                // when it was closed right before the crash
+                if let Some(interval) = self.db.get_interval(rowid)?.filter(|interval| interval.time_to < state.heartbeat) {
+                    let day = self.calendar.date_of_millis(interval.time_from, interval.utc_offset);
+                    let millis = state.heartbeat - interval.time_to;
+                    self.db.update_time_to(rowid, state.heartbeat)?;
+                    self.db.add_to_summary(&interval.window_name, interval.project.as_ref(), None, day, millis)?;
+                }
+            }
+            self.db.insert_downtime(DowntimeKind::Offline, state.heartbeat, now_ts)
+        }).unwrap();
+    }
+
+    /// Writes the end of the interval and adds the time since the last write to its day's summary.
+    fn flush(
+        &self,
+        window_name: &String,
+        project: Option<&String>,
+        interval: &mut OpenInterval,
+        time_to: i64,
+    ) -> Result<(), Box<dyn Error>> {
+        self.db.update_time_to(interval.rowid, time_to)?;
+        self.db.add_to_summary(window_name, project, None, interval.day, time_to - interval.flushed_at)?;
+        interval.flushed_at = time_to;
+        Ok(())
+    }
+
+    /// Records that the tracker is alive and which interval it has open, returns what was written.
+    fn beat(&self, now_ts: i64, open_rowid: Option<i64>) -> TrackerState {
+        let state = TrackerState {
+            heartbeat: now_ts,
+            open_rowid,
+            clean_shutdown: false,
+        };
+        self.db.save_tracker_state(&state).unwrap();
+        state
+    }
+
+    fn shut_down(&self, now_ts: i64) {
+        self.db.save_tracker_state(&TrackerState {
+            heartbeat: now_ts,
+            open_rowid: None,
+            clean_shutdown: true,
+        }).unwrap();
+    }
+
+    /// A shared folder that cannot be reached is tried again on the next sync.
+    fn sync(&self) {
+        if let Some(sync) = &self.sync {
+            if let Err(err) = sync.sync(&self.db) {
+                println!("Could not sync: {err}");
+            }
+        }
+    }
+
+    fn backup(&self) {
+        if !self.backup.daily {
+            return;
+        }
+        let backups = Backups::new(Backups::get_default_dir());
+        if let Err(err) = backups.create_daily(&self.db, self.backup.keep) {
+            println!("Could not back up the database: {err}");
+        }
+    }
+
+    fn digest(&self) {
+        if !self.digest.weekly {
+            return;
+        }
+        let today = self.calendar.date_of(&self.host.now());
+        if let Err(err) = Digest::create_weekly(&self.db, &self.calendar, &Digest::get_default_dir(), today) {
+            println!("Could not write the weekly digest: {err}");
+        }
+    }
+
+    /// Returns the sample as it is stored and its project. The project is resolved before the
+    /// privacy rules, and an anonymized sample loses it like `apply_to_interval` does.
+    fn get_sample(&self) -> (Sample, Option<String>) {
+        let sample = self.host.sample();
+        let project = self.projects.get_project(&sample.exe_name, &sample.title);
+        // a dropped window is treated like the desktop, so nothing is recorded for it
+        match self.privacy.apply(sample.clone()) {
+            Some(new_sample) if new_sample == sample => (new_sample, project),
+            new_sample => (new_sample.unwrap_or_default(), None),
+        }
+    }
+
+    /// Waits for the next tick, returns the event that ended the wait early if there was one.
+    fn wait(&self) -> Option<TrackerEvents> {
+        match self.host.recv(&self.events, TICK_INTERVAL) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(TrackerEvents::Stop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use common::db::Downtime;
+    use common::privacy::{PrivacyAction, PrivacyRule};
+    use common::project::ProjectRule;
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// A clock that only moves when the tracker waits or a task says so, and a single window.
+    struct FakeHost {
+        now: Rc<Cell<i64>>,
+        sample: Sample,
+        /// How long each wait takes, the tracker is stopped once they are used up.
+        waits: RefCell<VecDeque<i64>>,
+    }
+
+    impl Host for FakeHost {
+        fn now(&self) -> DateTime<Local> {
+            Local.timestamp_millis_opt(self.now.get()).unwrap()
+        }
+
+        fn sample(&self) -> Sample {
+            self.sample.clone()
+        }
+
+        fn recv(&self, _events: &Receiver<TrackerEvents>, _timeout: Duration) -> Result<TrackerEvents, RecvTimeoutError> {
+            match self.waits.borrow_mut().pop_front() {
+                Some(millis) => {
+                    self.now.set(self.now.get() + millis);
+                    Err(RecvTimeoutError::Timeout)
+                }
+                None => Ok(TrackerEvents::Stop),
+            }
+        }
+    }
+
+    const START: i64 = 1_700_000_000_000;
+
+    /// A tracker without rules or tasks that sees code.exe until the waits are used up, and its clock.
+    fn fake_tracker(waits: Vec<i64>) -> (Tracker, Rc<Cell<i64>>) {
+        let now = Rc::new(Cell::new(START));
+        let (_sender, events) = mpsc::channel();
+        let host = FakeHost {
+            now: now.clone(),
+            sample: Sample {
+                exe_name: "code.exe".to_string(),
+                title: "tracker.rs - app-time".to_string(),
+                display_name: "code.exe".to_string(),
+            },
+            waits: RefCell::new(waits.into()),
+        };
+        let tracker = Tracker {
+            db: Db::open_in_memory().unwrap(),
+            privacy: PrivacyFilter::new(&[]).unwrap(),
+            projects: ProjectMatcher::new(&[]).unwrap(),
+            calendar: Calendar::default(),
+            sync: None,
+            backup: BackupConfig::default(),
+            digest: DigestConfig::default(),
+            tasks: vec![],
+            host: Box::new(host),
+            events,
+        };
+        (tracker, now)
+    }
+
+    fn sleep(db: &Db) -> i64 {
+        db.get_downtime(None).unwrap().iter()
+            .filter(|Downtime { kind, .. }| *kind == DowntimeKind::Sleep)
+            .map(|downtime| downtime.millis)
+            .sum()
+    }
+
+    #[test]
+    fn slow_scheduled_work_is_not_a_suspend() {
+        let (mut tracker, now) = fake_tracker(vec![5_000; 60]);
+        tracker.tasks = vec![ScheduledTask {
+            interval_millis: 60_000,
+            run: Box::new(move |_| now.set(now.get() + 30_000)),
+        }];
+        tracker.init();
+        let db = tracker.db;
+        assert_eq!(sleep(&db), 0);
+        let intervals = db.get_intervals(None).unwrap();
+        assert_eq!(intervals.len(), 1);
+        // the first tick comes after the run at the start, the interval lasts until the stop
+        let heartbeat = db.get_tracker_state().unwrap().unwrap().heartbeat;
+        assert_eq!((intervals[0].time_from, intervals[0].time_to), (START + 30_000, heartbeat));
+    }
+
+    #[test]
+    fn long_wait_is_a_suspend() {
+        let (tracker, _) = fake_tracker(vec![5_000, 5_000, 60_000, 5_000]);
+        tracker.init();
+        let db = tracker.db;
+        assert_eq!(sleep(&db), 60_000);
+        let mut intervals = db.get_intervals(None).unwrap();
+        intervals.sort_by_key(|interval| interval.time_from);
+        let times: Vec<_> = intervals.iter().map(|interval| (interval.time_from, interval.time_to)).collect();
+        assert_eq!(times, vec![(START, START + 10_000), (START + 70_000, START + 75_000)]);
+    }
+
+    fn projects_with(privacy: &[PrivacyRule]) -> Vec<(String, Option<String>)> {
+        let (mut tracker, _) = fake_tracker(vec![5_000]);
+        tracker.privacy = PrivacyFilter::new(privacy).unwrap();
+        tracker.projects = ProjectMatcher::new(&[ProjectRule {
+            name: Some("app-time".to_string()),
+            exe: Some("code.exe".to_string()),
+            ..ProjectRule::default()
+        }]).unwrap();
+        tracker.init();
+        tracker.db.get_intervals(None).unwrap().into_iter()
+            .map(|interval| (interval.window_name, interval.project))
+            .collect()
+    }
+
+    #[test]
+    fn project_is_resolved_before_the_privacy_rules() {
+        let title = PrivacyRule {
+            title: Some("^tracker".to_string()),
+            action: PrivacyAction::Anonymize,
+            ..PrivacyRule::default()
+        };
+        let exe = PrivacyRule {
+            exe: Some("code.exe".to_string()),
+            ..title.clone()
+        };
+        let other = PrivacyRule {
+            exe: Some("notepad.exe".to_string()),
+            ..exe.clone()
+        };
+        let app_time = Some("app-time".to_string());
+        assert_eq!(projects_with(&[]), vec![("code.exe".to_string(), app_time.clone())]);
+        assert_eq!(projects_with(&[other]), vec![("code.exe".to_string(), app_time)]);
+        // the exe is kept for an anonymized sample, its project would give away the window
+        assert_eq!(projects_with(&[title]), vec![("Hidden".to_string(), None)]);
+        assert_eq!(projects_with(&[exe]), vec![("Hidden".to_string(), None)]);
+    }
+}
@@ -1,91 +1,351 @@
-use chrono::Local;
-use common::date_range_input_widget::DateRangeInputWidget;
-use common::datetime::DateTimeExtensions;
-use common::db::{Db, WindowStat};
-use ratatui::crossterm::event;
-use ratatui::crossterm::event::{KeyCode, KeyEventKind};
-use ratatui::layout::{Direction, Layout};
-use ratatui::prelude::{Constraint, Style};
+use chrono::{DateTime, Local, NaiveDate};
+use common::config::Config;
+use common::date_input_widget::DateFormat;
+use common::date_range_input_widget::{DateRangeInputWidget, RangePreset};
+use common::datetime::{format_duration, format_local_time, parse_local_time, Calendar, PeriodLength, StatsPeriod};
+use common::db::{Db, Interval};
+use common::keymap::{Action, Keymap};
+use common::privacy::PrivacyFilter;
+use common::productivity::{Dashboard, Goal, ProductivityConfig};
+use common::project::ProjectMatcher;
+use common::query::{GroupBy, Stat, StatKey, StatsQuery};
+use common::theme::Theme;
+use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::crossterm::{event, execute};
+use ratatui::layout::{Direction, Layout, Position, Rect};
+use ratatui::prelude::Constraint;
 use ratatui::style::Stylize;
-use ratatui::text::Line;
-use ratatui::widgets::{Block, Cell, Padding, Row, Table, Tabs};
-use ratatui::DefaultTerminal;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Clear, Gauge, Padding, Paragraph, Row, Table, TableState, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::error::Error;
 use std::io;
 use std::time::Duration;
 
 pub fn main() -> io::Result<()> {
     let db = Db::init().unwrap();
+    let config = Config::load().unwrap();
+    db.check_summary(&config.calendar).unwrap();
+    let mut app = App::new(db, config, Local::now()).unwrap();
     let mut terminal = ratatui::init();
+    execute!(io::stdout(), EnableMouseCapture)?;
     terminal.clear()?;
-    UI { terminal, db }.run()
+    let result = run(&mut terminal, &mut app);
+    execute!(io::stdout(), DisableMouseCapture)?;
+    ratatui::restore();
+    result
 }
 
-pub struct UI {
-    terminal: DefaultTerminal,
+/// Draws the app and passes the events to it until it is quit.
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    loop {
+        app.load(Local::now());
+        terminal.draw(|frame| app.render(frame))?;
+        // without any event the stats are reloaded, to show the time tracked meanwhile
+        if !event::poll(Duration::from_secs(5))? {
+            continue;
+        }
+        match app.update(event::read()?) {
+            Command::Continue => {}
+            Command::Quit => return Ok(()),
+            Command::Perform(effect) => app.perform(effect),
+        }
+    }
+}
+
+/// What the loop does after an event, besides drawing the app again.
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Continue,
+    Quit,
+    /// Changes the intervals, see `App::perform`.
+    Perform(Effect),
+}
+
+/// A change to the intervals asked for by a key, made in the database by `App::perform`.
+#[derive(PartialEq, Debug)]
+pub enum Effect {
+    Add(Interval),
+    Edit(Interval),
+    Split { rowid: i64, at: i64 },
+    Delete(i64),
+    Undo,
+}
+
+/// Everything the UI shows. The stats are read from the database by `load`, drawn by `render`
+/// and the keys and clicks change what is shown in `update`, so the app does not depend on
+/// the terminal it is drawn in. The changes to the intervals `update` asks for are only made
+/// in the database by `perform`.
+pub struct App {
     db: Db,
+    calendar: Calendar,
+    /// Attributes the intervals added by hand when no project is entered.
+    projects: ProjectMatcher,
+    /// Applies the privacy rules to the intervals added and changed by hand.
+    privacy: PrivacyFilter,
+    productivity: ProductivityConfig,
+    goals: Vec<Goal>,
+    keymap: Keymap,
+    theme: Theme,
+    /// The built-in periods, then the named ones from the config and the custom one last.
+    periods: Vec<StatsPeriod>,
+    selected_tab: usize,
+    /// The first tab drawn, the tabs scroll when they do not all fit.
+    first_tab: usize,
+    /// Columns taken by each tab when they were drawn last.
+    tab_widths: Vec<usize>,
+    date_range_input: DateRangeInputWidget,
+    device_filter: DeviceFilter,
+    baseline: Baseline,
+    view: StatsView,
+    focus: Focus,
+    show_help: bool,
+    selected_row: usize,
+    table_state: TableState,
+    areas: Areas,
+    form: Option<IntervalForm>,
+    message: String,
+    devices: Vec<String>,
+    /// Colors set for apps in the database, the others get one picked by their name.
+    app_colors: HashMap<String, String>,
+    baseline_period: Option<(i64, i64)>,
+    stat_rows: Vec<StatRow>,
+    intervals: Vec<Interval>,
+    dashboard: Option<Dashboard>,
+    /// When the stats were loaded last, the periods and the dates picked run up to it.
+    now: DateTime<Local>,
 }
-impl UI {
-    pub fn run(&mut self) -> io::Result<()> {
-        let mut selected_tab = 0;
-        let periods: Vec<StatsPeriod> = vec![
+
+impl App {
+    pub fn new(db: Db, config: Config, now: DateTime<Local>) -> Result<App, Box<dyn Error>> {
+        let mut periods: Vec<StatsPeriod> = vec![
             StatsPeriod::Total,
             StatsPeriod::Today,
             StatsPeriod::Yesterday,
-            StatsPeriod::Last3Days,
+            StatsPeriod::LastDays(3),
             StatsPeriod::ThisWeek,
             StatsPeriod::LastWeek,
-            StatsPeriod::Custom
+            StatsPeriod::ThisMonth,
+            StatsPeriod::LastMonth,
+            StatsPeriod::ThisYear,
+            StatsPeriod::LastYear,
+            StatsPeriod::LastDays(7),
+            StatsPeriod::LastDays(30),
+            StatsPeriod::LastDays(90),
         ];
-        let min_date = self.db.get_min_date().unwrap();
-        let mut date_range_input = DateRangeInputWidget::new(
-            min_date,
-            Local::now().date_naive(),
-        );
-        loop {
-            let is_custom = selected_tab == periods.len() - 1;
-
-            let titles = periods.iter()
-                .map(|period| {
-                    if *period == StatsPeriod::Custom
-                        && is_custom { "" } else { period.as_title() }
-                })
-                .collect::<Vec<&str>>();
-            let paddings = (" ", " ");
-            let divider = "|";
-            let titles_width = titles.iter()
-                .map(|title| title.len() + paddings.0.len() + paddings.1.len())
-                .sum::<usize>() + titles.len() * divider.len() - 2;
-            self.terminal.draw(|frame| {
-                let tabs = Tabs::new(
-                    titles.into_iter()
-                        .map(Line::from)
-                        .collect::<Vec<Line>>()
-                )
-                    .style(Style::default().white())
-                    .highlight_style(Style::default().on_yellow().black())
-                    .divider(divider)
-                    .padding(paddings.0, paddings.1)
-                    .select(selected_tab);
-
-                let table_block = Block::default()
-                    .padding(Padding::left(1));
-                let time_period = if is_custom {
-                    date_range_input.get_time_period().into()
-                } else {
-                    periods[selected_tab].as_time_period()
-                };
-                let rows: Vec<WindowStatRow> = self.db.get_stats(time_period)
+        periods.extend(config.periods.into_iter().map(StatsPeriod::Named));
+        periods.push(StatsPeriod::Custom);
+        let theme = Theme::load(config.ui.theme);
+        let today = config.calendar.date_of(&now);
+        let date_range_input = DateRangeInputWidget::new(db.get_min_date()?.unwrap_or(today), today)
+            .format(DateFormat::parse(&config.ui.date_format)?)
+            .theme(theme.clone());
+        Ok(App {
+            projects: ProjectMatcher::new(&config.projects)?,
+            privacy: PrivacyFilter::new(&config.privacy)?,
+            keymap: Keymap::new(&config.keys)?,
+            theme,
+            db,
+            calendar: config.calendar,
+            productivity: config.productivity,
+            goals: config.goals,
+            periods,
+            selected_tab: 0,
+            first_tab: 0,
+            tab_widths: Vec::new(),
+            date_range_input,
+            device_filter: DeviceFilter::All,
+            baseline: Baseline::None,
+            view: StatsView::Apps,
+            focus: Focus::Periods,
+            show_help: false,
+            selected_row: 0,
+            table_state: TableState::default(),
+            areas: Areas::default(),
+            form: None,
+            message: String::new(),
+            devices: Vec::new(),
+            app_colors: HashMap::new(),
+            baseline_period: None,
+            stat_rows: Vec::new(),
+            intervals: Vec::new(),
+            dashboard: None,
+            now,
+        })
+    }
+
+    fn is_custom(&self) -> bool {
+        self.selected_tab == self.periods.len() - 1
+    }
+
+    fn get_row_count(&self) -> usize {
+        self.stat_rows.len().max(self.intervals.len())
+    }
+
+    /// Reads what the selected period and view show from the database as of `now`.
+    pub fn load(&mut self, now: DateTime<Local>) {
+        self.now = now;
+        self.devices = self.db.get_devices().unwrap_or_default();
+        self.app_colors = self.db.get_app_colors().unwrap_or_default();
+        let is_custom = self.is_custom();
+        if !is_custom && self.focus == Focus::Dates {
+            self.focus = Focus::Periods;
+        }
+        if self.focus != Focus::Dates {
+            self.date_range_input.deselect();
+        }
+        let time_period = if is_custom {
+            self.date_range_input.get_time_period(&self.calendar).into()
+        } else {
+            self.periods[self.selected_tab].as_time_period(&self.calendar, &now)
+        };
+        let length = if is_custom {
+            Some(PeriodLength::Days(self.date_range_input.get_num_days()))
+        } else {
+            self.periods[self.selected_tab].get_length()
+        };
+        self.baseline_period = self.baseline.get_time_period(time_period, length);
+        self.stat_rows = match self.view.as_group_by() {
+            Some(group_by) => {
+                let mut query = StatsQuery::new()
+                    .period(time_period)
+                    .group_by(group_by)
+                    .calendar(self.calendar);
+                if let DeviceFilter::Only(device) = &self.device_filter {
+                    query = query.device(device.clone());
+                }
+                let previous: Option<HashMap<StatKey, i64>> = self.baseline_period.map(|period| {
+                    self.db.get_stats(&query.clone().period(Some(period)))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|stat| (stat.key, stat.millis))
+                        .collect()
+                });
+                self.db.get_stats(&query)
                     .unwrap_or_default()
                     .into_iter()
-                    .map(|window_stat| { WindowStatRow { window_stat } })
-                    .collect();
+                    .map(|stat| {
+                        let previous_millis = previous.as_ref()
+                            .map(|previous| previous.get(&stat.key).copied().unwrap_or(0));
+                        StatRow { stat, previous_millis }
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        self.intervals = match self.view {
+            StatsView::Intervals => self.db.get_intervals(time_period).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        self.selected_row = self.selected_row.min(self.get_row_count().saturating_sub(1));
+        // the dashboard is always about today, whichever period is selected
+        self.dashboard = match self.view {
+            StatsView::Dashboard => Dashboard::load(&self.db, &self.productivity, &self.goals, &self.calendar, self.calendar.date_of(&now)).ok(),
+            _ => None,
+        };
+    }
+
+    /// Draws what was loaded last, remembering where each part went for the clicks.
+    pub fn render(&mut self, frame: &mut Frame) {
+        let is_custom = self.is_custom();
+        let titles = self.periods.iter()
+            .map(|period| {
+                if *period == StatsPeriod::Custom
+                    && is_custom { String::new() } else { period.as_title() }
+            })
+            .collect::<Vec<String>>();
+        let paddings = (" ", " ");
+        let divider = "|";
+        self.tab_widths = titles.iter()
+            .map(|title| title.len() + paddings.0.len() + paddings.1.len() + divider.len())
+            .collect();
+        // the tabs scroll when they do not all fit, so the selected one is always shown
+        let mut available = frame.area().width as usize;
+        if is_custom {
+            available = available.saturating_sub(self.date_range_input.get_width() as usize);
+        }
+        self.first_tab = 0;
+        while self.first_tab < self.selected_tab
+            && self.tab_widths[self.first_tab..=self.selected_tab].iter().sum::<usize>() > available {
+            self.first_tab += 1;
+        }
+        let titles_width = self.tab_widths[self.first_tab..].iter().sum::<usize>().saturating_sub(2);
+        let help_key = self.keymap.get_bindings().iter()
+            .find(|(action, _)| *action == Action::Help)
+            .and_then(|(_, keys)| keys.first())
+            .map(|key| format!(" {} help ", key.name))
+            .unwrap_or_default();
+        let highlight = |focused: bool| {
+            if focused { self.theme.highlight } else { self.theme.inactive }
+        };
+        let tabs = Tabs::new(
+            titles.into_iter()
+                .skip(self.first_tab)
+                .map(Line::from)
+                .collect::<Vec<Line>>()
+        )
+            .style(self.theme.text)
+            .highlight_style(highlight(self.focus == Focus::Periods))
+            .divider(divider)
+            .padding(paddings.0, paddings.1)
+            .select(self.selected_tab - self.first_tab);
+
+        let view_titles = StatsView::all().into_iter()
+            .map(|option| {
+                let title = Span::from(format!(" {} ", option.as_title()));
+                if option == self.view { title.style(self.theme.highlight) } else { title }
+            })
+            .collect::<Vec<Span>>();
+        let mut table_block = Block::default()
+            .title(Line::from(view_titles))
+            .title_bottom(self.message.as_str())
+            .title_bottom(Line::from(help_key).right_aligned())
+            .padding(Padding::left(1));
+        // the filter is only offered once intervals from another device have been merged
+        if !self.devices.is_empty() && self.view.as_group_by().is_some() {
+            table_block = table_block.title(Line::from(self.device_filter.as_title().to_string()).right_aligned());
+        }
+        if self.baseline_period.is_some() && self.view.as_group_by().is_some() {
+            table_block = table_block.title(Line::from(self.baseline.as_title()).right_aligned());
+        }
 
-                let total_seconds = rows.iter().clone()
-                    .map(|row| row.window_stat.seconds)
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(2),
+                Constraint::Fill(1),
+            ])
+            .split(frame.area());
+        self.areas.tabs = layout[0];
+        self.areas.dates = Rect::default();
+        self.areas.views = Rect { height: 1, ..layout[1] };
+        self.areas.table = table_block.inner(layout[1]);
+        if is_custom {
+            let tabs_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![
+                    Constraint::Length(titles_width as u16),
+                    Constraint::Fill(1),
+                ])
+                .split(layout[0]);
+            frame.render_widget(&tabs, tabs_layout[0]);
+            frame.render_widget(&mut self.date_range_input, tabs_layout[1]);
+            self.areas.tabs = tabs_layout[0];
+            self.areas.dates = tabs_layout[1];
+        } else {
+            frame.render_widget(&tabs, layout[0]);
+        }
+
+        self.table_state.select(Some(self.selected_row));
+        match self.view {
+            StatsView::Apps | StatsView::Projects => {
+                let total_millis = self.stat_rows.iter()
+                    .map(|row| row.stat.millis)
                     .sum();
 
-                let table = Table::new(
-                    rows.iter().map(|row| row.create_row(total_seconds)),
+                let mut table = Table::new(
+                    self.stat_rows.iter().map(|row| row.create_row(total_millis, &self.theme, &self.app_colors)),
                     [
                         Constraint::Length(30),
                         Constraint::Length(1),
@@ -94,149 +354,686 @@ impl UI {
                         Constraint::Length(4),
                         Constraint::Length(1),
                         Constraint::Length(20),
+                        Constraint::Length(1),
+                        Constraint::Length(24),
                     ],
                 ).block(table_block);
-
-                let layout = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints(vec![
-                        Constraint::Length(2),
+                // rows of the stats are only picked to scroll through them
+                if self.focus == Focus::Table {
+                    table = table.highlight_style(highlight(true));
+                }
+                frame.render_stateful_widget(&table, layout[1], &mut self.table_state);
+            }
+            StatsView::Dashboard => {
+                if let Some(dashboard) = &self.dashboard {
+                    render_dashboard(frame, layout[1], table_block, dashboard, &self.theme);
+                }
+            }
+            StatsView::Intervals => {
+                let table = Table::new(
+                    self.intervals.iter().map(create_interval_row),
+                    [
+                        Constraint::Length(33),
+                        Constraint::Length(12),
+                        Constraint::Length(25),
+                        Constraint::Length(20),
+                        Constraint::Length(6),
                         Constraint::Fill(1),
-                    ])
-                    .split(frame.area());
-                if is_custom {
-                    let tabs_layout = Layout::default()
-                        .direction(Direction::Horizontal)
-                        .constraints(vec![
-                            Constraint::Length(titles_width as u16),
-                            Constraint::Fill(1),
-                        ])
-                        .split(layout[0]);
-                    frame.render_widget(&tabs, tabs_layout[0]);
-                    frame.render_widget(&mut date_range_input, tabs_layout[1]);
-                } else {
-                    frame.render_widget(&tabs, layout[0]);
+                    ],
+                )
+                    .block(table_block)
+                    .highlight_style(highlight(self.focus == Focus::Table));
+                frame.render_stateful_widget(&table, layout[1], &mut self.table_state);
+            }
+        }
+        if let Some(form) = &self.form {
+            form.render(frame, &self.theme);
+        }
+        self.date_range_input.render_calendar(self.areas.dates, frame.buffer_mut());
+        if self.show_help {
+            render_help(frame, &self.keymap, &self.theme);
+        }
+    }
+
+    /// Changes what is shown after a key or click, and returns what the loop does next.
+    pub fn update(&mut self, event: Event) -> Command {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key(key),
+            Event::Mouse(mouse) if self.form.is_none() => {
+                self.handle_mouse(mouse);
+                Command::Continue
+            }
+            _ => Command::Continue,
+        }
+    }
+
+    /// Makes the change `update` asked for. The form it was entered in is closed once it is
+    /// made, and stays open to correct it when the change is refused.
+    pub fn perform(&mut self, effect: Effect) {
+        let done = match effect {
+            Effect::Add(interval) => self.db.add_interval(&interval, &self.calendar)
+                .map(|rowid| format!("Added interval {rowid}")),
+            Effect::Edit(interval) => self.db.edit_interval(&interval, &self.calendar)
+                .map(|()| format!("Changed interval {}", interval.rowid)),
+            Effect::Split { rowid, at } => self.db.split_interval(rowid, at, &self.calendar)
+                .map(|second| format!("Split off interval {second}")),
+            Effect::Delete(rowid) => self.db.delete_interval(rowid, &self.calendar)
+                .map(|()| format!("Deleted interval {rowid}")),
+            Effect::Undo => self.db.undo(&self.calendar)
+                .map(|action| action.map_or("Nothing to undo".to_string(), |action| format!("Undid {action}"))),
+        };
+        match done {
+            Ok(done) => {
+                self.message = done;
+                self.form = None;
+            }
+            Err(err) => self.message = err.to_string(),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Command {
+        if let Some(mut form) = self.form.take() {
+            match form.handle_input(key) {
+                FormInput::Editing => self.form = Some(form),
+                FormInput::Cancel => {}
+                FormInput::Submit => {
+                    let submitted = self.submit(&form);
+                    // open until the change is made
+                    self.form = Some(form);
+                    match submitted {
+                        Ok(effect) => return Command::Perform(effect),
+                        Err(err) => self.message = err.to_string(),
+                    }
                 }
-                frame.render_widget(&table, layout[1]);
-            })?;
-
-            if event::poll(Duration::from_secs(5))? {
-                if let event::Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
-                        if date_range_input.is_any_selected() {
-                            date_range_input.handle_input(key);
-                            if !date_range_input.is_any_selected() {
-                                match key.code {
-                                    KeyCode::Left => selected_tab = periods.len() - 2,
-                                    KeyCode::Right => selected_tab = 0,
-                                    _ => {}
-                                };
-                            }
-                        } else {
-                            match key.code {
-                                KeyCode::Left => selected_tab = (selected_tab + periods.len() - 1) % periods.len(),
-                                KeyCode::Right => selected_tab = (selected_tab + 1) % periods.len(),
-                                _ => {}
-                            };
-                            if selected_tab == periods.len() - 1 {
-                                date_range_input.handle_input(key);
-                            }
+            }
+            return Command::Continue;
+        }
+        if self.show_help {
+            self.show_help = false;
+            return Command::Continue;
+        }
+        // the dates take digits and their own keys before the keymap
+        if self.focus == Focus::Dates && self.date_range_input.handle_input(key) {
+            if !self.date_range_input.is_any_selected() {
+                self.focus = if key.code == KeyCode::Left { Focus::Periods } else { Focus::Table };
+            }
+            return Command::Continue;
+        }
+        match self.keymap.get_action(&key) {
+            Some(action) => self.handle_action(action),
+            None => Command::Continue,
+        }
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        if self.show_help {
+            self.show_help = mouse.kind != MouseEventKind::Down(MouseButton::Left);
+            return;
+        }
+        let position = Position { x: mouse.column, y: mouse.row };
+        let period_count = self.periods.len();
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.areas.tabs.contains(position) {
+                    let mut x = self.areas.tabs.x as usize;
+                    for (tab, width) in self.tab_widths.iter().enumerate().skip(self.first_tab) {
+                        x += width;
+                        if (position.x as usize) < x {
+                            self.selected_tab = tab;
+                            self.focus = Focus::Periods;
+                            break;
+                        }
+                    }
+                } else if self.areas.dates.contains(position) {
+                    self.date_range_input.select_at(position.x - self.areas.dates.x);
+                    self.focus = Focus::Dates;
+                } else if self.areas.views.contains(position) {
+                    let mut x = self.areas.views.x as usize;
+                    for option in StatsView::all() {
+                        x += option.as_title().len() + 2;
+                        if (position.x as usize) < x {
+                            self.view = option;
+                            break;
                         }
                     }
+                } else if self.areas.table.contains(position) {
+                    let row = self.table_state.offset() + (position.y - self.areas.table.y) as usize;
+                    if row < self.get_row_count() {
+                        self.selected_row = row;
+                    }
+                    self.focus = Focus::Table;
+                }
+            }
+            MouseEventKind::ScrollUp if self.areas.tabs.contains(position) => {
+                self.selected_tab = (self.selected_tab + period_count - 1) % period_count;
+            }
+            MouseEventKind::ScrollDown if self.areas.tabs.contains(position) => {
+                self.selected_tab = (self.selected_tab + 1) % period_count;
+            }
+            MouseEventKind::ScrollUp => self.selected_row = self.selected_row.saturating_sub(1),
+            MouseEventKind::ScrollDown => self.selected_row += 1,
+            _ => {}
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) -> Command {
+        let is_custom = self.is_custom();
+        let period_count = self.periods.len();
+        let selected = self.intervals.get(self.selected_row);
+        match (action, self.focus) {
+            (Action::Quit, _) => return Command::Quit,
+            (Action::Help, _) => self.show_help = true,
+            (Action::NextFocus, _) => self.focus = self.focus.next(is_custom),
+            (Action::PreviousFocus, _) => self.focus = self.focus.previous(is_custom),
+            (Action::Left | Action::Right | Action::Up | Action::Down, Focus::Dates) => {
+                let code = match action {
+                    Action::Left => KeyCode::Left,
+                    Action::Right => KeyCode::Right,
+                    Action::Up => KeyCode::Up,
+                    _ => KeyCode::Down,
+                };
+                self.date_range_input.handle_input(KeyEvent::from(code));
+                // moving past either end leaves the dates
+                if !self.date_range_input.is_any_selected() {
+                    self.focus = if code == KeyCode::Left { Focus::Periods } else { Focus::Table };
                 }
             }
+            (Action::Right, Focus::Periods) if is_custom => {
+                self.date_range_input.select();
+                self.focus = Focus::Dates;
+            }
+            (Action::Left, Focus::Periods) => self.selected_tab = (self.selected_tab + period_count - 1) % period_count,
+            (Action::Right, Focus::Periods) => self.selected_tab = (self.selected_tab + 1) % period_count,
+            (Action::Left, Focus::Table) => self.view = self.view.previous(),
+            (Action::Right, Focus::Table) => self.view = self.view.next(),
+            (Action::Up, _) => self.selected_row = self.selected_row.saturating_sub(1),
+            (Action::Down, _) => self.selected_row += 1,
+            (Action::NextView, _) => self.view = self.view.next(),
+            (Action::DeviceFilter, _) => self.device_filter = self.device_filter.next(&self.devices),
+            (Action::Baseline, _) => self.baseline = self.baseline.next(),
+            (Action::RangePreset, _) => {
+                let preset = self.date_range_input.get_preset()
+                    .map_or(RangePreset::LastDays(7), |preset| preset.next());
+                self.date_range_input.set_preset(preset, self.calendar.date_of(&self.now));
+                self.selected_tab = period_count - 1;
+                self.message = preset.as_title();
+            }
+            (Action::Tab(tab), _) if tab < period_count => {
+                self.selected_tab = tab;
+                self.focus = Focus::Periods;
+            }
+            (Action::Add, _) if self.view == StatsView::Intervals => {
+                self.form = Some(IntervalForm::add(self.calendar.date_of(&self.now)));
+            }
+            (Action::Edit, _) if self.view == StatsView::Intervals => self.form = selected.map(IntervalForm::edit),
+            (Action::Split, _) if self.view == StatsView::Intervals => self.form = selected.map(IntervalForm::split),
+            (Action::Delete, _) if self.view == StatsView::Intervals => {
+                if let Some(interval) = selected {
+                    return Command::Perform(Effect::Delete(interval.rowid));
+                }
+            }
+            (Action::Undo, _) if self.view == StatsView::Intervals => return Command::Perform(Effect::Undo),
+            _ => {}
+        }
+        Command::Continue
+    }
+
+    /// Reads what was entered into the form as the change to make.
+    fn submit(&self, form: &IntervalForm) -> Result<Effect, Box<dyn Error>> {
+        match &form.action {
+            FormAction::Add | FormAction::Edit(_) => {
+                let existing = match &form.action {
+                    FormAction::Edit(interval) => Some(interval),
+                    _ => None,
+                };
+                let rowid = existing.map_or(0, |interval| interval.rowid);
+                let app = form.get("App").trim().to_string();
+                if app.is_empty() {
+                    return Err("The app is required".into());
+                }
+                let title = Some(form.get("Title").trim().to_string()).filter(|title| !title.is_empty());
+                let title_or_app = title.clone().unwrap_or_else(|| app.clone());
+                let project = match form.get("Project").trim() {
+                    "" if existing.is_none() => self.projects.get_project(&app, &title_or_app),
+                    "" => None,
+                    project => Some(project.to_string()),
+                };
+                let time_from = parse_form_time(form.get("From"))?;
+                let start = DateTime::from_timestamp_millis(time_from).unwrap_or_default();
+                let interval = Interval {
+                    rowid,
+                    window_name: app,
+                    title,
+                    project,
+                    time_from,
+                    time_to: parse_form_time(form.get("To"))?,
+                    utc_offset: Some(start.with_timezone(&Local).offset().local_minus_utc()),
+                    manual: existing.is_none_or(|interval| interval.manual),
+                };
+                let interval = self.privacy.apply_to_interval(&interval)
+                    .ok_or(format!("{} is dropped by the privacy rules", interval.window_name))?;
+                Ok(if existing.is_some() { Effect::Edit(interval) } else { Effect::Add(interval) })
+            }
+            FormAction::Split(rowid) => Ok(Effect::Split { rowid: *rowid, at: parse_form_time(form.get("At"))? }),
         }
     }
 }
 
-#[derive(PartialEq)]
-enum StatsPeriod {
-    Total,
-    Today,
-    Yesterday,
-    Last3Days,
-    ThisWeek,
-    LastWeek,
-    Custom,
+fn render_dashboard(frame: &mut Frame, area: Rect, block: Block, dashboard: &Dashboard, theme: &Theme) {
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let score = dashboard.score.map_or("-".to_string(), |score| score.to_string());
+    let lines = vec![
+        Line::from(vec![
+            Span::from("Score "),
+            Span::from(score).bold(),
+            Span::from(format!("   Streak {} days", dashboard.streak)),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("Productive {}", format_duration(dashboard.productive_millis)), theme.positive),
+            Span::from(format!("   Neutral {}", format_duration(dashboard.neutral_millis))),
+            Span::styled(format!("   Distracting {}", format_duration(dashboard.distracting_millis)), theme.negative),
+        ]),
+    ];
+    let mut constraints = vec![Constraint::Length(3)];
+    constraints.extend(dashboard.goals.iter().map(|_| Constraint::Length(2)));
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner);
+    frame.render_widget(Paragraph::new(lines), layout[0]);
+    for (progress, area) in dashboard.goals.iter().zip(layout.iter().skip(1)) {
+        let target = progress.goal.get_target_millis();
+        let ratio = if target > 0 { progress.millis as f64 / target as f64 } else { 1.0 };
+        let color = match (progress.goal.limit, progress.is_met) {
+            (true, false) => theme.negative,
+            (_, true) => theme.positive,
+            _ => theme.pending,
+        };
+        let gauge = Gauge::default()
+            .gauge_style(color)
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(format!(
+                "{}: {} / {}",
+                progress.goal.as_title(),
+                format_duration(progress.millis),
+                format_duration(target),
+            ));
+        frame.render_widget(gauge, Rect { height: 1, ..*area });
+    }
 }
 
-impl StatsPeriod {
+/// Lists the keys of every action over the rest of the screen, the period tabs on one line.
+fn render_help(frame: &mut Frame, keymap: &Keymap, theme: &Theme) {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut tab_keys: Vec<&str> = Vec::new();
+    for (action, keys) in keymap.get_bindings() {
+        if let Action::Tab(_) = action {
+            tab_keys.extend(keys.iter().map(|key| key.name.as_str()));
+            continue;
+        }
+        if keys.is_empty() {
+            continue;
+        }
+        let keys = keys.iter().map(|key| key.name.as_str()).collect::<Vec<&str>>().join(", ");
+        lines.push(Line::from(vec![Span::styled(format!("{keys:<18}"), theme.accent), Span::from(action.describe())]));
+    }
+    if !tab_keys.is_empty() {
+        lines.push(Line::from(vec![Span::styled(format!("{:<18}", tab_keys.join(" ")), theme.accent), Span::from("Go to a period by its number")]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from("Click the periods, dates, views and rows, scroll with the wheel"));
+    let area = frame.area();
+    let height = lines.len() as u16 + 2;
+    let width = 70;
+    let popup = Rect {
+        x: area.width.saturating_sub(width) / 2,
+        y: area.height.saturating_sub(height) / 2,
+        width: area.width.min(width),
+        height: area.height.min(height),
+    };
+    let block = Block::bordered()
+        .title(" Keys ")
+        .title_bottom(" Any key to close ")
+        .padding(Padding::horizontal(1));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+fn parse_form_time(value: &str) -> Result<i64, Box<dyn Error>> {
+    parse_local_time(value).ok_or(format!("Invalid time {value}, expected yyyy-mm-dd hh:mm").into())
+}
+
+fn create_interval_row(interval: &Interval) -> Row<'static> {
+    let from = format_local_time(interval.time_from);
+    let mut to = format_local_time(interval.time_to);
+    // the end is shown without its date when it is the same as the start's
+    if to.get(..11) == from.get(..11) {
+        to = to.split_off(11);
+    }
+    Row::new(vec![
+        Cell::from(format!("{from} - {to}")),
+        Cell::from(format_duration(interval.time_to - interval.time_from)),
+        Cell::from(interval.window_name.clone()),
+        Cell::from(interval.project.clone().unwrap_or_default()),
+        Cell::from(if interval.manual { "manual" } else { "" }),
+        Cell::from(interval.title.clone().unwrap_or_default()),
+    ])
+}
+
+/// What the time of the selected period is compared with.
+#[derive(Copy, Clone, PartialEq)]
+enum Baseline {
+    None,
+    /// The period of the same length right before, e.g. last week for this week or last month
+    /// for this month.
+    PreviousPeriod,
+    /// The same days a week earlier.
+    WeekBefore,
+}
+
+impl Baseline {
     fn as_title(&self) -> &str {
         match self {
-            StatsPeriod::Total => "Total",
-            StatsPeriod::Today => "Today",
-            StatsPeriod::Yesterday => "Yesterday",
-            StatsPeriod::Last3Days => "Last 3 days",
-            StatsPeriod::ThisWeek => "This week",
-            StatsPeriod::LastWeek => "Last week",
-            StatsPeriod::Custom => "Custom",
-        }
-    }
-    fn as_time_period(&self) -> Option<(u32, u32)> {
-        let now = Local::now();
-        let now_ts = now.timestamp() as u32;
+            Baseline::None => "",
+            Baseline::PreviousPeriod => "vs previous period",
+            Baseline::WeekBefore => "vs week before",
+        }
+    }
+
+    fn next(&self) -> Baseline {
         match self {
-            StatsPeriod::Today => Some((
-                now.start_of_day_ts(0),
-                now_ts
-            )),
-            StatsPeriod::Yesterday => Some((
-                now.start_of_day_ts(1),
-                now.start_of_day_ts(0)
-            )),
-            StatsPeriod::Last3Days => Some((
-                now.start_of_day_ts(2),
-                now_ts
-            )),
-            StatsPeriod::ThisWeek => Some((
-                now.start_of_week_ts(0),
-                now_ts
-            )),
-            StatsPeriod::LastWeek => Some((
-                now.start_of_week_ts(1),
-                now.start_of_week_ts(0)
-            )),
-            _ => None,
+            Baseline::None => Baseline::PreviousPeriod,
+            Baseline::PreviousPeriod => Baseline::WeekBefore,
+            Baseline::WeekBefore => Baseline::None,
+        }
+    }
+
+    /// Moves `period` back by whole days or months, so a period running until now is compared
+    /// with the same time of day.
+    fn get_time_period(&self, period: Option<(i64, i64)>, length: Option<PeriodLength>) -> Option<(i64, i64)> {
+        let (from, to) = period?;
+        let length = match self {
+            Baseline::None => return None,
+            Baseline::PreviousPeriod => length?,
+            Baseline::WeekBefore => PeriodLength::Days(7),
+        };
+        Some((length.subtract_from(&Local, from), length.subtract_from(&Local, to)))
+    }
+}
+
+/// What the table shows, the time grouped by app or project, the intervals themselves or
+/// today's productivity.
+#[derive(Copy, Clone, PartialEq)]
+enum StatsView {
+    Apps,
+    Projects,
+    Intervals,
+    Dashboard,
+}
+
+impl StatsView {
+    fn all() -> [StatsView; 4] {
+        [StatsView::Apps, StatsView::Projects, StatsView::Intervals, StatsView::Dashboard]
+    }
+
+    fn as_title(&self) -> &str {
+        match self {
+            StatsView::Apps => "Apps",
+            StatsView::Projects => "Projects",
+            StatsView::Intervals => "Intervals",
+            StatsView::Dashboard => "Dashboard",
+        }
+    }
+
+    fn as_group_by(&self) -> Option<GroupBy> {
+        match self {
+            StatsView::Apps => Some(GroupBy::App),
+            StatsView::Projects => Some(GroupBy::Project),
+            StatsView::Intervals | StatsView::Dashboard => None,
+        }
+    }
+
+    fn next(&self) -> StatsView {
+        match self {
+            StatsView::Apps => StatsView::Projects,
+            StatsView::Projects => StatsView::Intervals,
+            StatsView::Intervals => StatsView::Dashboard,
+            StatsView::Dashboard => StatsView::Apps,
+        }
+    }
+
+    fn previous(&self) -> StatsView {
+        match self {
+            StatsView::Apps => StatsView::Dashboard,
+            StatsView::Projects => StatsView::Apps,
+            StatsView::Intervals => StatsView::Projects,
+            StatsView::Dashboard => StatsView::Intervals,
+        }
+    }
+}
+
+/// Which part of the screen the arrow keys go to.
+#[derive(Copy, Clone, PartialEq)]
+enum Focus {
+    Periods,
+    /// The dates of the custom period, only while it is selected.
+    Dates,
+    Table,
+}
+
+impl Focus {
+    fn next(&self, is_custom: bool) -> Focus {
+        match self {
+            Focus::Periods if is_custom => Focus::Dates,
+            Focus::Periods | Focus::Dates => Focus::Table,
+            Focus::Table => Focus::Periods,
+        }
+    }
+
+    fn previous(&self, is_custom: bool) -> Focus {
+        match self {
+            Focus::Periods => Focus::Table,
+            Focus::Dates => Focus::Periods,
+            Focus::Table if is_custom => Focus::Dates,
+            Focus::Table => Focus::Periods,
+        }
+    }
+}
+
+/// Where the parts of the screen were drawn last, to find what is clicked.
+#[derive(Default)]
+struct Areas {
+    tabs: Rect,
+    dates: Rect,
+    views: Rect,
+    /// The rows of the table, without its titles.
+    table: Rect,
+}
+
+enum FormAction {
+    Add,
+    /// The interval as it was before it was edited.
+    Edit(Interval),
+    Split(i64),
+}
+
+enum FormInput {
+    Editing,
+    Submit,
+    Cancel,
+}
+
+/// Fields to enter an interval, or the time to split one at, shown over the table.
+struct IntervalForm {
+    action: FormAction,
+    fields: Vec<(&'static str, String)>,
+    focused: usize,
+}
+
+impl IntervalForm {
+    /// Times start out with the date filled in.
+    fn add(day: NaiveDate) -> Self {
+        let date = day.format("%Y-%m-%d ").to_string();
+        IntervalForm {
+            action: FormAction::Add,
+            fields: vec![
+                ("App", String::new()),
+                ("Title", String::new()),
+                ("Project", String::new()),
+                ("From", date.clone()),
+                ("To", date),
+            ],
+            focused: 0,
+        }
+    }
+
+    fn edit(interval: &Interval) -> Self {
+        IntervalForm {
+            action: FormAction::Edit(interval.clone()),
+            fields: vec![
+                ("App", interval.window_name.clone()),
+                ("Title", interval.title.clone().unwrap_or_default()),
+                ("Project", interval.project.clone().unwrap_or_default()),
+                ("From", format_local_time(interval.time_from)),
+                ("To", format_local_time(interval.time_to)),
+            ],
+            focused: 0,
+        }
+    }
+
+    fn split(interval: &Interval) -> Self {
+        let middle = interval.time_from + (interval.time_to - interval.time_from) / 2;
+        IntervalForm {
+            action: FormAction::Split(interval.rowid),
+            fields: vec![("At", format_local_time(middle))],
+            focused: 0,
+        }
+    }
+
+    fn get(&self, label: &str) -> &str {
+        self.fields.iter()
+            .find(|(field, _)| *field == label)
+            .map_or("", |(_, value)| value.as_str())
+    }
+
+    fn handle_input(&mut self, key: KeyEvent) -> FormInput {
+        let count = self.fields.len();
+        match key.code {
+            KeyCode::Enter => return FormInput::Submit,
+            KeyCode::Esc => return FormInput::Cancel,
+            KeyCode::Tab | KeyCode::Down => self.focused = (self.focused + 1) % count,
+            KeyCode::BackTab | KeyCode::Up => self.focused = (self.focused + count - 1) % count,
+            KeyCode::Backspace => {
+                self.fields[self.focused].1.pop();
+            }
+            KeyCode::Char(c) => self.fields[self.focused].1.push(c),
+            _ => {}
+        }
+        FormInput::Editing
+    }
+
+    fn render(&self, frame: &mut Frame, theme: &Theme) {
+        let title = match self.action {
+            FormAction::Add => " Add interval ",
+            FormAction::Edit(_) => " Edit interval ",
+            FormAction::Split(_) => " Split interval ",
+        };
+        let lines: Vec<Line> = self.fields.iter()
+            .enumerate()
+            .map(|(i, (label, value))| {
+                let value = if i == self.focused { Span::styled(format!("{value}_"), theme.highlight) } else { value.clone().into() };
+                Line::from(vec![Span::from(format!("{label:<8}")), value])
+            })
+            .collect();
+        let area = frame.area();
+        let height = lines.len() as u16 + 2;
+        let popup = Rect {
+            x: area.width.saturating_sub(60) / 2,
+            y: area.height.saturating_sub(height) / 2,
+            width: area.width.min(60),
+            height: area.height.min(height),
+        };
+        let block = Block::bordered()
+            .title(title)
+            .title_bottom(" Enter save | Esc cancel ")
+            .padding(Padding::horizontal(1));
+        frame.render_widget(Clear, popup);
+        frame.render_widget(Paragraph::new(lines).block(block), popup);
+    }
+}
+
+/// Which devices the stats are counted for.
+#[derive(Clone, PartialEq)]
+enum DeviceFilter {
+    All,
+    /// A single device, None for this one.
+    Only(Option<String>),
+}
+
+impl DeviceFilter {
+    fn as_title(&self) -> &str {
+        match self {
+            DeviceFilter::All => "All devices",
+            DeviceFilter::Only(None) => "This device",
+            DeviceFilter::Only(Some(device)) => device,
         }
     }
+
+    /// Cycles through all devices, this device and then each of the other `devices`.
+    fn next(&self, devices: &[String]) -> DeviceFilter {
+        let filters: Vec<DeviceFilter> = [DeviceFilter::All, DeviceFilter::Only(None)].into_iter()
+            .chain(devices.iter().map(|device| DeviceFilter::Only(Some(device.clone()))))
+            .collect();
+        let position = filters.iter().position(|filter| filter == self).unwrap_or(0);
+        filters[(position + 1) % filters.len()].clone()
+    }
 }
 
-struct WindowStatRow {
-    window_stat: WindowStat,
+struct StatRow {
+    stat: Stat,
+    /// The time of the baseline period, None when nothing is compared.
+    previous_millis: Option<i64>,
 }
 
-impl WindowStatRow {
-    fn create_row(&self, total_window_stats_seconds: u32) -> Row {
-        let window_name = String::from(&self.window_stat.window_name);
-        let window_stat_time = Self::format_time(self.window_stat.seconds);
-        let ratio = self.window_stat.seconds as f64 / total_window_stats_seconds as f64;
+impl StatRow {
+    /// The bar of an app is drawn in its color, if the theme has them.
+    fn create_row(&self, total_stats_millis: i64, theme: &Theme, app_colors: &HashMap<String, String>) -> Row<'_> {
+        let name = self.stat.key.to_string();
+        let stat_time = format_duration(self.stat.millis);
+        let ratio = self.stat.millis as f64 / total_stats_millis as f64;
         let percentage = (ratio * 100.0).round();
         let progress_bar = Self::progress_bar(30, ratio);
+        let bar_style = match &self.stat.key {
+            StatKey::App(app) => theme.get_app_bar(app, app_colors),
+            _ => theme.bar,
+        };
         Row::new(vec![
-            Cell::from(window_name),
+            Cell::from(name),
             Cell::from("│"),
-            Cell::from(progress_bar).yellow(),
+            Cell::from(progress_bar).style(bar_style),
             Cell::from("│"),
             Cell::from(format!("{percentage}%")),
             Cell::from("│"),
-            Cell::from(window_stat_time)
+            Cell::from(stat_time),
+            Cell::from("│"),
+            self.change_cell(theme),
         ])
     }
 
-    fn format_time(seconds: u32) -> String { // TODO REFACTOR ASAP
-        let minutes = seconds / 60;
-        if minutes == 0 {
-            return format!("{seconds}s");
-        }
-        let hours = minutes / 60;
-        if hours == 0 {
-            return format!("{minutes}m {}s", seconds % 60);
-        }
-        let days = hours / 24;
-        if days == 0 {
-            return format!("{hours}h {}m {}s", minutes % 60, seconds % 60);
+    /// The difference to the baseline, green when more time was spent and red when less.
+    fn change_cell(&self, theme: &Theme) -> Cell<'static> {
+        let Some(previous_millis) = self.previous_millis else {
+            return Cell::from("");
+        };
+        let delta = self.stat.millis - previous_millis;
+        if previous_millis == 0 {
+            return Cell::from("new").style(theme.positive);
         }
-        format!("{days}d {}h {}m {}s", hours % 24, minutes % 60, seconds % 60)
+        let sign = if delta < 0 { "-" } else { "+" };
+        let percentage = (delta as f64 / previous_millis as f64 * 100.0).round();
+        let text = format!("{sign}{} ({percentage:+}%)", format_duration(delta.abs()));
+        Cell::from(text).style(if delta < 0 { theme.negative } else { theme.positive })
     }
 
     fn progress_bar(length: u16, ratio: f64) -> String {
@@ -244,3 +1041,236 @@ impl WindowStatRow {
         vec!["▀"; progress].join("")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use common::datetime::from_local;
+    use insta::assert_snapshot;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    const CONFIG: &str = r#"
+        [productivity]
+        productive_categories = ["Development"]
+        distracting_apps = ["slack.exe"]
+
+        [[goals]]
+        productivity = "productive"
+        hours = 4
+
+        [[goals]]
+        app = "slack.exe"
+        hours = 1
+        limit = true
+    "#;
+
+    fn at(time: &str) -> DateTime<Local> {
+        from_local(&Local, NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    /// Wednesday afternoon, the stats are loaded as of it whatever the clock says.
+    fn now() -> DateTime<Local> {
+        at("2024-06-12 15:00")
+    }
+
+    fn record(db: &Db, app: &str, title: &str, project: Option<&str>, from: &str, to: &str) {
+        let time_from = at(from);
+        let project = project.map(String::from);
+        let rowid = db.insert(
+            &app.to_string(),
+            &title.to_string(),
+            project.as_ref(),
+            time_from.timestamp_millis(),
+            time_from.offset().local_minus_utc(),
+        ).unwrap();
+        db.update_time_to(rowid, at(to).timestamp_millis()).unwrap();
+    }
+
+    fn create_app(db: Db) -> App {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        db.check_summary(&config.calendar).unwrap();
+        App::new(db, config, now()).unwrap()
+    }
+
+    /// Two days of work and the morning of today.
+    fn create_recorded_app() -> App {
+        let db = Db::open_in_memory().unwrap();
+        db.set_category(&"code.exe".to_string(), Some(&"Development".to_string())).unwrap();
+        record(&db, "code.exe", "main.rs - app-time", Some("app-time"), "2024-06-10 09:00", "2024-06-10 12:30");
+        record(&db, "firefox.exe", "Docs", None, "2024-06-10 13:00", "2024-06-10 14:00");
+        record(&db, "slack.exe", "General", None, "2024-06-11 10:00", "2024-06-11 10:45");
+        record(&db, "code.exe", "db.rs - app-time", Some("app-time"), "2024-06-12 09:00", "2024-06-12 11:30");
+        record(&db, "firefox.exe", "Docs", Some("app-time"), "2024-06-12 11:30", "2024-06-12 12:00");
+        record(&db, "slack.exe", "General", None, "2024-06-12 13:00", "2024-06-12 13:20");
+        create_app(db)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Command {
+        app.update(Event::Key(KeyEvent::from(code)))
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    /// The app showing the intervals, the newest one selected.
+    fn create_intervals_app() -> App {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        app.load(now());
+        app
+    }
+
+    fn draw(app: &mut App) -> String {
+        app.load(now());
+        let mut terminal = Terminal::new(TestBackend::new(110, 14)).unwrap();
+        terminal.draw(|frame| app.render(frame)).unwrap();
+        terminal.backend().to_string()
+    }
+
+    #[test]
+    fn apps_of_all_time() {
+        assert_snapshot!(draw(&mut create_recorded_app()));
+    }
+
+    #[test]
+    fn apps_of_today_compared_with_the_day_before() {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Char('c'));
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn projects() {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Char('v'));
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn intervals() {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn dashboard() {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn custom_range() {
+        let mut app = create_recorded_app();
+        // the custom period is the last tab, left of the first one, right
+        // of it the day of the start date is selected and moved a day on
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Up);
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn custom_range_preset() {
+        let mut app = create_recorded_app();
+        press(&mut app, KeyCode::Char('p'));
+        assert_snapshot!(draw(&mut app));
+    }
+
+    #[test]
+    fn empty_database() {
+        let mut app = create_app(Db::open_in_memory().unwrap());
+        assert_snapshot!("empty_database_apps", draw(&mut app));
+        press(&mut app, KeyCode::Left);
+        assert_snapshot!("empty_database_custom_range", draw(&mut app));
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('v'));
+        assert_snapshot!("empty_database_dashboard", draw(&mut app));
+    }
+
+    #[test]
+    fn changes_are_asked_for_and_not_made() {
+        let mut app = create_intervals_app();
+        let newest = app.intervals[0].clone();
+        assert_eq!(press(&mut app, KeyCode::Char('x')), Command::Perform(Effect::Delete(newest.rowid)));
+        assert_eq!(press(&mut app, KeyCode::Char('u')), Command::Perform(Effect::Undo));
+        assert_eq!(app.db.get_intervals(None).unwrap().len(), 6);
+
+        press(&mut app, KeyCode::Char('e'));
+        type_text(&mut app, "-beta");
+        let Command::Perform(Effect::Edit(edited)) = press(&mut app, KeyCode::Enter) else {
+            panic!("the edit was not submitted");
+        };
+        assert_eq!(edited, Interval { window_name: "slack.exe-beta".to_string(), ..newest.clone() });
+        assert!(app.form.is_some());
+        assert_eq!(app.db.get_interval(newest.rowid).unwrap(), Some(newest));
+        assert_eq!(press(&mut app, KeyCode::Esc), Command::Continue);
+        assert_eq!(press(&mut app, KeyCode::Char('q')), Command::Quit);
+    }
+
+    #[test]
+    fn form_is_closed_once_its_change_is_made() {
+        let mut app = create_intervals_app();
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "vim.exe");
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Tab);
+        }
+        type_text(&mut app, "07:00");
+        press(&mut app, KeyCode::Tab);
+        type_text(&mut app, "08:30");
+        let Command::Perform(effect) = press(&mut app, KeyCode::Enter) else {
+            panic!("the interval was not submitted");
+        };
+        let Effect::Add(interval) = &effect else {
+            panic!("{effect:?} is not an added interval");
+        };
+        assert_eq!((interval.time_from, interval.time_to), (at("2024-06-12 07:00").timestamp_millis(), at("2024-06-12 08:30").timestamp_millis()));
+        app.perform(effect);
+        assert!(app.form.is_none());
+        assert_eq!(app.message, "Added interval 7");
+        app.perform(Effect::Undo);
+        assert_eq!(app.message, "Undid add");
+        assert_eq!(app.db.get_intervals(None).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn form_stays_open_when_its_change_is_refused() {
+        let mut app = create_intervals_app();
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "vim.exe");
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Tab);
+        }
+        // code.exe ran from 9:00 to 11:30
+        type_text(&mut app, "10:00");
+        press(&mut app, KeyCode::Tab);
+        type_text(&mut app, "10:30");
+        let Command::Perform(effect) = press(&mut app, KeyCode::Enter) else {
+            panic!("the interval was not submitted");
+        };
+        app.perform(effect);
+        assert!(app.form.is_some());
+        assert_eq!(app.message, "The interval overlaps another one");
+
+        // and errors in what was entered keep it open without asking for a change
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "x");
+        assert_eq!(press(&mut app, KeyCode::Enter), Command::Continue);
+        assert!(app.form.is_some());
+        assert_eq!(app.db.get_intervals(None).unwrap().len(), 6);
+    }
+
+}
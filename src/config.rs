@@ -0,0 +1,134 @@
+use crate::datetime::{Calendar, NamedPeriod};
+use crate::db::Db;
+use crate::privacy::PrivacyRule;
+use crate::productivity::{Goal, ProductivityConfig};
+use crate::project::ProjectRule;
+use crate::theme::ThemeName;
+use crate::timesheet::Rounding;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::{env, fs};
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub calendar: Calendar,
+    pub privacy: Vec<PrivacyRule>,
+    pub projects: Vec<ProjectRule>,
+    pub tracker: TrackerConfig,
+    pub sync: SyncConfig,
+    pub backup: BackupConfig,
+    pub digest: DigestConfig,
+    pub timesheet: Rounding,
+    pub productivity: ProductivityConfig,
+    pub goals: Vec<Goal>,
+    pub periods: Vec<NamedPeriod>,
+    /// Keys of the UI by action, see `Keymap`.
+    pub keys: HashMap<String, Vec<String>>,
+    pub ui: UiConfig,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct TrackerConfig {
+    /// Start intervals as soon as the foreground window changes instead of on the next poll.
+    pub foreground_events: bool,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            foreground_events: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Folder shared between the devices, e.g. one kept in sync by a cloud drive. Nothing is
+    /// synced when it is not set.
+    pub dir: Option<PathBuf>,
+    /// Name of this device in the folder, the computer name by default. Without one, e.g.
+    /// outside Windows, it is a name made up for the database the first time it was opened.
+    pub device: Option<String>,
+}
+
+impl SyncConfig {
+    pub fn get_device(&self, db: &Db) -> Result<String, Box<dyn Error>> {
+        match self.device.clone().or_else(|| env::var("COMPUTERNAME").ok().filter(|name| !name.is_empty())) {
+            Some(device) => Ok(device),
+            None => db.get_device_name(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Let the tracker back the database up once a day.
+    pub daily: bool,
+    /// How many backups are kept in the backups folder, older ones are deleted.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            daily: true,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DigestConfig {
+    /// Let the tracker write the digest of the last week into the digests folder.
+    pub weekly: bool,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        DigestConfig {
+            weekly: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct UiConfig {
+    /// How dates are shown and typed, with each of `%d`, `%m` and `%Y` once, e.g. "%Y-%m-%d".
+    pub date_format: String,
+    /// dark, light or high-contrast, without colors whenever NO_COLOR is set.
+    pub theme: ThemeName,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            date_format: "%d.%m.%Y".to_string(),
+            theme: ThemeName::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn get_path() -> PathBuf {
+        let mut config_path = Db::get_data_dir();
+        config_path.push("config.toml");
+        config_path
+    }
+
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let path = Self::get_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let config = toml::from_str(&fs::read_to_string(path)?)?;
+        Ok(config)
+    }
+}
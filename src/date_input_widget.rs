@@ -1,11 +1,15 @@
-use chrono::{Days, Months, NaiveDate};
+use crate::theme::Theme;
+use chrono::{Datelike, Days, Months, NaiveDate};
 use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 use ratatui::layout::Rect;
 use ratatui::prelude::{Stylize, Widget};
-use ratatui::style::{Color, Style};
+use ratatui::style::Style;
+use ratatui::widgets::calendar::{CalendarEventStore, Monthly};
+use ratatui::widgets::{Block, Clear};
+use std::error::Error;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Selection {
     None,
     Day,
@@ -19,15 +23,128 @@ impl Selection {
             Selection::Day => "%d",
             Selection::Month => "%m",
             Selection::Year => "%Y",
-            _ => panic!("{:?} is not a date part", self)
+            Selection::None => "",
         }
     }
+
+    /// How many digits the part is typed with.
+    fn get_width(&self) -> usize {
+        match self {
+            Selection::Year => 4,
+            Selection::None => 0,
+            _ => 2,
+        }
+    }
+}
+
+/// The order of the day, month and year and what separates them, e.g. "%d.%m.%Y" or "%Y-%m-%d".
+#[derive(Clone, Debug)]
+pub struct DateFormat {
+    parts: Vec<FormatPart>,
+}
+
+#[derive(Clone, Debug)]
+enum FormatPart {
+    Date(Selection),
+    Literal(String),
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        DateFormat::parse("%d.%m.%Y").unwrap()
+    }
+}
+
+impl DateFormat {
+    /// Reads a format with each of `%d`, `%m` and `%Y` exactly once, anything else is shown as it is.
+    pub fn parse(format: &str) -> Result<DateFormat, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+            let part = match chars.next() {
+                Some('d') => Selection::Day,
+                Some('m') => Selection::Month,
+                Some('Y') => Selection::Year,
+                Some('%') => {
+                    literal.push('%');
+                    continue;
+                }
+                _ => return Err(format!("Date format {format} may only use %d, %m and %Y").into()),
+            };
+            if !literal.is_empty() {
+                parts.push(FormatPart::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(FormatPart::Date(part));
+        }
+        if !literal.is_empty() {
+            parts.push(FormatPart::Literal(literal));
+        }
+        let format = DateFormat { parts };
+        if format.get_order().len() != 3 || [Selection::Day, Selection::Month, Selection::Year].iter()
+            .any(|part| !format.get_order().contains(part)) {
+            return Err(format!("Date format {format:?} must have each of %d, %m and %Y once").into());
+        }
+        Ok(format)
+    }
+
+    /// Columns taken by a date.
+    pub fn get_width(&self) -> u16 {
+        self.parts.iter()
+            .map(|part| match part {
+                FormatPart::Date(part) => part.get_width(),
+                FormatPart::Literal(literal) => literal.chars().count(),
+            })
+            .sum::<usize>() as u16
+    }
+
+    /// The parts in the order they are shown.
+    fn get_order(&self) -> Vec<Selection> {
+        self.parts.iter()
+            .filter_map(|part| match part {
+                FormatPart::Date(part) => Some(*part),
+                FormatPart::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    fn first(&self) -> Selection {
+        self.get_order().first().copied().unwrap_or(Selection::None)
+    }
+
+    fn last(&self) -> Selection {
+        self.get_order().last().copied().unwrap_or(Selection::None)
+    }
+
+    /// The part `offset` places away from `selection`, None past either end.
+    fn step(&self, selection: Selection, offset: isize) -> Selection {
+        let order = self.get_order();
+        order.iter()
+            .position(|part| *part == selection)
+            .and_then(|position| position.checked_add_signed(offset))
+            .and_then(|position| order.get(position).copied())
+            .unwrap_or(Selection::None)
+    }
 }
 
 #[derive(Clone)]
 pub struct DateInputState {
     pub date: NaiveDate,
     pub selection: Selection,
+    /// Digits typed into the selected part so far.
+    pub typed: String,
+    /// Whether the month calendar is shown to pick the date from.
+    pub calendar: bool,
+}
+
+impl DateInputState {
+    pub fn new(date: NaiveDate) -> Self {
+        DateInputState { date, selection: Selection::None, typed: String::new(), calendar: false }
+    }
 }
 
 #[derive(Clone)]
@@ -35,91 +152,210 @@ pub struct DateInputWidget {
     pub state: DateInputState,
     pub min: Option<NaiveDate>,
     pub max: Option<NaiveDate>,
+    pub format: DateFormat,
+    pub theme: Theme,
 }
 
 impl Widget for &mut DateInputWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let default_style = Style::default().bold();
-        let parts: Vec<(&str, Style)> = [Selection::Day, Selection::Month, Selection::Year].iter()
-            .map(|sel| {
-                let style = if self.state.selection == *sel {
-                    default_style.fg(Color::Yellow)
-                } else {
-                    default_style
-                };
-                (sel.as_date_part(), style)
-            })
-            .collect();
         let mut position_x = area.left();
-        for i in 0..parts.len() {
-            let (str, style) = parts[i];
-            buf.set_string(
-                position_x,
-                area.top(),
-                self.state.date.format(str).to_string(),
-                style,
-            );
-            position_x += str.len() as u16;
-            if i < parts.len() - 1 {
-                buf.set_string(
-                    position_x,
-                    area.top(),
-                    ".",
-                    default_style,
-                );
-                position_x += 1;
-            }
+        for part in &self.format.parts {
+            let (text, style) = match part {
+                FormatPart::Date(part) if self.state.selection == *part => {
+                    let text = if self.state.typed.is_empty() {
+                        self.state.date.format(part.as_date_part()).to_string()
+                    } else {
+                        format!("{:_<width$}", self.state.typed, width = part.get_width())
+                    };
+                    (text, default_style.patch(self.theme.accent))
+                }
+                FormatPart::Date(part) => (self.state.date.format(part.as_date_part()).to_string(), default_style),
+                FormatPart::Literal(literal) => (literal.clone(), default_style),
+            };
+            buf.set_string(position_x, area.top(), &text, style);
+            position_x += text.chars().count() as u16;
         }
     }
 }
 
 impl DateInputWidget {
-    pub fn handle_input(&mut self, key: KeyEvent) {
-        let state = &mut self.state;
-        if state.selection == Selection::None {
-            state.selection = Selection::Day;
-            return;
+    pub fn new(date: NaiveDate, min: Option<NaiveDate>, max: Option<NaiveDate>) -> Self {
+        DateInputWidget { state: DateInputState::new(date), min, max, format: DateFormat::default(), theme: Theme::default() }
+    }
+
+    pub fn select_first(&mut self) {
+        self.state.selection = self.format.first();
+    }
+
+    pub fn select_last(&mut self) {
+        self.state.selection = self.format.last();
+    }
+
+    /// Keeps the digits typed so far and closes the calendar.
+    pub fn deselect(&mut self) {
+        self.apply_typed();
+        self.state.selection = Selection::None;
+        self.state.calendar = false;
+    }
+
+    /// Selects the part shown at `column` of the widget.
+    pub fn select_at(&mut self, column: u16) {
+        self.apply_typed();
+        let mut end = 0;
+        for part in &self.format.parts {
+            let (selection, width) = match part {
+                FormatPart::Date(part) => (*part, part.get_width()),
+                FormatPart::Literal(literal) => (Selection::None, literal.chars().count()),
+            };
+            end += width;
+            if selection != Selection::None {
+                self.state.selection = selection;
+            }
+            if (column as usize) < end {
+                break;
+            }
         }
-        match key.code {
-            KeyCode::Left => {
-                match state.selection {
-                    Selection::Day => { state.selection = Selection::None }
-                    Selection::Month => { state.selection = Selection::Day }
-                    Selection::Year => { state.selection = Selection::Month }
-                    _ => {}
+    }
+
+    /// Handles the keys of the selected part and of the calendar, returns whether the key was used.
+    ///
+    /// Digits are typed into the part, Up and Down step it and PageUp and PageDown step the
+    /// next larger one. Enter opens the calendar, where the arrows move by days and weeks and
+    /// the page keys by months until Enter or Esc closes it.
+    pub fn handle_input(&mut self, key: KeyEvent) -> bool {
+        if self.state.selection == Selection::None {
+            return false;
+        }
+        if self.state.calendar {
+            let date = self.state.date;
+            let moved = match key.code {
+                KeyCode::Left => date.checked_sub_days(Days::new(1)),
+                KeyCode::Right => date.checked_add_days(Days::new(1)),
+                KeyCode::Up => date.checked_sub_days(Days::new(7)),
+                KeyCode::Down => date.checked_add_days(Days::new(7)),
+                KeyCode::PageUp => date.checked_sub_months(Months::new(1)),
+                KeyCode::PageDown => date.checked_add_months(Months::new(1)),
+                KeyCode::Enter | KeyCode::Esc => {
+                    self.state.calendar = false;
+                    None
                 }
+                _ => return false,
+            };
+            if let Some(moved) = moved {
+                self.state.date = self.clamp(moved);
             }
-            KeyCode::Right => {
-                match state.selection {
-                    Selection::Day => { state.selection = Selection::Month }
-                    Selection::Month => { state.selection = Selection::Year }
-                    Selection::Year => { state.selection = Selection::None }
-                    _ => {}
+            return true;
+        }
+        match key.code {
+            KeyCode::Char(digit @ '0'..='9') => {
+                self.state.typed.push(digit);
+                if self.state.typed.len() >= self.state.selection.get_width() {
+                    self.apply_typed();
+                    let next = self.format.step(self.state.selection, 1);
+                    if next != Selection::None {
+                        self.state.selection = next;
+                    }
                 }
             }
-            KeyCode::Up => {
-                match state.selection {
-                    Selection::Day => { state.date = state.date.checked_add_days(Days::new(1)).unwrap() }
-                    Selection::Month => { state.date = state.date.checked_add_months(Months::new(1)).unwrap() }
-                    Selection::Year => { state.date = state.date.checked_add_months(Months::new(12)).unwrap() }
-                    _ => {}
-                }
-                if self.max.is_some() && state.date > self.max.unwrap() {
-                    state.date = self.max.unwrap()
-                }
+            KeyCode::Backspace => {
+                self.state.typed.pop();
             }
-            KeyCode::Down => {
-                match state.selection {
-                    Selection::Day => { state.date = state.date.checked_sub_days(Days::new(1)).unwrap() }
-                    Selection::Month => { state.date = state.date.checked_sub_months(Months::new(1)).unwrap() }
-                    Selection::Year => { state.date = state.date.checked_sub_months(Months::new(12)).unwrap() }
-                    _ => {}
-                }
-                if self.min.is_some() && state.date < self.min.unwrap() {
-                    state.date = self.min.unwrap()
+            KeyCode::Enter => {
+                self.apply_typed();
+                self.state.calendar = true;
+            }
+            KeyCode::Left => {
+                self.apply_typed();
+                self.state.selection = self.format.step(self.state.selection, -1);
+            }
+            KeyCode::Right => {
+                self.apply_typed();
+                self.state.selection = self.format.step(self.state.selection, 1);
+            }
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                self.apply_typed();
+                let step: u32 = match (self.state.selection, matches!(key.code, KeyCode::PageUp | KeyCode::PageDown)) {
+                    (Selection::Day, false) => 0,
+                    (Selection::Day, true) | (Selection::Month, false) => 1,
+                    (Selection::Month, true) | (Selection::Year, false) => 12,
+                    _ => 120,
+                };
+                let date = self.state.date;
+                let moved = match (step, matches!(key.code, KeyCode::Up | KeyCode::PageUp)) {
+                    (0, true) => date.checked_add_days(Days::new(1)),
+                    (0, false) => date.checked_sub_days(Days::new(1)),
+                    (months, true) => date.checked_add_months(Months::new(months)),
+                    (months, false) => date.checked_sub_months(Months::new(months)),
+                };
+                if let Some(moved) = moved {
+                    self.state.date = self.clamp(moved);
                 }
             }
-            _ => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Renders the month of the date below `area`, where the widget was drawn, if it is open.
+    pub fn render_calendar(&self, area: Rect, buf: &mut Buffer) {
+        if !self.state.calendar {
+            return;
+        }
+        let Some(date) = to_time_date(self.state.date) else {
+            return;
+        };
+        let mut events = CalendarEventStore::default();
+        events.add(date, self.theme.highlight);
+        let width = 24.min(buf.area.right().saturating_sub(area.x));
+        let popup = Rect {
+            x: area.x,
+            y: area.y + 1,
+            width,
+            height: 10.min(buf.area.bottom().saturating_sub(area.y + 1)),
+        };
+        Clear.render(popup, buf);
+        Monthly::new(date, events)
+            .default_style(self.theme.text)
+            .show_month_header(self.theme.text.bold())
+            .show_weekdays_header(self.theme.muted)
+            .block(Block::bordered().title_bottom(" Enter/Esc "))
+            .render(popup, buf);
+    }
+
+    /// Sets the selected part to the typed digits, keeping the day within the month.
+    fn apply_typed(&mut self) {
+        let typed = std::mem::take(&mut self.state.typed);
+        let Ok(value) = typed.parse::<u32>() else {
+            return;
+        };
+        let date = self.state.date;
+        let (year, month, day) = match self.state.selection {
+            Selection::Day => (date.year(), date.month(), value),
+            Selection::Month => (date.year(), value.clamp(1, 12), date.day()),
+            Selection::Year => (value as i32, date.month(), date.day()),
+            Selection::None => return,
+        };
+        let Some(first) = NaiveDate::from_ymd_opt(year, month, 1) else {
+            return;
         };
+        let days_in_month = first.checked_add_months(Months::new(1))
+            .map_or(31, |next| (next - first).num_days() as u32);
+        if let Some(typed_date) = first.with_day(day.clamp(1, days_in_month)) {
+            self.state.date = self.clamp(typed_date);
+        }
     }
-}
\ No newline at end of file
+
+    fn clamp(&self, date: NaiveDate) -> NaiveDate {
+        match (self.min, self.max) {
+            (Some(min), _) if date < min => min,
+            (_, Some(max)) if date > max => max,
+            _ => date,
+        }
+    }
+}
+
+fn to_time_date(date: NaiveDate) -> Option<time::Date> {
+    let month = time::Month::try_from(date.month() as u8).ok()?;
+    time::Date::from_calendar_date(date.year(), month, date.day() as u8).ok()
+}
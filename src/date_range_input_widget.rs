@@ -1,80 +1,465 @@
-use crate::date_input_widget::{DateInputState, DateInputWidget, Selection};
-use chrono::NaiveDate;
+use crate::date_input_widget::{DateFormat, DateInputWidget, Selection};
+use crate::datetime::Calendar;
+use crate::theme::Theme;
+use chrono::{Datelike, Days, NaiveDate};
 use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 use ratatui::layout::{Offset, Rect};
 use ratatui::prelude::Widget;
 use ratatui::style::Style;
 
+/// Between the two dates.
+const SEPARATOR: &str = " - ";
+
+/// A range the dates can be set to at once, ending on the given day.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RangePreset {
+    LastDays(u64),
+    MonthToDate,
+    YearToDate,
+}
+
+impl RangePreset {
+    pub fn all() -> Vec<RangePreset> {
+        vec![
+            RangePreset::LastDays(7),
+            RangePreset::LastDays(30),
+            RangePreset::MonthToDate,
+            RangePreset::YearToDate,
+        ]
+    }
+
+    pub fn as_title(&self) -> String {
+        match self {
+            RangePreset::LastDays(days) => format!("Last {days} days"),
+            RangePreset::MonthToDate => "Month to date".to_string(),
+            RangePreset::YearToDate => "Year to date".to_string(),
+        }
+    }
+
+    /// The preset after this one, the first one after the last.
+    pub fn next(&self) -> RangePreset {
+        let all = Self::all();
+        let position = all.iter().position(|preset| preset == self).map_or(0, |position| position + 1);
+        all[position % all.len()]
+    }
+
+    /// The first and last day of the range ending on `today`.
+    pub fn get_dates(&self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let from = match self {
+            RangePreset::LastDays(days) => today.checked_sub_days(Days::new(days.saturating_sub(1))),
+            RangePreset::MonthToDate => today.with_day(1),
+            RangePreset::YearToDate => today.with_ordinal(1),
+        };
+        (from.unwrap_or(today), today)
+    }
+}
+
 pub struct DateRangeInputWidget {
     pub min: Option<NaiveDate>,
     pub max: Option<NaiveDate>,
     inputs: (DateInputWidget, DateInputWidget),
+    /// The preset the dates were last set to, until they are changed.
+    preset: Option<RangePreset>,
 }
 
 impl Widget for &mut DateRangeInputWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let date_width = self.get_date_width();
         let (from, to) = &mut self.inputs;
         from.render(area, buf);
-        buf.set_string(area.left() + 11, area.top(), "-", Style::default());
-        to.render(area.offset(Offset { x: 13, y: 0 }), buf);
+        buf.set_string(area.left() + date_width, area.top(), SEPARATOR, Style::default());
+        to.render(area.offset(Offset { x: (date_width + SEPARATOR.len() as u16) as i32, y: 0 }), buf);
     }
 }
 
 impl DateRangeInputWidget {
     pub fn new(min: NaiveDate, max: NaiveDate) -> Self {
-        Self {
+        let mut widget = Self {
             inputs: (
-                DateInputWidget {
-                    state: DateInputState { date: min, selection: Selection::None },
-                    min: min.into(),
-                    max: max.into(),
-                },
-                DateInputWidget {
-                    state: DateInputState { date: max, selection: Selection::None },
-                    min: min.into(),
-                    max: max.into(),
-                }
+                DateInputWidget::new(min, min.into(), max.into()),
+                DateInputWidget::new(max, min.into(), max.into()),
             ),
             min: min.into(),
             max: max.into(),
-        }
+            preset: None,
+        };
+        widget.keep_valid(false);
+        widget
+    }
+
+    pub fn format(mut self, format: DateFormat) -> Self {
+        self.inputs.0.format = format.clone();
+        self.inputs.1.format = format;
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.inputs.0.theme = theme.clone();
+        self.inputs.1.theme = theme;
+        self
+    }
+
+    /// Columns taken by the two dates and the dash between them.
+    pub fn get_width(&self) -> u16 {
+        self.get_date_width() * 2 + SEPARATOR.len() as u16
     }
+
+    fn get_date_width(&self) -> u16 {
+        self.inputs.0.format.get_width()
+    }
+
     pub fn is_any_selected(&self) -> bool {
         let (from, to) = &self.inputs;
         from.state.selection != Selection::None || to.state.selection != Selection::None
     }
 
-    pub fn handle_input(&mut self, key: KeyEvent) {
+    /// Selects the first part of the first date.
+    pub fn select(&mut self) {
+        self.deselect();
+        self.inputs.0.select_first();
+    }
+
+    pub fn deselect(&mut self) {
+        let (from, to) = &mut self.inputs;
+        from.deselect();
+        to.deselect();
+        self.keep_valid(false);
+    }
+
+    pub fn get_dates(&self) -> (NaiveDate, NaiveDate) {
+        let (from, to) = &self.inputs;
+        (from.state.date, to.state.date)
+    }
+
+    /// Sets both dates, within the min and max and swapped if `from` is after `to`.
+    pub fn set_dates(&mut self, from: NaiveDate, to: NaiveDate) {
+        self.inputs.0.state.date = from.min(to);
+        self.inputs.1.state.date = from.max(to);
+        self.preset = None;
+        self.keep_valid(false);
+    }
+
+    pub fn get_preset(&self) -> Option<RangePreset> {
+        self.preset
+    }
+
+    /// Sets the dates to the preset range ending on `today`.
+    pub fn set_preset(&mut self, preset: RangePreset, today: NaiveDate) {
+        let (from, to) = preset.get_dates(today);
+        self.set_dates(from, to);
+        self.preset = Some(preset);
+    }
+
+    /// Keeps both dates within the min and max with the first one not after the second, moving
+    /// the other date when one was moved past it.
+    fn keep_valid(&mut self, to_moved: bool) {
+        let (min, max) = (self.min, self.max);
+        let (from, to) = &mut self.inputs;
+        for input in [&mut *from, &mut *to] {
+            input.min = min;
+            input.max = max;
+            if let Some(min) = min {
+                input.state.date = input.state.date.max(min);
+            }
+            if let Some(max) = max {
+                input.state.date = input.state.date.min(max);
+            }
+        }
+        if from.state.date > to.state.date {
+            if to_moved {
+                from.state.date = to.state.date;
+            } else {
+                to.state.date = from.state.date;
+            }
+        }
+    }
+
+    /// Selects the date part shown at `column` of the widget.
+    pub fn select_at(&mut self, column: u16) {
+        self.deselect();
+        let date_width = self.get_date_width();
+        let (from, to) = &mut self.inputs;
+        if column < date_width + 1 {
+            from.select_at(column);
+        } else {
+            to.select_at(column.saturating_sub(date_width + SEPARATOR.len() as u16));
+        }
+    }
+
+    /// Passes the key to the selected date, returns whether it was used.
+    pub fn handle_input(&mut self, key: KeyEvent) -> bool {
         let is_any_selected = self.is_any_selected();
         let (from, to) = &mut self.inputs;
         if !is_any_selected {
             match key.code {
-                KeyCode::Left => { to.state.selection = Selection::Year }
-                KeyCode::Right => { from.state.selection = Selection::Day }
-                _ => {}
+                KeyCode::Left => to.select_last(),
+                KeyCode::Right => from.select_first(),
+                _ => return false,
             }
-            return;
+            return true;
         }
-        if from.state.selection != Selection::None {
-            from.handle_input(key);
+        let dates = (from.state.date, to.state.date);
+        let is_used;
+        let to_moved = from.state.selection == Selection::None;
+        if !to_moved {
+            is_used = from.handle_input(key);
             if from.state.selection == Selection::None && key.code == KeyCode::Right {
-                to.state.selection = Selection::Day;
+                to.select_first();
             }
-            to.min = from.state.date.into();
-        } else if to.state.selection != Selection::None {
-            to.handle_input(key);
+        } else {
+            is_used = to.handle_input(key);
             if to.state.selection == Selection::None && key.code == KeyCode::Left {
-                from.state.selection = Selection::Year;
+                from.select_last();
             }
-            from.max = to.state.date.into();
         }
+        if (from.state.date, to.state.date) != dates {
+            self.preset = None;
+        }
+        self.keep_valid(to_moved);
+        is_used
     }
-    pub fn get_time_period(&self) -> (u32, u32) {
+
+    /// Renders the calendar of the selected date below `area`, where the widget was drawn.
+    pub fn render_calendar(&self, area: Rect, buf: &mut Buffer) {
         let (from, to) = &self.inputs;
-        (
-            from.state.date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as u32,
-            to.state.date.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp() as u32,
-        )
+        from.render_calendar(area, buf);
+        to.render_calendar(area.offset(Offset { x: (self.get_date_width() + SEPARATOR.len() as u16) as i32, y: 0 }), buf);
     }
-}
\ No newline at end of file
+
+    pub fn get_num_days(&self) -> u64 {
+        let (from, to) = self.get_dates();
+        (to - from).num_days().max(0) as u64 + 1
+    }
+
+    /// Returns the period covering the selected days.
+    pub fn get_time_period(&self, calendar: &Calendar) -> (i64, i64) {
+        let (from, to) = self.get_dates();
+        calendar.period(from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::prelude::Stylize;
+    use ratatui::Terminal;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn dates(from: &str, to: &str) -> (NaiveDate, NaiveDate) {
+        (date(from), date(to))
+    }
+
+    /// June 10th to 12th, dates can be picked in May and June.
+    fn create_widget() -> DateRangeInputWidget {
+        let mut widget = DateRangeInputWidget::new(date("2024-05-01"), date("2024-06-30"));
+        widget.set_dates(date("2024-06-10"), date("2024-06-12"));
+        widget
+    }
+
+    fn press(widget: &mut DateRangeInputWidget, code: KeyCode) -> bool {
+        widget.handle_input(KeyEvent::from(code))
+    }
+
+    fn draw(widget: &mut DateRangeInputWidget, height: u16) -> Terminal<TestBackend> {
+        let mut terminal = Terminal::new(TestBackend::new(widget.get_width() + 3, height)).unwrap();
+        terminal.draw(|frame| {
+            let area = frame.area();
+            frame.render_widget(&mut *widget, area);
+            widget.render_calendar(area, frame.buffer_mut());
+        }).unwrap();
+        terminal
+    }
+
+    fn get_text(terminal: &Terminal<TestBackend>) -> String {
+        terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    fn get_selections(widget: &DateRangeInputWidget) -> (Selection, Selection) {
+        (widget.inputs.0.state.selection, widget.inputs.1.state.selection)
+    }
+
+    #[test]
+    fn renders_both_dates_with_the_selected_part_highlighted() {
+        let mut widget = create_widget();
+        widget.select();
+        let terminal = draw(&mut widget, 1);
+        let mut expected = Buffer::with_lines(["10.06.2024 - 12.06.2024   "]);
+        expected.set_style(Rect::new(0, 0, 10, 1), Style::default().bold());
+        expected.set_style(Rect::new(13, 0, 10, 1), Style::default().bold());
+        expected.set_style(Rect::new(0, 0, 2, 1), Theme::default().accent);
+        terminal.backend().assert_buffer(&expected);
+    }
+
+    #[test]
+    fn renders_in_the_configured_format() {
+        let mut widget = create_widget().format(DateFormat::parse("%Y-%m-%d").unwrap());
+        assert_eq!(widget.get_width(), 23);
+        assert_eq!(get_text(&draw(&mut widget, 1)), "2024-06-10 - 2024-06-12   ");
+    }
+
+    #[test]
+    fn renders_typed_digits_and_the_calendar() {
+        let mut widget = create_widget();
+        widget.select();
+        press(&mut widget, KeyCode::Char('2'));
+        assert_eq!(get_text(&draw(&mut widget, 1)), "2_.06.2024 - 12.06.2024   ");
+        press(&mut widget, KeyCode::Enter);
+        insta::assert_snapshot!(draw(&mut widget, 11).backend());
+    }
+
+    #[test]
+    fn moving_from_past_to_moves_to() {
+        let mut widget = create_widget();
+        widget.select();
+        for _ in 0..4 {
+            assert!(press(&mut widget, KeyCode::Up));
+        }
+        assert_eq!(widget.get_dates(), dates("2024-06-14", "2024-06-14"));
+        press(&mut widget, KeyCode::Down);
+        assert_eq!(widget.get_dates(), dates("2024-06-13", "2024-06-14"));
+        press(&mut widget, KeyCode::Right);
+        press(&mut widget, KeyCode::PageUp);
+        assert_eq!(widget.get_dates(), dates("2024-06-30", "2024-06-30"));
+    }
+
+    #[test]
+    fn moving_to_before_from_moves_from() {
+        let mut widget = create_widget();
+        widget.select_at(13);
+        for _ in 0..4 {
+            press(&mut widget, KeyCode::Down);
+        }
+        assert_eq!(widget.get_dates(), dates("2024-06-08", "2024-06-08"));
+        press(&mut widget, KeyCode::Up);
+        assert_eq!(widget.get_dates(), dates("2024-06-08", "2024-06-09"));
+        // typed past the min
+        widget.select_at(22);
+        for digit in "2023".chars() {
+            press(&mut widget, KeyCode::Char(digit));
+        }
+        assert_eq!(widget.get_dates(), dates("2024-05-01", "2024-05-01"));
+    }
+
+    #[test]
+    fn typing_from_past_to_moves_to() {
+        let mut widget = create_widget();
+        widget.select();
+        press(&mut widget, KeyCode::Char('2'));
+        press(&mut widget, KeyCode::Char('0'));
+        assert_eq!(widget.get_dates(), dates("2024-06-20", "2024-06-20"));
+        assert_eq!(get_selections(&widget), (Selection::Month, Selection::None));
+    }
+
+    #[test]
+    fn set_dates_keeps_within_min_and_max() {
+        let mut widget = create_widget();
+        widget.set_dates(date("2024-06-12"), date("2024-06-10"));
+        assert_eq!(widget.get_dates(), dates("2024-06-10", "2024-06-12"));
+        widget.set_dates(date("2024-04-01"), date("2024-07-30"));
+        assert_eq!(widget.get_dates(), dates("2024-05-01", "2024-06-30"));
+        assert_eq!(widget.get_num_days(), 61);
+        widget.set_dates(date("2024-07-01"), date("2024-07-30"));
+        assert_eq!(widget.get_dates(), dates("2024-06-30", "2024-06-30"));
+    }
+
+    #[test]
+    fn presets_cycle_and_end_today() {
+        let today = date("2024-06-12");
+        let mut preset = RangePreset::all()[0];
+        let mut seen = Vec::new();
+        for _ in 0..RangePreset::all().len() {
+            seen.push((preset.as_title(), preset.get_dates(today)));
+            preset = preset.next();
+        }
+        assert_eq!(preset, RangePreset::all()[0]);
+        assert_eq!(seen, [
+            ("Last 7 days".to_string(), dates("2024-06-06", "2024-06-12")),
+            ("Last 30 days".to_string(), dates("2024-05-14", "2024-06-12")),
+            ("Month to date".to_string(), dates("2024-06-01", "2024-06-12")),
+            ("Year to date".to_string(), dates("2024-01-01", "2024-06-12")),
+        ]);
+        assert_eq!(RangePreset::LastDays(90).next(), RangePreset::all()[0]);
+        assert_eq!(RangePreset::LastDays(1).get_dates(today), (today, today));
+        assert_eq!(RangePreset::LastDays(0).get_dates(today), (today, today));
+    }
+
+    #[test]
+    fn preset_is_kept_until_the_dates_change() {
+        let mut widget = create_widget();
+        assert_eq!(widget.get_preset(), None);
+        widget.set_preset(RangePreset::YearToDate, date("2024-06-12"));
+        assert_eq!(widget.get_preset(), Some(RangePreset::YearToDate));
+        assert_eq!(widget.get_dates(), dates("2024-05-01", "2024-06-12"));
+        widget.select();
+        press(&mut widget, KeyCode::Right);
+        assert_eq!(widget.get_preset(), Some(RangePreset::YearToDate));
+        press(&mut widget, KeyCode::Up);
+        assert_eq!(widget.get_preset(), None);
+        widget.set_preset(RangePreset::LastDays(7), date("2024-06-12"));
+        widget.set_dates(date("2024-06-01"), date("2024-06-02"));
+        assert_eq!(widget.get_preset(), None);
+    }
+
+    #[test]
+    fn select_at_maps_columns_to_date_parts() {
+        use Selection::*;
+        let mut widget = create_widget();
+        let expected = [
+            (Day, None), (Day, None), (Day, None),
+            (Month, None), (Month, None), (Month, None),
+            (Year, None), (Year, None), (Year, None), (Year, None),
+            // the separator is split between the dates
+            (Year, None), (None, Day), (None, Day),
+            (None, Day), (None, Day), (None, Day),
+            (None, Month), (None, Month), (None, Month),
+            (None, Year), (None, Year), (None, Year), (None, Year),
+            // past the end
+            (None, Year),
+        ];
+        for (column, expected) in expected.into_iter().enumerate() {
+            widget.select_at(column as u16);
+            assert_eq!(get_selections(&widget), expected, "column {column}");
+        }
+
+        let mut widget = create_widget().format(DateFormat::parse("%Y-%m-%d").unwrap());
+        widget.select_at(0);
+        assert_eq!(get_selections(&widget), (Year, None));
+        widget.select_at(8);
+        assert_eq!(get_selections(&widget), (Day, None));
+        widget.select_at(13);
+        assert_eq!(get_selections(&widget), (None, Year));
+    }
+
+    #[test]
+    fn arrows_move_between_the_parts_of_both_dates() {
+        use Selection::*;
+        let mut widget = create_widget();
+        assert!(!press(&mut widget, KeyCode::Up));
+        assert!(press(&mut widget, KeyCode::Left));
+        assert_eq!(get_selections(&widget), (None, Year));
+        widget.deselect();
+        assert!(press(&mut widget, KeyCode::Right));
+        let mut selections = vec![get_selections(&widget)];
+        while widget.is_any_selected() {
+            press(&mut widget, KeyCode::Right);
+            selections.push(get_selections(&widget));
+        }
+        assert_eq!(selections, [
+            (Day, None), (Month, None), (Year, None),
+            (None, Day), (None, Month), (None, Year),
+            (None, None),
+        ]);
+        widget.select_at(13);
+        press(&mut widget, KeyCode::Left);
+        assert_eq!(get_selections(&widget), (Year, None));
+        widget.select();
+        press(&mut widget, KeyCode::Left);
+        assert!(!widget.is_any_selected());
+        assert_eq!(widget.get_dates(), dates("2024-06-10", "2024-06-12"));
+    }
+}
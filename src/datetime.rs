@@ -1,30 +1,536 @@
-use chrono::{DateTime, Datelike, Local, TimeDelta, Timelike};
+use chrono::{DateTime, Datelike, Days, FixedOffset, Local, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone, Weekday};
+use serde::{Deserialize, Deserializer};
+
+/// Resolves a local date and time in `tz`. A time skipped by a DST transition resolves to
+/// the end of the gap; when it is repeated the earlier one is used.
+pub fn from_local<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => dt,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => {
+            // the gap ends where the offset changes, which lies between the time read with the
+            // offset in effect after the gap and the time read with the one before it
+            let read_with = |offset: FixedOffset| (local - TimeDelta::seconds(offset.local_minus_utc() as i64))
+                .and_utc()
+                .timestamp();
+            let mut earliest = read_with(tz.offset_from_utc_datetime(&(local + TimeDelta::days(1))).fix());
+            let mut latest = read_with(tz.offset_from_utc_datetime(&(local - TimeDelta::days(1))).fix());
+            let at = |seconds: i64| DateTime::from_timestamp(seconds, 0).unwrap_or_default().with_timezone(tz);
+            while earliest < latest {
+                let middle = earliest + (latest - earliest) / 2;
+                if at(middle).naive_local() >= local {
+                    latest = middle;
+                } else {
+                    earliest = middle + 1;
+                }
+            }
+            at(latest)
+        }
+    }
+}
+
+/// How local time is divided into days and weeks. A day starts at `day_start` instead of
+/// midnight, so time spent before it counts towards the previous day.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct Calendar {
+    pub week_start: Weekday,
+    #[serde(deserialize_with = "deserialize_hh_mm")]
+    pub day_start: NaiveTime,
+}
+
+/// The UTC offsets in seconds an interval can have been recorded with, from UTC-12 to UTC+14.
+pub const MIN_UTC_OFFSET: i32 = -12 * 3600;
+pub const MAX_UTC_OFFSET: i32 = 14 * 3600;
+
+/// Returns the offset in seconds the local time zone had at a timestamp in milliseconds.
+pub fn local_offset_at(millis: i64) -> i32 {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+        .with_timezone(&Local).offset().local_minus_utc()
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar {
+            week_start: Weekday::Mon,
+            day_start: NaiveTime::MIN,
+        }
+    }
+}
+
+impl Calendar {
+    /// Returns the day `dt` belongs to.
+    pub fn date_of<Tz: TimeZone>(&self, dt: &DateTime<Tz>) -> NaiveDate {
+        (dt.naive_local() - self.day_start.signed_duration_since(NaiveTime::MIN)).date()
+    }
+
+    /// Returns the day of a timestamp in milliseconds stored with the offset it was recorded
+    /// with, which is read as the current local time when missing.
+    pub fn date_of_millis(&self, millis: i64, utc_offset: Option<i32>) -> NaiveDate {
+        let utc = DateTime::from_timestamp_millis(millis).unwrap_or_default();
+        match utc_offset.and_then(FixedOffset::east_opt) {
+            Some(offset) => self.date_of(&utc.with_timezone(&offset)),
+            None => self.date_of(&utc.with_timezone(&Local)),
+        }
+    }
+
+    /// Returns the first instant of `date` in `tz`.
+    pub fn day_start<Tz: TimeZone>(&self, tz: &Tz, date: NaiveDate) -> DateTime<Tz> {
+        from_local(tz, date.and_time(self.day_start))
+    }
+
+    /// Returns the local timestamps in milliseconds from the start of `from` to the start
+    /// of the day after `to`.
+    pub fn period(&self, from: NaiveDate, to: NaiveDate) -> (i64, i64) {
+        (
+            self.day_start(&Local, from).timestamp_millis(),
+            self.day_start(&Local, to + Days::new(1)).timestamp_millis(),
+        )
+    }
+
+    /// Returns the timestamps in milliseconds the days from `from` to `to` lie between whatever
+    /// offset they were recorded with: from the start of `from` in the easternmost offset to the
+    /// end of `to` in the westernmost one.
+    pub fn period_in_any_offset(&self, from: NaiveDate, to: NaiveDate) -> (i64, i64) {
+        (
+            self.day_start_millis(from, MAX_UTC_OFFSET),
+            self.day_start_millis(to + Days::new(1), MIN_UTC_OFFSET),
+        )
+    }
+
+    /// Returns the first instant of `date` in the UTC offset in seconds.
+    pub fn day_start_millis(&self, date: NaiveDate, utc_offset: i32) -> i64 {
+        date.and_time(self.day_start).and_utc().timestamp_millis() - utc_offset as i64 * 1000
+    }
+
+    /// Returns the first day of the week `date` is in.
+    pub fn week_of(&self, date: NaiveDate) -> NaiveDate {
+        date - Days::new(self.days_since_week_start(date))
+    }
+
+    /// Returns the first day of the month `date` is in.
+    pub fn month_of(&self, date: NaiveDate) -> NaiveDate {
+        date.with_day(1).unwrap_or(date)
+    }
+
+    /// Returns the first day of the year `date` is in.
+    pub fn year_of(&self, date: NaiveDate) -> NaiveDate {
+        date.with_ordinal(1).unwrap_or(date)
+    }
+
+    fn days_since_week_start(&self, date: NaiveDate) -> u64 {
+        let weekday = date.weekday().num_days_from_monday();
+        ((7 + weekday - self.week_start.num_days_from_monday()) % 7) as u64
+    }
+}
+
+fn deserialize_hh_mm<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+const LOCAL_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Reads a local time entered as "yyyy-mm-dd hh:mm" as a timestamp in milliseconds.
+pub fn parse_local_time(value: &str) -> Option<i64> {
+    let local = NaiveDateTime::parse_from_str(value.trim(), LOCAL_TIME_FORMAT).ok()?;
+    Some(from_local(&Local, local).timestamp_millis())
+}
+
+/// Formats a timestamp in milliseconds as the local time `parse_local_time` reads.
+pub fn format_local_time(millis: i64) -> String {
+    DateTime::from_timestamp_millis(millis)
+        .map(|time| time.with_timezone(&Local).format(LOCAL_TIME_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+pub fn format_duration(millis: i64) -> String {
+    let seconds = millis / 1000;
+    let minutes = seconds / 60;
+    if minutes == 0 {
+        return format!("{seconds}s");
+    }
+    let hours = minutes / 60;
+    if hours == 0 {
+        return format!("{minutes}m {}s", seconds % 60);
+    }
+    let days = hours / 24;
+    if days == 0 {
+        return format!("{hours}h {}m {}s", minutes % 60, seconds % 60);
+    }
+    format!("{days}d {}h {}m {}s", hours % 24, minutes % 60, seconds % 60)
+}
 
 pub trait DateTimeExtensions {
-    fn start_of_day(&self) -> Option<DateTime<Local>>;
-    fn start_of_day_ts(&self, days_to_subtract: i64) -> u32;
-    fn start_of_week_ts(&self, weeks_to_subtract: i64) -> u32;
-    fn num_days_between_starts(&self, to: DateTime<Local>) -> i64;
+    fn start_of_day(&self, calendar: &Calendar) -> Self;
+    fn start_of_day_ts(&self, calendar: &Calendar, days_to_subtract: u64) -> Option<i64>;
+    fn start_of_week_ts(&self, calendar: &Calendar, weeks_to_subtract: u64) -> Option<i64>;
+    fn num_days_between_starts(&self, calendar: &Calendar, to: Self) -> i64;
+}
+
+/// Day arithmetic is done on local calendar dates rather than by subtracting 24-hour spans,
+/// so days that are 23 or 25 hours long around DST transitions are handled correctly.
+/// Timestamps are in milliseconds, like the ones stored in the database. None is returned for
+/// days before the first one chrono can represent.
+impl<Tz: TimeZone> DateTimeExtensions for DateTime<Tz> {
+    fn start_of_day(&self, calendar: &Calendar) -> Self {
+        calendar.day_start(&self.timezone(), calendar.date_of(self))
+    }
+    fn start_of_day_ts(&self, calendar: &Calendar, days_to_subtract: u64) -> Option<i64> {
+        let date = calendar.date_of(self).checked_sub_days(Days::new(days_to_subtract))?;
+        Some(calendar.day_start(&self.timezone(), date).timestamp_millis())
+    }
+    fn start_of_week_ts(&self, calendar: &Calendar, weeks_to_subtract: u64) -> Option<i64> {
+        let days_to_subtract = weeks_to_subtract.checked_mul(7)?
+            .checked_add(calendar.days_since_week_start(calendar.date_of(self)))?;
+        self.start_of_day_ts(calendar, days_to_subtract)
+    }
+    fn num_days_between_starts(&self, calendar: &Calendar, to: Self) -> i64 {
+        (calendar.date_of(&to) - calendar.date_of(self)).num_days()
+    }
+}
+
+/// A period from the `[[periods]]` section of the config, either the last `days` days up to now
+/// or the days from `from` to `to`, where a missing `to` runs until now.
+///
+/// ```toml
+/// [[periods]]
+/// name = "Last 2 weeks"
+/// days = 14
+///
+/// [[periods]]
+/// name = "Q3"
+/// from = "2026-07-01"
+/// to = "2026-09-30"
+/// ```
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+pub struct NamedPeriod {
+    pub name: String,
+    pub days: Option<u64>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// A period the stats can be shown for, relative to the current time.
+#[derive(Clone, PartialEq, Debug)]
+pub enum StatsPeriod {
+    Total,
+    Today,
+    Yesterday,
+    /// The given number of days up to now, today included.
+    LastDays(u64),
+    ThisWeek,
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+    ThisYear,
+    LastYear,
+    Named(NamedPeriod),
+    /// Days picked in the UI, which has to work out the period itself.
+    Custom,
+}
+
+/// How long a period is, in the unit it is moved back by to get the one before it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PeriodLength {
+    Days(u64),
+    Months(u32),
+}
+
+impl PeriodLength {
+    /// Moves a timestamp in milliseconds back by the length, keeping the local time of day.
+    /// A day missing in a shorter month is clamped to its last day.
+    pub fn subtract_from<Tz: TimeZone>(&self, tz: &Tz, millis: i64) -> i64 {
+        let local = DateTime::from_timestamp_millis(millis).unwrap_or_default().with_timezone(tz).naive_local();
+        let shifted = match self {
+            PeriodLength::Days(days) => local - Days::new(*days),
+            PeriodLength::Months(months) => local - Months::new(*months),
+        };
+        from_local(tz, shifted).timestamp_millis()
+    }
 }
 
-impl DateTimeExtensions for DateTime<Local> {
-    fn start_of_day(&self) -> Option<DateTime<Local>> {
-        self.with_hour(0)
-            .and_then(|dt| dt.with_minute(0))
-            .and_then(|dt| dt.with_second(0))
-            .and_then(|dt| dt.with_nanosecond(0))
+impl StatsPeriod {
+    pub fn as_title(&self) -> String {
+        match self {
+            StatsPeriod::Total => "Total".to_string(),
+            StatsPeriod::Today => "Today".to_string(),
+            StatsPeriod::Yesterday => "Yesterday".to_string(),
+            StatsPeriod::LastDays(days) => format!("Last {days} days"),
+            StatsPeriod::ThisWeek => "This week".to_string(),
+            StatsPeriod::LastWeek => "Last week".to_string(),
+            StatsPeriod::ThisMonth => "This month".to_string(),
+            StatsPeriod::LastMonth => "Last month".to_string(),
+            StatsPeriod::ThisYear => "This year".to_string(),
+            StatsPeriod::LastYear => "Last year".to_string(),
+            StatsPeriod::Named(period) => period.name.clone(),
+            StatsPeriod::Custom => "Custom".to_string(),
+        }
     }
-    fn start_of_day_ts(&self, days_to_subtract: i64) -> u32 {
-        (self.clone() - TimeDelta::days(days_to_subtract))
-            .start_of_day()
-            .unwrap()
-            .timestamp() as u32
+
+    /// Returns the period in milliseconds as of `now`, None for all time and for `Custom`.
+    /// Periods that have not ended yet run until `now`.
+    pub fn as_time_period<Tz: TimeZone>(&self, calendar: &Calendar, now: &DateTime<Tz>) -> Option<(i64, i64)> {
+        let tz = now.timezone();
+        let today = calendar.date_of(now);
+        let start_of = |date: NaiveDate| calendar.day_start(&tz, date).timestamp_millis();
+        let now_ts = now.timestamp_millis();
+        match self {
+            StatsPeriod::Total | StatsPeriod::Custom => None,
+            StatsPeriod::Today => Some((start_of(today), now_ts)),
+            StatsPeriod::Yesterday => Some((start_of(today - Days::new(1)), start_of(today))),
+            StatsPeriod::LastDays(days) => Some((start_of(today - Days::new(days.saturating_sub(1))), now_ts)),
+            StatsPeriod::ThisWeek => Some((start_of(calendar.week_of(today)), now_ts)),
+            StatsPeriod::LastWeek => {
+                let week = calendar.week_of(today);
+                Some((start_of(week - Days::new(7)), start_of(week)))
+            }
+            StatsPeriod::ThisMonth => Some((start_of(calendar.month_of(today)), now_ts)),
+            StatsPeriod::LastMonth => {
+                let month = calendar.month_of(today);
+                Some((start_of(month - Months::new(1)), start_of(month)))
+            }
+            StatsPeriod::ThisYear => Some((start_of(calendar.year_of(today)), now_ts)),
+            StatsPeriod::LastYear => {
+                let year = calendar.year_of(today);
+                Some((start_of(year - Months::new(12)), start_of(year)))
+            }
+            StatsPeriod::Named(period) => match (period.days, period.from) {
+                (Some(days), _) => StatsPeriod::LastDays(days).as_time_period(calendar, now),
+                (None, Some(from)) => {
+                    let to = period.to.map_or(now_ts, |to| start_of(to + Days::new(1)));
+                    Some((start_of(from), to))
+                }
+                (None, None) => None,
+            },
+        }
+    }
+
+    /// Returns how long the period is, None when there is nothing to compare it with.
+    pub fn get_length(&self) -> Option<PeriodLength> {
+        match self {
+            StatsPeriod::Today | StatsPeriod::Yesterday => Some(PeriodLength::Days(1)),
+            StatsPeriod::LastDays(days) => Some(PeriodLength::Days(*days)),
+            StatsPeriod::ThisWeek | StatsPeriod::LastWeek => Some(PeriodLength::Days(7)),
+            StatsPeriod::ThisMonth | StatsPeriod::LastMonth => Some(PeriodLength::Months(1)),
+            StatsPeriod::ThisYear | StatsPeriod::LastYear => Some(PeriodLength::Months(12)),
+            StatsPeriod::Named(period) => match (period.days, period.from, period.to) {
+                (Some(days), _, _) => Some(PeriodLength::Days(days)),
+                (None, Some(from), Some(to)) => Some(PeriodLength::Days((to - from).num_days().max(0) as u64 + 1)),
+                _ => None,
+            },
+            StatsPeriod::Total | StatsPeriod::Custom => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use chrono_tz::America::New_York;
+    use chrono_tz::Australia::Lord_Howe;
+    use chrono_tz::Europe::Berlin;
+    use chrono_tz::Tz;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
     }
-    fn start_of_week_ts(&self, weeks_to_subtract: i64) -> u32 {
-        (self.clone() - TimeDelta::weeks(weeks_to_subtract))
-            .start_of_day_ts(self.weekday().num_days_from_monday() as i64)
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        local(value).and_utc()
     }
-    fn num_days_between_starts(&self, to: DateTime<Local>) -> i64 {
-        (to.start_of_day().unwrap() - self.start_of_day().unwrap()).num_days()
+
+    fn calendar(day_start: &str) -> Calendar {
+        Calendar { day_start: NaiveTime::parse_from_str(day_start, "%H:%M").unwrap(), ..Calendar::default() }
     }
-}
\ No newline at end of file
+
+    /// Each zone with the day it springs forward and the day it falls back in 2024.
+    /// Lord Howe moves by half an hour, and springs forward in October.
+    const ZONES: [(Tz, &str, &str); 3] = [
+        (Berlin, "2024-03-31", "2024-10-27"),
+        (New_York, "2024-03-10", "2024-11-03"),
+        (Lord_Howe, "2024-10-06", "2024-04-07"),
+    ];
+
+    #[test]
+    fn skipped_times_resolve_to_the_end_of_the_gap() {
+        assert_eq!(from_local(&Berlin, local("2024-03-31 02:30")), utc("2024-03-31 01:00"));
+        assert_eq!(from_local(&New_York, local("2024-03-10 02:30")), utc("2024-03-10 07:00"));
+        assert_eq!(from_local(&Lord_Howe, local("2024-10-06 02:15")), utc("2024-10-05 15:30"));
+    }
+
+    #[test]
+    fn repeated_times_resolve_to_the_earlier_one() {
+        assert_eq!(from_local(&Berlin, local("2024-10-27 02:30")), utc("2024-10-27 00:30"));
+        assert_eq!(from_local(&New_York, local("2024-11-03 01:30")), utc("2024-11-03 05:30"));
+        assert_eq!(from_local(&Lord_Howe, local("2024-04-07 01:45")), utc("2024-04-06 14:45"));
+    }
+
+    #[test]
+    fn days_are_shorter_and_longer_around_transitions() {
+        let hours = |tz: &Tz, calendar: &Calendar, day: &str| {
+            let start = calendar.day_start(tz, date(day));
+            let end = calendar.day_start(tz, date(day) + Days::new(1));
+            (end - start).num_minutes() as f64 / 60.0
+        };
+        let midnight = calendar("00:00");
+        assert_eq!(hours(&Berlin, &midnight, "2024-03-31"), 23.0);
+        assert_eq!(hours(&Berlin, &midnight, "2024-10-27"), 25.0);
+        assert_eq!(hours(&New_York, &midnight, "2024-03-10"), 23.0);
+        assert_eq!(hours(&New_York, &midnight, "2024-11-03"), 25.0);
+        assert_eq!(hours(&Lord_Howe, &midnight, "2024-10-06"), 23.5);
+        assert_eq!(hours(&Lord_Howe, &midnight, "2024-04-07"), 24.5);
+        // a day starting within the gap starts where the gap ends
+        let late = calendar("02:15");
+        assert_eq!(late.day_start(&Berlin, date("2024-03-31")), utc("2024-03-31 01:00"));
+        assert_eq!(hours(&Berlin, &late, "2024-03-30"), 23.75);
+        assert_eq!(hours(&Berlin, &late, "2024-03-31"), 23.25);
+        assert_eq!(late.day_start(&Lord_Howe, date("2024-10-06")), utc("2024-10-05 15:30"));
+        assert_eq!(hours(&Lord_Howe, &late, "2024-10-05"), 23.75);
+        // a day starting within the repeated hour starts the first time it comes round
+        assert_eq!(late.day_start(&Berlin, date("2024-10-27")), utc("2024-10-27 00:15"));
+        assert_eq!(hours(&Berlin, &late, "2024-10-27"), 25.0);
+    }
+
+    #[test]
+    fn every_instant_belongs_to_the_day_that_started_last() {
+        for (tz, spring, fall) in ZONES {
+            for day_start in ["00:00", "01:45", "02:15", "04:00", "23:30"] {
+                let calendar = calendar(day_start);
+                for transition in [spring, fall] {
+                    for offset in 0..4 {
+                        let day = date(transition) - Days::new(1) + Days::new(offset);
+                        let start = calendar.day_start(&tz, day);
+                        let end = calendar.day_start(&tz, day + Days::new(1));
+                        let context = format!("{tz} {day} starting at {day_start}");
+                        assert!(start < end, "{context}");
+                        assert_eq!(calendar.date_of(&start), day, "{context}");
+                        assert_eq!(calendar.date_of(&(start - TimeDelta::seconds(1))), day - Days::new(1), "{context}");
+                        assert_eq!(calendar.date_of(&(end - TimeDelta::seconds(1))), day, "{context}");
+                        assert_eq!(calendar.date_of_millis(start.timestamp_millis(), Some(start.offset().fix().local_minus_utc())), day, "{context}");
+                        assert_eq!(start.num_days_between_starts(&calendar, end), 1, "{context}");
+                        assert_eq!(end.start_of_day_ts(&calendar, 1), Some(start.timestamp_millis()), "{context}");
+                    }
+                }
+            }
+        }
+    }
+
+    fn millis(value: &str) -> i64 {
+        utc(value).timestamp_millis()
+    }
+
+    fn period(from: &str, to: &str) -> Option<(i64, i64)> {
+        Some((millis(from), millis(to)))
+    }
+
+    #[test]
+    fn periods_run_up_to_now() {
+        let calendar = Calendar::default();
+        let now = utc("2024-06-12 15:00");
+        let named = |days, from: Option<&str>, to: Option<&str>| StatsPeriod::Named(NamedPeriod {
+            name: "Named".to_string(),
+            days,
+            from: from.map(date),
+            to: to.map(date),
+        });
+        let expected = [
+            (StatsPeriod::Total, None),
+            (StatsPeriod::Custom, None),
+            (StatsPeriod::Today, period("2024-06-12 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::Yesterday, period("2024-06-11 00:00", "2024-06-12 00:00")),
+            (StatsPeriod::LastDays(3), period("2024-06-10 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::LastDays(0), period("2024-06-12 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::ThisWeek, period("2024-06-10 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::LastWeek, period("2024-06-03 00:00", "2024-06-10 00:00")),
+            (StatsPeriod::ThisMonth, period("2024-06-01 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::LastMonth, period("2024-05-01 00:00", "2024-06-01 00:00")),
+            (StatsPeriod::ThisYear, period("2024-01-01 00:00", "2024-06-12 15:00")),
+            (StatsPeriod::LastYear, period("2023-01-01 00:00", "2024-01-01 00:00")),
+            (named(Some(14), None, None), period("2024-05-30 00:00", "2024-06-12 15:00")),
+            (named(None, Some("2024-04-01"), Some("2024-04-30")), period("2024-04-01 00:00", "2024-05-01 00:00")),
+            (named(None, Some("2024-06-01"), None), period("2024-06-01 00:00", "2024-06-12 15:00")),
+            (named(None, None, None), None),
+        ];
+        for (stats_period, expected) in expected {
+            assert_eq!(stats_period.as_time_period(&calendar, &now), expected, "{stats_period:?}");
+        }
+    }
+
+    #[test]
+    fn periods_before_the_day_start_belong_to_the_previous_year() {
+        let calendar = Calendar { week_start: Weekday::Sun, ..calendar("04:00") };
+        // still the last day of 2024, a Tuesday
+        let now = utc("2025-01-01 01:00");
+        let expected = [
+            (StatsPeriod::Today, period("2024-12-31 04:00", "2025-01-01 01:00")),
+            (StatsPeriod::Yesterday, period("2024-12-30 04:00", "2024-12-31 04:00")),
+            (StatsPeriod::ThisWeek, period("2024-12-29 04:00", "2025-01-01 01:00")),
+            (StatsPeriod::LastWeek, period("2024-12-22 04:00", "2024-12-29 04:00")),
+            (StatsPeriod::ThisMonth, period("2024-12-01 04:00", "2025-01-01 01:00")),
+            (StatsPeriod::LastMonth, period("2024-11-01 04:00", "2024-12-01 04:00")),
+            (StatsPeriod::ThisYear, period("2024-01-01 04:00", "2025-01-01 01:00")),
+            (StatsPeriod::LastYear, period("2023-01-01 04:00", "2024-01-01 04:00")),
+        ];
+        for (stats_period, expected) in expected {
+            assert_eq!(stats_period.as_time_period(&calendar, &now), expected, "{stats_period:?}");
+        }
+        // from the day start on it is the first day of 2025
+        let now = utc("2025-01-01 04:00");
+        assert_eq!(StatsPeriod::ThisYear.as_time_period(&calendar, &now), period("2025-01-01 04:00", "2025-01-01 04:00"));
+        assert_eq!(StatsPeriod::LastYear.as_time_period(&calendar, &now), period("2024-01-01 04:00", "2025-01-01 04:00"));
+        assert_eq!(StatsPeriod::LastMonth.as_time_period(&calendar, &now), period("2024-12-01 04:00", "2025-01-01 04:00"));
+    }
+
+    #[test]
+    fn periods_at_month_ends() {
+        let calendar = Calendar::default();
+        let now = utc("2024-03-01 10:00");
+        assert_eq!(StatsPeriod::Yesterday.as_time_period(&calendar, &now), period("2024-02-29 00:00", "2024-03-01 00:00"));
+        assert_eq!(StatsPeriod::LastMonth.as_time_period(&calendar, &now), period("2024-02-01 00:00", "2024-03-01 00:00"));
+        assert_eq!(StatsPeriod::LastDays(2).as_time_period(&calendar, &now), period("2024-02-29 00:00", "2024-03-01 10:00"));
+        let now = utc("2024-03-31 23:59");
+        assert_eq!(StatsPeriod::LastMonth.as_time_period(&calendar, &now), period("2024-02-01 00:00", "2024-03-01 00:00"));
+        assert_eq!(StatsPeriod::ThisMonth.as_time_period(&calendar, &now), period("2024-03-01 00:00", "2024-03-31 23:59"));
+        // the day the clocks go forward is an hour shorter, the month starts an hour earlier in UTC
+        let now = from_local(&Berlin, local("2024-03-31 12:00"));
+        assert_eq!(StatsPeriod::Today.as_time_period(&calendar, &now), period("2024-03-30 23:00", "2024-03-31 10:00"));
+        assert_eq!(StatsPeriod::Yesterday.as_time_period(&calendar, &now), period("2024-03-29 23:00", "2024-03-30 23:00"));
+        assert_eq!(StatsPeriod::ThisMonth.as_time_period(&calendar, &now), period("2024-02-29 23:00", "2024-03-31 10:00"));
+    }
+
+    #[test]
+    fn subtracting_lengths_keeps_the_time_of_day() {
+        let subtract = |length: PeriodLength, value: &str| length.subtract_from(&Utc, millis(value));
+        assert_eq!(subtract(PeriodLength::Days(1), "2024-03-01 10:00"), millis("2024-02-29 10:00"));
+        assert_eq!(subtract(PeriodLength::Days(7), "2024-01-03 08:30"), millis("2023-12-27 08:30"));
+        assert_eq!(subtract(PeriodLength::Days(0), "2024-01-03 08:30"), millis("2024-01-03 08:30"));
+        assert_eq!(subtract(PeriodLength::Months(1), "2024-01-15 12:00"), millis("2023-12-15 12:00"));
+        assert_eq!(subtract(PeriodLength::Months(12), "2025-01-01 00:00"), millis("2024-01-01 00:00"));
+        // days missing in the shorter month are clamped to its end
+        assert_eq!(subtract(PeriodLength::Months(1), "2024-03-31 12:00"), millis("2024-02-29 12:00"));
+        assert_eq!(subtract(PeriodLength::Months(1), "2023-03-31 12:00"), millis("2023-02-28 12:00"));
+        assert_eq!(subtract(PeriodLength::Months(1), "2024-05-31 12:00"), millis("2024-04-30 12:00"));
+        assert_eq!(subtract(PeriodLength::Months(12), "2024-02-29 12:00"), millis("2023-02-28 12:00"));
+        assert_eq!(subtract(PeriodLength::Months(3), "2024-05-31 12:00"), millis("2024-02-29 12:00"));
+    }
+
+    #[test]
+    fn subtracting_lengths_across_transitions() {
+        let berlin = |value: &str| from_local(&Berlin, local(value)).timestamp_millis();
+        assert_eq!(PeriodLength::Days(1).subtract_from(&Berlin, berlin("2024-04-01 12:00")), berlin("2024-03-31 12:00"));
+        assert_eq!(berlin("2024-04-01 12:00") - berlin("2024-03-31 12:00"), 24 * 3_600_000);
+        // the same time the day before is only 23 hours earlier over the spring transition
+        assert_eq!(PeriodLength::Days(1).subtract_from(&Berlin, berlin("2024-03-31 12:00")), berlin("2024-03-30 12:00"));
+        assert_eq!(berlin("2024-03-31 12:00") - berlin("2024-03-30 12:00"), 23 * 3_600_000);
+        // landing in the gap moves to its end, in the repeated hour to its first pass
+        assert_eq!(PeriodLength::Days(1).subtract_from(&Berlin, berlin("2024-04-01 02:30")), millis("2024-03-31 01:00"));
+        assert_eq!(PeriodLength::Days(1).subtract_from(&Berlin, berlin("2024-10-28 02:30")), millis("2024-10-27 00:30"));
+        assert_eq!(PeriodLength::Months(1).subtract_from(&Berlin, berlin("2024-11-01 00:00")), millis("2024-09-30 22:00"));
+    }
+}
@@ -1,105 +1,1317 @@
-use chrono::{DateTime, Local, NaiveDate};
-use rusqlite::Connection;
+use crate::datetime::{local_offset_at, Calendar, MAX_UTC_OFFSET, MIN_UTC_OFFSET};
+use crate::query::{GroupBy, Stat, StatKey, StatsQuery};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use rusqlite::backup::Progress;
+use rusqlite::{params_from_iter, Connection, DatabaseName, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
+/// Schema changes applied in order, `user_version` holds the number of the ones already applied.
+const MIGRATIONS: &[&str] = &[
+    "create table if not exists activity (
+        window_name text not null,
+        time_from integer not null,
+        time_to integer not null
+    )",
+    // seconds east of UTC in effect when the interval was recorded, null for older rows
+    "alter table activity add column utc_offset integer",
+    "alter table activity add column title text",
+    "create table if not exists app (
+        window_name text primary key,
+        category text
+    )",
+    "create index if not exists activity_window_name_time_from on activity (window_name, time_from);
+     create index if not exists activity_time_from_time_to on activity (time_from, time_to);",
+    "create table if not exists tracker_state (
+        id integer primary key check (id = 1),
+        heartbeat integer not null,
+        open_rowid integer,
+        clean_shutdown integer not null
+    )",
+    "create table if not exists downtime (
+        kind text not null,
+        time_from integer not null,
+        time_to integer not null
+    )",
+    // timestamps were whole seconds, they are milliseconds from here on
+    "update activity set time_from = time_from * 1000, time_to = time_to * 1000;
+     update downtime set time_from = time_from * 1000, time_to = time_to * 1000;
+     update tracker_state set heartbeat = heartbeat * 1000;",
+    // intervals merged from other devices keep the device and the rowid they had there,
+    // both are null for the ones recorded here
+    "alter table activity add column device text;
+     alter table activity add column source_rowid integer;
+     create unique index if not exists activity_device_source_rowid on activity (device, source_rowid);
+     create table if not exists sync_export (
+         id integer primary key check (id = 1),
+         last_rowid integer not null,
+         last_time_to integer not null
+     );
+     create table if not exists sync_import (
+         device text primary key,
+         position integer not null
+     );",
+    // time per app and local day in milliseconds, kept for days whose intervals were pruned
+    "create table if not exists daily_summary (
+         window_name text not null,
+         device text,
+         day text not null,
+         time integer not null
+     );
+     create index if not exists daily_summary_day on daily_summary (day);",
+    // summaries are kept for every day from here on, the ones so far were all rolled up by a prune
+    "alter table daily_summary add column pruned integer not null default 0;
+     update daily_summary set pruned = 1;
+     create index if not exists daily_summary_window_name_day on daily_summary (window_name, day);
+     create table if not exists summary_state (
+         id integer primary key check (id = 1),
+         day_start integer not null
+     );",
+    // the project the rules in the config attributed the time to, null for none
+    "alter table activity add column project text;
+     alter table daily_summary add column project text;",
+    // intervals entered by hand are flagged, every edit is logged with the intervals it removed
+    // and added as JSON arrays so it can be undone
+    "alter table activity add column manual integer not null default 0;
+     create table if not exists edit_log (
+         id integer primary key,
+         time integer not null,
+         action text not null,
+         removed text not null,
+         added text not null
+     );",
+    // the color an app is drawn in, picked by its name when null
+    "alter table app add column color text;",
+    // the rowid of a deleted interval was given to the next one, other devices then mixed the
+    // two up. Rowids are never reused from here on, and every change to an interval recorded
+    // here is queued for the change log, the last rowid exported before included.
+    "create table activity_new (
+         id integer primary key autoincrement,
+         window_name text not null,
+         time_from integer not null,
+         time_to integer not null,
+         utc_offset integer,
+         title text,
+         device text,
+         source_rowid integer,
+         project text,
+         manual integer not null default 0
+     );
+     insert into activity_new (id, window_name, time_from, time_to, utc_offset, title, device, source_rowid, project, manual)
+         select rowid, window_name, time_from, time_to, utc_offset, title, device, source_rowid, project, manual
+         from activity;
+     drop table activity;
+     alter table activity_new rename to activity;
+     create index activity_window_name_time_from on activity (window_name, time_from);
+     create index activity_time_from_time_to on activity (time_from, time_to);
+     create unique index activity_device_source_rowid on activity (device, source_rowid);
+     insert into sqlite_sequence (name, seq)
+         select 'activity', 0 where not exists (select 1 from sqlite_sequence where name = 'activity');
+     update sqlite_sequence set seq = max(seq, (select coalesce(max(last_rowid), 0) from sync_export))
+         where name = 'activity';
+     create table sync_change (
+         id integer primary key autoincrement,
+         interval_rowid integer not null unique
+     );
+     insert into sync_change (interval_rowid)
+         select rowid from activity
+         where device is null and not exists (
+             select 1 from sync_export
+             where activity.rowid < last_rowid or activity.rowid = last_rowid and activity.time_to <= last_time_to
+         )
+         order by rowid;
+     drop table sync_export;
+     create trigger activity_insert_change after insert on activity when new.device is null begin
+         insert or replace into sync_change (interval_rowid) values (new.rowid);
+     end;
+     create trigger activity_update_change after update on activity when new.device is null begin
+         insert or replace into sync_change (interval_rowid) values (new.rowid);
+     end;
+     create trigger activity_delete_change after delete on activity when old.device is null begin
+         insert or replace into sync_change (interval_rowid) values (old.rowid);
+     end;",
+    // a name made up once for this database, which the sync folder tells this device apart by
+    // when neither the config nor the computer name give it one
+    "create table if not exists device (
+         id integer primary key check (id = 1),
+         name text not null
+     );
+     insert into device (id, name) values (1, lower(hex(randomblob(8))));",
+];
+
+/// A window name and the title it was recorded with, which is null for older rows.
+pub type StoredSample = (String, Option<String>);
 
 pub struct Db {
     connection: Connection,
 }
 
 impl Db {
-    pub fn get_path() -> PathBuf {
-        let local_data_dir = env::var("LOCALAPPDATA").unwrap_or_else(|_| {
-            panic!("Could not find env LOCALAPPDATA in the windows system");
-        });
-        let mut db_path = PathBuf::from(local_data_dir);
-        db_path.push("app-time");
+    /// The folder everything is kept in: `AppData\\Local\\app-time` on Windows, elsewhere
+    /// `app-time` in `XDG_DATA_HOME` or `~/.local/share`.
+    pub fn get_data_dir() -> PathBuf {
+        let local_data_dir = env::var_os("LOCALAPPDATA")
+            .or_else(|| env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()))
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+            .unwrap_or_else(|| panic!("Could not find LOCALAPPDATA, XDG_DATA_HOME or HOME"));
+        let mut data_dir = local_data_dir;
+        data_dir.push("app-time");
 
-        if !db_path.exists() {
-            fs::create_dir_all(&db_path).expect("Could not create a directory in AppData\\Local");
+        if !data_dir.exists() {
+            fs::create_dir_all(&data_dir).expect("Could not create the data directory");
         }
+        data_dir
+    }
 
+    pub fn get_path() -> PathBuf {
+        let mut db_path = Self::get_data_dir();
         db_path.push("db.sqlite");
         db_path
     }
 
     pub fn init() -> Result<Db, Box<dyn Error>> {
+        Self::open(&Self::get_path())
+    }
+
+    pub fn open(path: &Path) -> Result<Db, Box<dyn Error>> {
         let db = Db {
-            connection: Connection::open(Self::get_path())?
+            connection: Connection::open(path)?
         };
-        db.connection.execute("create table if not exists activity (
-            window_name text not null,
-            time_from integer not null,
-            time_to integer not null
-        )", ())?;
+        // lets the ui read while the tracker writes, and makes the tracker's commits cheaper
+        db.connection.pragma_update_and_check(None, "journal_mode", "wal", |row| row.get::<_, String>(0))?;
+        db.connection.pragma_update(None, "synchronous", "normal")?;
+        db.migrate()?;
         Ok(db)
     }
 
-    pub fn update_last(&self, window_name: &String, time_to: u32) -> Result<(), Box<dyn Error>> {
-        self.connection.execute("update activity 
-            set time_to = ?
-            where rowid = (
-                select rowid from activity 
-                where window_name = ? 
-                order by time_from desc 
-                limit 1
-        )", (time_to, window_name))?;
+    /// A database that only lives as long as the returned value, with the schema of a new one.
+    pub fn open_in_memory() -> Result<Db, Box<dyn Error>> {
+        let db = Db {
+            connection: Connection::open_in_memory()?
+        };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn migrate(&self) -> Result<(), Box<dyn Error>> {
+        let version: i64 = self.connection.query_row("pragma user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = self.connection.unchecked_transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", i as i64 + 1)?;
+            transaction.commit()?;
+        }
+        self.fill_offsets()
+    }
+
+    /// Gives the intervals recorded before offsets were stored the offset the local time zone
+    /// had when they started, so their days do not change with the current offset.
+    fn fill_offsets(&self) -> Result<(), Box<dyn Error>> {
+        let mut statement = self.connection.prepare("select rowid, time_from from activity where utc_offset is null")?;
+        let intervals = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(i64, i64)>, _>>()?;
+        self.in_transaction(|| {
+            for (rowid, time_from) in intervals {
+                self.connection.execute(
+                    "update activity set utc_offset = ?2 where rowid = ?1",
+                    (rowid, local_offset_at(time_from)),
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Copies the database into `path` while it stays in use.
+    pub fn backup(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.connection.backup(DatabaseName::Main, path, None)?;
+        Ok(())
+    }
+
+    /// Replaces the database with the one at `path` and brings its schema up to date.
+    pub fn restore(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if !path.exists() {
+            return Err(format!("{} does not exist", path.display()).into());
+        }
+        self.connection.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        self.migrate()
+    }
+
+    /// Deletes what was recorded before `before`, intervals running across it are cut there.
+    /// With `rollup` the summaries of the deleted days are kept, so `before` should be the start
+    /// of a day in `calendar`.
+    pub fn prune(&self, before: i64, rollup: bool, calendar: &Calendar) -> Result<usize, Box<dyn Error>> {
+        self.in_transaction(|| {
+            if rollup {
+                self.summarize(Some((0, before)), None, true, calendar)?;
+            } else {
+                let day = calendar.date_of_millis(before, None);
+                self.connection.execute("delete from daily_summary where day < ?1", [day])?;
+            }
+            // other devices keep what they merged, so pruning is left out of the change log
+            // unless an interval that is cut had a change waiting for it anyway
+            let last_change: i64 = self.connection.query_row(
+                "select coalesce(max(id), 0) from sync_change",
+                [],
+                |row| row.get(0),
+            )?;
+            let mut statement = self.connection.prepare(
+                "select interval_rowid from sync_change
+                     where interval_rowid in (select rowid from activity where time_from < ?1 and time_to > ?1)"
+            )?;
+            let pending = statement.query_map([before], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            let deleted = self.connection.execute("delete from activity where time_to <= ?1", [before])?;
+            self.connection.execute("update activity set time_from = ?1 where time_from < ?1", [before])?;
+            self.connection.execute("delete from sync_change where id > ?1", [last_change])?;
+            for rowid in pending {
+                self.connection.execute("insert into sync_change (interval_rowid) values (?1)", [rowid])?;
+            }
+            self.connection.execute("delete from downtime where time_to <= ?1", [before])?;
+            self.connection.execute("update downtime set time_from = ?1 where time_from < ?1", [before])?;
+            // in other offsets `before` is within a day, whose intervals after it are summed up again
+            self.resummarize(
+                calendar.date_of_millis(before, Some(MIN_UTC_OFFSET)),
+                calendar.date_of_millis(before, Some(MAX_UTC_OFFSET)),
+                calendar,
+            )?;
+            Ok(deleted)
+        })
+    }
+
+    /// Adds time recorded on `day` to its summary.
+    pub fn add_to_summary(
+        &self,
+        window_name: &String,
+        project: Option<&String>,
+        device: Option<&String>,
+        day: NaiveDate,
+        millis: i64,
+    ) -> Result<(), Box<dyn Error>> {
+        if millis <= 0 {
+            return Ok(());
+        }
+        let updated = self.connection.execute(
+            "update daily_summary set time = time + ?5
+                 where window_name = ?1 and project is ?2 and device is ?3 and day = ?4",
+            (window_name, project, device, day, millis),
+        )?;
+        if updated == 0 {
+            self.connection.execute(
+                "insert into daily_summary (window_name, project, device, day, time) values (?1, ?2, ?3, ?4, ?5)",
+                (window_name, project, device, day, millis),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds `daily_summary` if it was built with another day start, or not at all yet.
+    pub fn check_summary(&self, calendar: &Calendar) -> Result<(), Box<dyn Error>> {
+        let built_with: Option<i64> = self.connection.query_row(
+            "select day_start from summary_state",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        if built_with != Some(Self::get_day_start_millis(calendar)) {
+            self.rebuild_summary(calendar)?;
+        }
         Ok(())
     }
 
-    pub fn insert(&self, window_name: &String, time_from: u32) -> Result<(), Box<dyn Error>> {
+    /// Sums up all intervals again. The summaries of pruned days are kept as they are.
+    pub fn rebuild_summary(&self, calendar: &Calendar) -> Result<(), Box<dyn Error>> {
+        self.in_transaction(|| {
+            self.connection.execute("delete from daily_summary where not pruned", [])?;
+            self.summarize(None, None, false, calendar)?;
+            self.connection.execute(
+                "insert or replace into summary_state (id, day_start) values (1, ?1)",
+                [Self::get_day_start_millis(calendar)],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Replaces the summaries of the days the intervals within `period` are in, only of the days
+    /// from the first to the last of `days` if given. The summaries of pruned days are kept.
+    fn summarize(
+        &self,
+        period: Option<(i64, i64)>,
+        days: Option<(NaiveDate, NaiveDate)>,
+        pruned: bool,
+        calendar: &Calendar,
+    ) -> Result<(), Box<dyn Error>> {
+        let (sql, params) = StatsQuery::new()
+            .period(period)
+            .calendar(*calendar)
+            .to_summary_sql();
+        let mut statement = self.connection.prepare(&sql)?;
+        let mut summaries = statement.query_map(params_from_iter(params), |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })?
+            .collect::<Result<Vec<(String, Option<String>, Option<String>, NaiveDate, i64)>, _>>()?;
+        if let Some((first_day, last_day)) = days {
+            summaries.retain(|(_, _, _, day, _)| first_day <= *day && *day <= last_day);
+        }
+        for (_, _, _, day, _) in &summaries {
+            self.connection.execute("delete from daily_summary where day = ?1 and not pruned", [day])?;
+        }
+        for (window_name, project, device, day, time) in &summaries {
+            self.connection.execute(
+                "insert into daily_summary (window_name, project, device, day, time, pruned)
+                     values (?1, ?2, ?3, ?4, ?5, ?6)",
+                (window_name, project, device, day, time, pruned),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn get_day_start_millis(calendar: &Calendar) -> i64 {
+        calendar.day_start.signed_duration_since(NaiveTime::MIN).num_milliseconds()
+    }
+
+    /// Gives the space of deleted rows back to the file system.
+    pub fn vacuum(&self) -> Result<(), Box<dyn Error>> {
+        self.connection.execute_batch("vacuum")?;
+        Ok(())
+    }
+
+    /// Runs `f` in a single transaction, so a batch of writes is committed at once.
+    pub fn in_transaction<T>(&self, f: impl FnOnce() -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let result = f()?;
+        transaction.commit()?;
+        Ok(result)
+    }
+
+    pub fn update_time_to(&self, rowid: i64, time_to: i64) -> Result<(), Box<dyn Error>> {
         self.connection.execute(
-            "insert into activity (window_name, time_from, time_to) 
-                 values (?1, ?2, ?2)",
-            (&window_name, time_from),
+            "update activity set time_to = ?2 where rowid = ?1",
+            (rowid, time_to),
         )?;
         Ok(())
     }
 
-    pub fn get_stats(&self, period: Option<(u32, u32)>) -> Result<Vec<WindowStat>, Box<dyn Error>> {
-        let condition = period.map(|(from, to)| {
-            format!("and time_from >= {from} and time_to <= {to}")
-        }).unwrap_or_default();
-        let mut statement = self.connection.prepare(&format!(
-            "select window_name, sum(time_to - time_from) as time
-                  from activity
-                  where time > 0 {condition}
-                  group by window_name
-                  order by time desc"
-        ))?;
-        let result = statement.query_map([], |row| {
+    /// Inserts an interval starting at `time_from` and returns its rowid.
+    pub fn insert(
+        &self,
+        window_name: &String,
+        title: &String,
+        project: Option<&String>,
+        time_from: i64,
+        utc_offset: i32,
+    ) -> Result<i64, Box<dyn Error>> {
+        self.connection.execute(
+            "insert into activity (window_name, title, project, time_from, time_to, utc_offset) 
+                 values (?1, ?2, ?3, ?4, ?4, ?5)",
+            (&window_name, title, project, time_from, utc_offset),
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn get_stats(&self, query: &StatsQuery) -> Result<Vec<Stat>, Box<dyn Error>> {
+        let (sql, params) = query.to_sql();
+        let group_by = query.get_group_by();
+        let mut statement = self.connection.prepare(&sql)?;
+        let result = statement.query_map(params_from_iter(params), |row| {
             Ok(
-                WindowStat {
-                    window_name: row.get(0)?,
-                    seconds: row.get(1)?,
+                Stat {
+                    key: Self::get_stat_key(row, group_by)?,
+                    day: row.get(2)?,
+                    millis: row.get(1)?,
                 }
             )
         })?
-            .map(|row| { row.unwrap() }) // TODO deal with panic
-            .collect();
+            .collect::<Result<Vec<Stat>, _>>()?;
         Ok(result)
     }
-    pub fn get_min_date(&self) -> Result<NaiveDate, Box<dyn Error>> {
+
+    fn get_stat_key(row: &Row, group_by: GroupBy) -> rusqlite::Result<StatKey> {
+        let key = match group_by {
+            GroupBy::App => StatKey::App(row.get(0)?),
+            GroupBy::Title => StatKey::Title(row.get(0)?),
+            GroupBy::Category => StatKey::Category(row.get(0)?),
+            GroupBy::Project => StatKey::Project(row.get(0)?),
+            GroupBy::Device => StatKey::Device(row.get(0)?),
+            GroupBy::Day => StatKey::Day(row.get(0)?),
+            GroupBy::Hour => StatKey::Hour(row.get(0)?),
+        };
+        Ok(key)
+    }
+
+    /// The name made up for this database when it was created.
+    pub fn get_device_name(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.connection.query_row("select name from device", [], |row| row.get(0))?)
+    }
+
+    pub fn get_tracker_state(&self) -> Result<Option<TrackerState>, Box<dyn Error>> {
+        let state = self.connection.query_row(
+            "select heartbeat, open_rowid, clean_shutdown from tracker_state",
+            [],
+            |row| Ok(TrackerState {
+                heartbeat: row.get(0)?,
+                open_rowid: row.get(1)?,
+                clean_shutdown: row.get(2)?,
+            }),
+        ).optional()?;
+        Ok(state)
+    }
+
+    pub fn save_tracker_state(&self, state: &TrackerState) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "insert or replace into tracker_state (id, heartbeat, open_rowid, clean_shutdown)
+                 values (1, ?1, ?2, ?3)",
+            (state.heartbeat, state.open_rowid, state.clean_shutdown),
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_downtime(&self, kind: DowntimeKind, time_from: i64, time_to: i64) -> Result<(), Box<dyn Error>> {
+        if time_to <= time_from {
+            return Ok(());
+        }
+        self.connection.execute(
+            "insert into downtime (kind, time_from, time_to) values (?1, ?2, ?3)",
+            (kind.as_str(), time_from, time_to),
+        )?;
+        Ok(())
+    }
+
+    /// Returns the downtime within the period per kind, clipped to the period like `get_stats`.
+    pub fn get_downtime(&self, period: Option<(i64, i64)>) -> Result<Vec<Downtime>, Box<dyn Error>> {
+        let (from, to) = period.unwrap_or((0, i64::MAX));
+        let mut statement = self.connection.prepare(
+            "select kind, sum(min(time_to, ?2) - max(time_from, ?1)) as time
+                  from downtime
+                  where time_from < ?2 and time_to > ?1
+                  group by kind
+                  order by time desc"
+        )?;
+        let result = statement.query_map((from, to), |row| {
+            Ok((row.get::<_, String>(0)?, row.get(1)?))
+        })?
+            .filter_map(|row| match row {
+                Ok((kind, millis)) => DowntimeKind::parse(&kind)
+                    .map(|kind| Ok(Downtime { kind, millis })),
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<Result<Vec<Downtime>, _>>()?;
+        Ok(result)
+    }
+
+    /// Returns the first local day anything was recorded on, None if nothing was.
+    pub fn get_min_date(&self) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+        let min_time_from: Option<i64> = self.connection.query_row(
+            "select min(time_from) from activity",
+            [],
+            |row| row.get(0),
+        )?;
+        let min_date = min_time_from
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|time| time.with_timezone(&Local).date_naive());
+        // pruned days are only left in the summary
+        let min_summary_day: Option<NaiveDate> = self.connection.query_row(
+            "select min(day) from daily_summary",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(min_date.into_iter().chain(min_summary_day).min())
+    }
+
+    /// Returns the distinct window name and title pairs that have been recorded.
+    pub fn get_samples(&self) -> Result<Vec<StoredSample>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "select distinct window_name, title from activity"
+        )?;
+        let result = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<StoredSample>, _>>()?;
+        Ok(result)
+    }
+
+    pub fn delete_samples(&self, window_name: &String, title: &Option<String>) -> Result<usize, Box<dyn Error>> {
+        let deleted = self.connection.execute(
+            "delete from activity where window_name = ?1 and title is ?2",
+            (window_name, title),
+        )?;
+        Ok(deleted)
+    }
+
+    /// Renames the intervals of a sample and clears their project, which may give away what they were.
+    pub fn rename_samples(
+        &self,
+        window_name: &String,
+        title: &Option<String>,
+        new_window_name: &String,
+        new_title: &String,
+    ) -> Result<usize, Box<dyn Error>> {
+        let updated = self.connection.execute(
+            "update activity set window_name = ?3, title = ?4, project = null where window_name = ?1 and title is ?2",
+            (window_name, title, new_window_name, new_title),
+        )?;
+        Ok(updated)
+    }
+
+    /// Returns the apps in the summaries of pruned days, which are only known by their names.
+    pub fn get_pruned_apps(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "select distinct window_name from daily_summary where pruned"
+        )?;
+        let result = statement.query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(result)
+    }
+
+    pub fn delete_pruned_summaries(&self, window_name: &String) -> Result<usize, Box<dyn Error>> {
+        let deleted = self.connection.execute(
+            "delete from daily_summary where window_name = ?1 and pruned",
+            [window_name],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Renames an app in the summaries of pruned days and clears their project.
+    pub fn rename_pruned_summaries(&self, window_name: &String, new_window_name: &String) -> Result<usize, Box<dyn Error>> {
+        let updated = self.connection.execute(
+            "update daily_summary set window_name = ?2, project = null where window_name = ?1 and pruned",
+            (window_name, new_window_name),
+        )?;
+        Ok(updated)
+    }
+
+    /// Replaces the intervals kept in the edit log for undoing by what `filter` returns for
+    /// them, so undo cannot bring back what it dropped. Entries left without any interval are
+    /// deleted. Returns how many entries changed.
+    pub fn filter_edit_log(&self, filter: impl Fn(&Interval) -> Option<Interval>) -> Result<usize, Box<dyn Error>> {
+        let mut statement = self.connection.prepare("select id, removed, added from edit_log")?;
+        let entries = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(i64, String, String)>, _>>()?;
+        let mut changed = 0;
+        self.in_transaction(|| {
+            for (id, removed, added) in entries {
+                let old_removed: Vec<Interval> = serde_json::from_str(&removed)?;
+                let old_added: Vec<Interval> = serde_json::from_str(&added)?;
+                let new_removed: Vec<Interval> = old_removed.iter().filter_map(&filter).collect();
+                let new_added: Vec<Interval> = old_added.iter().filter_map(&filter).collect();
+                if new_removed == old_removed && new_added == old_added {
+                    continue;
+                }
+                if new_removed.is_empty() && new_added.is_empty() {
+                    self.connection.execute("delete from edit_log where id = ?1", [id])?;
+                } else {
+                    self.connection.execute(
+                        "update edit_log set removed = ?2, added = ?3 where id = ?1",
+                        (id, serde_json::to_string(&new_removed)?, serde_json::to_string(&new_added)?),
+                    )?;
+                }
+                changed += 1;
+            }
+            Ok(())
+        })?;
+        Ok(changed)
+    }
+
+    /// Attributes the intervals of a sample to `project` and returns how many changed.
+    pub fn set_sample_project(
+        &self,
+        window_name: &String,
+        title: &Option<String>,
+        project: Option<&String>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let updated = self.connection.execute(
+            "update activity set project = ?3 where window_name = ?1 and title is ?2 and project is not ?3",
+            (window_name, title, project),
+        )?;
+        Ok(updated)
+    }
+
+    /// Returns the changes to intervals recorded here that were not written to the change log
+    /// yet, each with the id to pass to `delete_exported_changes` once it is.
+    pub fn get_unexported_changes(&self) -> Result<Vec<(i64, Change)>, Box<dyn Error>> {
         let mut statement = self.connection.prepare(
-            "select min(time_from) from activity"
+            "select sync_change.id, sync_change.interval_rowid, activity.rowid, activity.window_name,
+                    activity.title, activity.project, activity.time_from, activity.time_to,
+                    activity.utc_offset, activity.manual
+                  from sync_change
+                  left join activity on activity.rowid = sync_change.interval_rowid and activity.device is null
+                  order by sync_change.id"
         )?;
-        let default_min_date = Local::now().date_naive();
         let result = statement.query_map([], |row| {
-            row.get::<_, i64>(0)
-                .map(|min_ts| DateTime::from_timestamp(min_ts, 0).unwrap())
-                .map(|date_time| date_time.date_naive())
+            let change = match row.get::<_, Option<i64>>(2)? {
+                Some(_) => Change::Interval(Interval {
+                    rowid: row.get(2)?,
+                    window_name: row.get(3)?,
+                    title: row.get(4)?,
+                    project: row.get(5)?,
+                    time_from: row.get(6)?,
+                    time_to: row.get(7)?,
+                    utc_offset: row.get(8)?,
+                    manual: row.get(9)?,
+                }),
+                None => Change::Deletion { deleted: row.get(1)? },
+            };
+            Ok((row.get(0)?, change))
         })?
-            .map(|row| { row.unwrap_or(default_min_date) })
-            .next()
-            .unwrap_or(default_min_date);
+            .collect::<Result<Vec<(i64, Change)>, _>>()?;
+        Ok(result)
+    }
+
+    /// Forgets the changes up to `last_id`, a change made since is kept even if it is to the same interval.
+    pub fn delete_exported_changes(&self, last_id: i64) -> Result<(), Box<dyn Error>> {
+        self.connection.execute("delete from sync_change where id <= ?1", [last_id])?;
+        Ok(())
+    }
+
+    /// Returns an interval recorded here.
+    pub fn get_interval(&self, rowid: i64) -> Result<Option<Interval>, Box<dyn Error>> {
+        let interval = self.connection.query_row(
+            "select rowid, window_name, title, project, time_from, time_to, utc_offset, manual
+                  from activity
+                  where rowid = ?1 and device is null",
+            [rowid],
+            Self::get_interval_from_row,
+        ).optional()?;
+        Ok(interval)
+    }
+
+    fn get_interval_from_row(row: &Row) -> rusqlite::Result<Interval> {
+        Ok(Interval {
+            rowid: row.get(0)?,
+            window_name: row.get(1)?,
+            title: row.get(2)?,
+            project: row.get(3)?,
+            time_from: row.get(4)?,
+            time_to: row.get(5)?,
+            utc_offset: row.get(6)?,
+            manual: row.get(7)?,
+        })
+    }
+
+    /// Returns the intervals recorded here that overlap the period, the latest first.
+    pub fn get_intervals(&self, period: Option<(i64, i64)>) -> Result<Vec<Interval>, Box<dyn Error>> {
+        let (from, to) = period.unwrap_or((0, i64::MAX));
+        let mut statement = self.connection.prepare(
+            "select rowid, window_name, title, project, time_from, time_to, utc_offset, manual
+                  from activity
+                  where device is null and time_from < ?2 and time_to > ?1
+                  order by time_from desc"
+        )?;
+        let result = statement.query_map((from, to), Self::get_interval_from_row)?
+            .collect::<Result<Vec<Interval>, _>>()?;
+        Ok(result)
+    }
+
+    /// Adds an interval entered by hand and returns its rowid.
+    pub fn add_interval(&self, interval: &Interval, calendar: &Calendar) -> Result<i64, Box<dyn Error>> {
+        let added = self.apply_edit("add", &[], &[Interval { rowid: 0, manual: true, ..interval.clone() }], calendar)?;
+        Ok(added[0].rowid)
+    }
+
+    /// Replaces the interval with the same rowid.
+    pub fn edit_interval(&self, interval: &Interval, calendar: &Calendar) -> Result<(), Box<dyn Error>> {
+        let old = self.get_editable_interval(interval.rowid)?;
+        self.apply_edit("edit", &[old], std::slice::from_ref(interval), calendar)?;
+        Ok(())
+    }
+
+    /// Cuts the interval in two at `at` and returns the rowid of the second part.
+    pub fn split_interval(&self, rowid: i64, at: i64, calendar: &Calendar) -> Result<i64, Box<dyn Error>> {
+        let old = self.get_editable_interval(rowid)?;
+        if at <= old.time_from || at >= old.time_to {
+            return Err("The interval can only be split within it".into());
+        }
+        let first = Interval { time_to: at, ..old.clone() };
+        let second = Interval { rowid: 0, time_from: at, ..old.clone() };
+        let added = self.apply_edit("split", &[old], &[first, second], calendar)?;
+        Ok(added[1].rowid)
+    }
+
+    pub fn delete_interval(&self, rowid: i64, calendar: &Calendar) -> Result<(), Box<dyn Error>> {
+        let old = self.get_editable_interval(rowid)?;
+        self.apply_edit("delete", &[old], &[], calendar)?;
+        Ok(())
+    }
+
+    /// Reverts the last edit that was not undone yet and returns what it was, None if there is none.
+    pub fn undo(&self, calendar: &Calendar) -> Result<Option<String>, Box<dyn Error>> {
+        let last: Option<(i64, String, String, String)> = self.connection.query_row(
+            "select id, action, removed, added from edit_log order by id desc limit 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).optional()?;
+        let Some((id, action, removed, added)) = last else {
+            return Ok(None);
+        };
+        let removed: Vec<Interval> = serde_json::from_str(&removed)?;
+        let added: Vec<Interval> = serde_json::from_str(&added)?;
+        self.in_transaction(|| {
+            for interval in &added {
+                self.connection.execute("delete from activity where rowid = ?1", [interval.rowid])?;
+            }
+            self.connection.execute("delete from edit_log where id = ?1", [id])?;
+            for interval in &removed {
+                self.restore_interval(interval)?;
+            }
+            self.refresh_summary(removed.iter().chain(&added), calendar)
+        })?;
+        Ok(Some(action))
+    }
+
+    /// Intervals of other devices are only changed through their change logs, and the one the
+    /// tracker has open would be overwritten by it.
+    fn get_editable_interval(&self, rowid: i64) -> Result<Interval, Box<dyn Error>> {
+        let interval = self.get_interval(rowid)?
+            .ok_or(format!("There is no interval {rowid} recorded on this device"))?;
+        if let Some(state) = self.get_tracker_state()? {
+            if state.is_running(Local::now().timestamp_millis()) && state.open_rowid == Some(rowid) {
+                return Err("The interval is still being recorded".into());
+            }
+        }
+        Ok(interval)
+    }
+
+    /// Removes and adds intervals recorded here in one go, as long as the added ones do not
+    /// overlap any other, and logs the change. Added intervals with a rowid of 0 get a new one.
+    /// The interval the tracker has open only has the end of its last flush written, it is
+    /// checked with the end it has by now.
+    fn apply_edit(
+        &self,
+        action: &str,
+        removed: &[Interval],
+        added: &[Interval],
+        calendar: &Calendar,
+    ) -> Result<Vec<Interval>, Box<dyn Error>> {
+        let open = self.get_tracker_state()?
+            .and_then(|state| state.get_open_interval(Local::now().timestamp_millis()));
+        let (open_rowid, open_until) = (open.map(|(rowid, _)| rowid), open.map_or(0, |(_, until)| until));
+        self.in_transaction(|| {
+            for interval in removed {
+                self.connection.execute("delete from activity where rowid = ?1", [interval.rowid])?;
+            }
+            let mut inserted = Vec::with_capacity(added.len());
+            for interval in added {
+                if interval.time_from >= interval.time_to {
+                    return Err("An interval has to end after it starts".into());
+                }
+                let overlapping: i64 = self.connection.query_row(
+                    "select count(*) from activity
+                         where device is null and time_from < ?2
+                         and case when rowid = ?3 then max(time_to, ?4) else time_to end > ?1",
+                    (interval.time_from, interval.time_to, open_rowid, open_until),
+                    |row| row.get(0),
+                )?;
+                if overlapping > 0 {
+                    return Err("The interval overlaps another one".into());
+                }
+                let rowid = self.insert_interval(interval)?;
+                inserted.push(Interval { rowid, ..interval.clone() });
+            }
+            self.connection.execute(
+                "insert into edit_log (time, action, removed, added) values (?1, ?2, ?3, ?4)",
+                (
+                    Local::now().timestamp_millis(),
+                    action,
+                    serde_json::to_string(removed)?,
+                    serde_json::to_string(&inserted)?,
+                ),
+            )?;
+            self.refresh_summary(removed.iter().chain(&inserted), calendar)?;
+            Ok(inserted)
+        })
+    }
+
+    /// Inserts an interval removed by an edit again. Edits logged before rowids were kept from
+    /// being reused may name one that was given to another interval since, the interval then
+    /// gets a new one everywhere.
+    fn restore_interval(&self, interval: &Interval) -> Result<(), Box<dyn Error>> {
+        let is_taken = self.connection.query_row(
+            "select 1 from activity where rowid = ?1",
+            [interval.rowid],
+            |_| Ok(()),
+        ).optional()?.is_some();
+        if !is_taken {
+            self.insert_interval(interval)?;
+            return Ok(());
+        }
+        let rowid = self.insert_interval(&Interval { rowid: 0, ..interval.clone() })?;
+        let mut statement = self.connection.prepare("select id, removed, added from edit_log")?;
+        let entries = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(i64, String, String)>, _>>()?;
+        for (id, removed, added) in entries {
+            let renumber = |intervals: String| -> Result<String, Box<dyn Error>> {
+                let mut intervals: Vec<Interval> = serde_json::from_str(&intervals)?;
+                for logged in intervals.iter_mut().filter(|logged| logged.rowid == interval.rowid) {
+                    logged.rowid = rowid;
+                }
+                Ok(serde_json::to_string(&intervals)?)
+            };
+            self.connection.execute(
+                "update edit_log set removed = ?2, added = ?3 where id = ?1",
+                (id, renumber(removed)?, renumber(added)?),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Inserts an interval recorded here, with its own rowid unless it is 0.
+    fn insert_interval(&self, interval: &Interval) -> Result<i64, Box<dyn Error>> {
+        self.connection.execute(
+            "insert into activity (rowid, window_name, title, project, time_from, time_to, utc_offset, manual)
+                 values (nullif(?1, 0), ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                interval.rowid,
+                &interval.window_name,
+                &interval.title,
+                &interval.project,
+                interval.time_from,
+                interval.time_to,
+                interval.utc_offset.unwrap_or_else(|| local_offset_at(interval.time_from)),
+                interval.manual,
+            ),
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Sums up the days the intervals are in again, runs of consecutive days at once.
+    pub fn refresh_summary<'a>(
+        &self,
+        intervals: impl Iterator<Item = &'a Interval>,
+        calendar: &Calendar,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut days = BTreeSet::new();
+        for interval in intervals {
+            let mut day = calendar.date_of_millis(interval.time_from, interval.utc_offset);
+            let last_day = calendar.date_of_millis(interval.time_to, interval.utc_offset);
+            while day <= last_day {
+                days.insert(day);
+                day = day.succ_opt().ok_or("The interval ends after the latest supported date")?;
+            }
+        }
+        let mut runs: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+        for day in days {
+            match runs.last_mut() {
+                Some((_, last_day)) if last_day.succ_opt() == Some(day) => *last_day = day,
+                _ => runs.push((day, day)),
+            }
+        }
+        for (first_day, last_day) in runs {
+            self.resummarize(first_day, last_day, calendar)?;
+        }
+        Ok(())
+    }
+
+    /// Sums up the intervals of the days from `first_day` to `last_day` again.
+    fn resummarize(&self, first_day: NaiveDate, last_day: NaiveDate, calendar: &Calendar) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "delete from daily_summary where day >= ?1 and day <= ?2 and not pruned",
+            (first_day, last_day),
+        )?;
+        // every interval on these days is within this period, whatever offset it has
+        let period = calendar.period_in_any_offset(first_day, last_day);
+        self.summarize(Some(period), Some((first_day, last_day)), false, calendar)
+    }
+
+    /// Returns how many bytes of the device's change log have been merged.
+    pub fn get_sync_import(&self, device: &String) -> Result<u64, Box<dyn Error>> {
+        let position = self.connection.query_row(
+            "select position from sync_import where device = ?1",
+            [device],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(position.unwrap_or(0))
+    }
+
+    pub fn save_sync_import(&self, device: &String, position: u64) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "insert or replace into sync_import (device, position) values (?1, ?2)",
+            (device, position),
+        )?;
+        Ok(())
+    }
+
+    /// Applies a change to an interval recorded on another device and returns the versions of
+    /// the interval it replaced and added, whose days have to be summed up again. The change
+    /// replaces what was merged before, so a change log can be read from the start any number
+    /// of times.
+    pub fn merge_interval(&self, device: &String, change: &Change) -> Result<Vec<Interval>, Box<dyn Error>> {
+        let source_rowid = match change {
+            Change::Interval(interval) => interval.rowid,
+            Change::Deletion { deleted } => *deleted,
+        };
+        let merged = self.connection.query_row(
+            "select source_rowid, window_name, title, project, time_from, time_to, utc_offset, manual
+                  from activity
+                  where device = ?1 and source_rowid = ?2",
+            (device, source_rowid),
+            Self::get_interval_from_row,
+        ).optional()?;
+        match change {
+            Change::Interval(interval) => {
+                self.connection.execute(
+                    "insert into activity (window_name, title, project, time_from, time_to, utc_offset, manual, device, source_rowid)
+                         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                         on conflict (device, source_rowid) do update
+                         set window_name = excluded.window_name, title = excluded.title, project = excluded.project,
+                             time_from = excluded.time_from, time_to = excluded.time_to,
+                             utc_offset = excluded.utc_offset, manual = excluded.manual",
+                    (
+                        &interval.window_name,
+                        &interval.title,
+                        &interval.project,
+                        interval.time_from,
+                        interval.time_to,
+                        // older versions did not store offsets, they are read as local time like here
+                        interval.utc_offset.unwrap_or_else(|| local_offset_at(interval.time_from)),
+                        interval.manual,
+                        device,
+                        interval.rowid,
+                    ),
+                )?;
+            }
+            Change::Deletion { deleted } => {
+                self.connection.execute(
+                    "delete from activity where device = ?1 and source_rowid = ?2",
+                    (device, deleted),
+                )?;
+            }
+        }
+        let mut touched: Vec<Interval> = merged.into_iter().collect();
+        if let Change::Interval(interval) = change {
+            touched.push(interval.clone());
+        }
+        Ok(touched)
+    }
+
+    /// Returns the names of the other devices whose intervals have been merged.
+    pub fn get_devices(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "select distinct device from activity where device is not null order by device"
+        )?;
+        let result = statement.query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(result)
+    }
+
+    /// Returns the category of every app that has one.
+    pub fn get_categories(&self) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "select window_name, category from app where category is not null"
+        )?;
+        let result = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<String, String>, _>>()?;
+        Ok(result)
+    }
+
+    /// Returns the color of every app that has one.
+    pub fn get_app_colors(&self) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "select window_name, color from app where color is not null"
+        )?;
+        let result = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<String, String>, _>>()?;
         Ok(result)
     }
+
+    pub fn set_app_color(&self, window_name: &String, color: Option<&String>) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "insert into app (window_name, color) values (?1, ?2)
+                 on conflict (window_name) do update set color = excluded.color",
+            (window_name, color),
+        )?;
+        Ok(())
+    }
+
+    pub fn set_category(&self, window_name: &String, category: Option<&String>) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "insert into app (window_name, category) values (?1, ?2)
+                 on conflict (window_name) do update set category = excluded.category",
+            (window_name, category),
+        )?;
+        Ok(())
+    }
 }
 
-pub struct WindowStat {
+/// A recorded interval as it is exchanged with other devices.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Interval {
+    pub rowid: i64,
     pub window_name: String,
-    pub seconds: u32,
+    pub title: Option<String>,
+    /// Missing from the change logs of versions without projects.
+    #[serde(default)]
+    pub project: Option<String>,
+    pub time_from: i64,
+    pub time_to: i64,
+    pub utc_offset: Option<i32>,
+    /// Entered by hand rather than recorded by the tracker.
+    #[serde(default)]
+    pub manual: bool,
+}
+
+/// A line of a change log.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum Change {
+    /// The interval with the rowid was deleted.
+    Deletion { deleted: i64 },
+    /// The interval was added or changed.
+    Interval(Interval),
+}
+
+/// How often the tracker writes its heartbeat at least while it runs.
+pub const HEARTBEAT_INTERVAL_MILLIS: i64 = 60_000;
+
+/// What the tracker leaves behind to tell on the next start whether it was shut down properly.
+pub struct TrackerState {
+    pub heartbeat: i64,
+    pub open_rowid: Option<i64>,
+    pub clean_shutdown: bool,
+}
+
+impl TrackerState {
+    /// Whether the tracker is still running at `now`, judged by how recent its heartbeat is.
+    pub fn is_running(&self, now: i64) -> bool {
+        !self.clean_shutdown && now - self.heartbeat < 2 * HEARTBEAT_INTERVAL_MILLIS
+    }
+
+    /// The rowid of the interval left open and when it ends: at `now` while the tracker is
+    /// running, at the heartbeat after a crash, which is where the next start will end it.
+    pub fn get_open_interval(&self, now: i64) -> Option<(i64, i64)> {
+        if self.clean_shutdown {
+            return None;
+        }
+        let until = if self.is_running(now) { now } else { self.heartbeat };
+        self.open_rowid.map(|rowid| (rowid, until))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DowntimeKind {
+    /// The tracker was not running.
+    Offline,
+    /// The session was locked.
+    Lock,
+    /// The system was suspended or hibernated.
+    Sleep,
+}
+
+impl DowntimeKind {
+    fn as_str(&self) -> &str {
+        match self {
+            DowntimeKind::Offline => "offline",
+            DowntimeKind::Lock => "lock",
+            DowntimeKind::Sleep => "sleep",
+        }
+    }
+
+    fn parse(value: &str) -> Option<DowntimeKind> {
+        match value {
+            "offline" => Some(DowntimeKind::Offline),
+            "lock" => Some(DowntimeKind::Lock),
+            "sleep" => Some(DowntimeKind::Sleep),
+            _ => None,
+        }
+    }
+
+    pub fn as_title(&self) -> &str {
+        match self {
+            DowntimeKind::Offline => "Offline",
+            DowntimeKind::Lock => "Locked",
+            DowntimeKind::Sleep => "Asleep",
+        }
+    }
+}
+
+pub struct Downtime {
+    pub kind: DowntimeKind,
+    pub millis: i64,
 }
 
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::Days;
+    use std::time::{Duration, Instant};
+
+    /// Adds `count` one-minute intervals of 50 apps, one every `step` milliseconds from `time_from`.
+    pub(crate) fn generate_intervals(db: &Db, count: i64, time_from: i64, step: i64) {
+        db.connection.execute(
+            "with recursive n(i) as (select 0 union all select i + 1 from n where i < ?1 - 1)
+                 insert into activity (window_name, title, time_from, time_to, utc_offset)
+                 select 'app' || (i % 50) || '.exe', 'title ' || (i % 1000), ?2 + i * ?3, ?2 + i * ?3 + 60000, 0
+                 from n",
+            (count, time_from, step),
+        ).unwrap();
+    }
+
+    fn interval(time_from: i64, time_to: i64) -> Interval {
+        Interval {
+            rowid: 0,
+            window_name: "code.exe".to_string(),
+            title: None,
+            project: None,
+            time_from,
+            time_to,
+            utc_offset: Some(0),
+            manual: true,
+        }
+    }
+
+    /// An interval the tracker opened ten minutes ago and last flushed a minute later.
+    fn open_interval(db: &Db, now: i64) -> i64 {
+        let rowid = db.insert(&"firefox.exe".to_string(), &"Docs".to_string(), None, now - 600_000, 0).unwrap();
+        db.update_time_to(rowid, now - 540_000).unwrap();
+        rowid
+    }
+
+    #[test]
+    fn manual_intervals_may_not_overlap_the_open_one() {
+        let db = Db::open_in_memory().unwrap();
+        let calendar = Calendar::default();
+        let now = Local::now().timestamp_millis();
+        let rowid = open_interval(&db, now);
+        db.save_tracker_state(&TrackerState { heartbeat: now - 30_000, open_rowid: Some(rowid), clean_shutdown: false }).unwrap();
+        // the open interval runs until now while the tracker is running
+        assert!(db.add_interval(&interval(now - 300_000, now - 240_000), &calendar).is_err());
+        assert!(db.add_interval(&interval(now - 1_200_000, now - 600_000), &calendar).is_ok());
+        assert!(db.edit_interval(&Interval { rowid, ..interval(now - 600_000, now - 540_000) }, &calendar).is_err());
+        // after a crash it runs until the heartbeat
+        db.save_tracker_state(&TrackerState { heartbeat: now - 300_000, open_rowid: Some(rowid), clean_shutdown: false }).unwrap();
+        assert!(db.add_interval(&interval(now - 360_000, now - 330_000), &calendar).is_err());
+        assert!(db.add_interval(&interval(now - 300_000, now - 240_000), &calendar).is_ok());
+        // after a clean shutdown it ends where it was written
+        db.save_tracker_state(&TrackerState { heartbeat: now - 300_000, open_rowid: None, clean_shutdown: true }).unwrap();
+        assert!(db.add_interval(&interval(now - 540_000, now - 480_000), &calendar).is_ok());
+        assert!(db.add_interval(&interval(now - 560_000, now - 550_000), &calendar).is_err());
+    }
+
+    fn summary_of(db: &Db, day: NaiveDate) -> i64 {
+        db.connection.query_row(
+            "select coalesce(sum(time), 0) from daily_summary where day = ?1",
+            [day],
+            |row| row.get(0),
+        ).unwrap()
+    }
+
+    #[test]
+    fn rebuild_keeps_the_pruned_part_of_a_day() {
+        let db = Db::open_in_memory().unwrap();
+        let calendar = Calendar::default();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let day_start = calendar.day_start_millis(day, 0);
+        let next_day_start = calendar.day_start_millis(day + Days::new(1), 0);
+        db.add_interval(&interval(day_start + 3_600_000, day_start + 7_200_000), &calendar).unwrap();
+        db.add_interval(&interval(next_day_start + 3_600_000, next_day_start + 7_200_000), &calendar).unwrap();
+        db.prune(next_day_start, true, &calendar).unwrap();
+        // e.g. merged from another device after the prune
+        db.add_interval(&interval(day_start + 36_000_000, day_start + 37_800_000), &calendar).unwrap();
+        assert_eq!(summary_of(&db, day), 5_400_000);
+        db.rebuild_summary(&calendar).unwrap();
+        assert_eq!(summary_of(&db, day), 5_400_000);
+        assert_eq!(summary_of(&db, day + Days::new(1)), 3_600_000);
+    }
+
+    /// Time per app and day, sorted.
+    fn stats_per_day(db: &Db, calendar: &Calendar) -> Vec<(String, Option<NaiveDate>, i64)> {
+        let query = StatsQuery::new().group_by(GroupBy::App).per_day().calendar(*calendar);
+        let mut stats: Vec<(String, Option<NaiveDate>, i64)> = db.get_stats(&query).unwrap().into_iter()
+            .map(|stat| (stat.key.to_string(), stat.day, stat.millis))
+            .collect();
+        stats.sort();
+        stats
+    }
+
+    /// Ten days of intervals from `first_day`, one of which runs across the start of the sixth
+    /// day, and returns the start of that day.
+    fn prepare_prune(db: &Db, calendar: &Calendar, first_day: NaiveDate) -> i64 {
+        generate_intervals(db, 10 * 24 * 4, calendar.day_start_millis(first_day, 0) + 120_000, 15 * 60_000);
+        let cut = calendar.day_start_millis(first_day + Days::new(5), 0);
+        db.add_interval(&interval(cut - 300_000, cut + 60_000), calendar).unwrap();
+        db.rebuild_summary(calendar).unwrap();
+        cut
+    }
+
+    #[test]
+    fn rollup_keeps_the_time_of_pruned_days() {
+        let db = Db::open_in_memory().unwrap();
+        let calendar = Calendar::default();
+        let first_day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let cut = prepare_prune(&db, &calendar, first_day);
+        let before = stats_per_day(&db, &calendar);
+
+        assert_eq!(db.prune(cut, true, &calendar).unwrap(), 5 * 24 * 4);
+        assert_eq!(stats_per_day(&db, &calendar), before);
+        let intervals = db.get_intervals(None).unwrap();
+        assert!(intervals.iter().all(|interval| interval.time_from >= cut));
+        assert!(intervals.iter().any(|interval| interval.time_from == cut && interval.time_to == cut + 60_000));
+        // and after the summary is built again
+        db.rebuild_summary(&calendar).unwrap();
+        assert_eq!(stats_per_day(&db, &calendar), before);
+    }
+
+    #[test]
+    fn prune_without_rollup_deletes_the_time_of_pruned_days() {
+        let db = Db::open_in_memory().unwrap();
+        let calendar = Calendar::default();
+        let first_day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let cut = prepare_prune(&db, &calendar, first_day);
+        let kept: Vec<_> = stats_per_day(&db, &calendar).into_iter()
+            .filter(|(_, day, _)| *day >= Some(first_day + Days::new(5)))
+            .collect();
+
+        db.prune(cut, false, &calendar).unwrap();
+        assert_eq!(stats_per_day(&db, &calendar), kept);
+        db.rebuild_summary(&calendar).unwrap();
+        assert_eq!(stats_per_day(&db, &calendar), kept);
+    }
+
+    /// Writes what the tracker does in `ticks` ticks of five seconds, switching to another app
+    /// every minute, and returns the time taken per tick.
+    fn time_ticks(db: &Db, time_from: i64, ticks: i64) -> Duration {
+        let day = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        let started = Instant::now();
+        let mut rowid = 0;
+        for tick in 0..ticks {
+            let now = time_from + tick * 5_000;
+            let window_name = format!("app{}.exe", tick / 12 % 50);
+            db.in_transaction(|| {
+                if tick % 12 == 0 {
+                    rowid = db.insert(&window_name, &"title".to_string(), None, now, 0)?;
+                } else {
+                    db.update_time_to(rowid, now)?;
+                    db.add_to_summary(&window_name, None, None, day, 5_000)?;
+                }
+                Ok(())
+            }).unwrap();
+            if tick % 12 == 0 {
+                db.save_tracker_state(&TrackerState { heartbeat: now, open_rowid: Some(rowid), clean_shutdown: false }).unwrap();
+            }
+        }
+        started.elapsed() / ticks as u32
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`, it writes a database of a few
+    /// hundred megabytes to the temp directory.
+    #[test]
+    #[ignore]
+    fn tick_cost_does_not_grow_with_history() {
+        let path = env::temp_dir().join(format!("app-time-ticks-{}.sqlite", std::process::id()));
+        let db = Db::open(&path).unwrap();
+        let time_from = 1_500_000_000_000;
+        let empty = time_ticks(&db, time_from, 2_000);
+        let started = Instant::now();
+        db.in_transaction(|| {
+            generate_intervals(&db, 3_000_000, time_from + 86_400_000, 60_000);
+            Ok(())
+        }).unwrap();
+        println!("3M intervals generated in {:?}", started.elapsed());
+        let full = time_ticks(&db, time_from + 4_000 * 86_400_000, 2_000);
+        println!("per tick: {empty:?} with an empty database, {full:?} with 3M intervals");
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+        assert!(full < empty * 3, "{full:?} per tick with 3M intervals, {empty:?} without");
+    }
+}
@@ -0,0 +1,401 @@
+use crate::datetime::{format_duration, format_local_time, Calendar};
+use crate::db::Db;
+use crate::query::{GroupBy, StatKey, StatsQuery};
+use crate::theme::{get_app_color, to_css, PALETTE};
+use chrono::{Days, NaiveDate};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FILE_NAME_FORMAT: &str = "digest-%Y-%m-%d.html";
+const TOP_APPS: usize = 10;
+const FOCUS_SESSIONS: usize = 5;
+/// Intervals of the same app this close to each other belong to one focus session.
+const MAX_FOCUS_GAP_MILLIS: i64 = 2 * 60_000;
+const STYLE: &str = "body{font-family:system-ui,sans-serif;color:#222;max-width:760px;margin:2em auto;padding:0 1em}
+h1{font-size:1.5em;margin-bottom:.2em}h2{font-size:1.1em;margin-top:2em;border-bottom:1px solid #ddd}
+.muted{color:#777}table{border-collapse:collapse;width:100%}td,th{padding:.3em .5em;text-align:left}
+td.num,th.num{text-align:right;white-space:nowrap}.bar{background:#4e79a7;height:.8em;border-radius:2px}
+.up{color:#2a7d2a}.down{color:#b03030}.legend span{display:inline-block;width:.8em;height:.8em;margin:0 .3em 0 1em}";
+
+pub struct AppTotal {
+    pub name: String,
+    pub millis: i64,
+    /// Time spent in the app the week before.
+    pub previous_millis: i64,
+    /// CSS color of the app's bar.
+    pub color: String,
+}
+
+/// Consecutive intervals of one app, as long as nothing else came in between.
+pub struct FocusSession {
+    pub app: String,
+    pub time_from: i64,
+    pub time_to: i64,
+}
+
+impl FocusSession {
+    pub fn get_millis(&self) -> i64 {
+        self.time_to - self.time_from
+    }
+}
+
+/// A summary of a week, compared with the week before, rendered as a single HTML file that
+/// needs nothing else to be viewed.
+pub struct Digest {
+    pub days: Vec<NaiveDate>,
+    /// Time per day of `days`.
+    pub day_totals: Vec<i64>,
+    pub previous_total: i64,
+    /// The apps the most time was spent in, most first.
+    pub apps: Vec<AppTotal>,
+    pub categories: Vec<(String, i64)>,
+    /// The longest sessions recorded on this device, longest first.
+    pub focus_sessions: Vec<FocusSession>,
+}
+
+impl Digest {
+    pub fn get_default_dir() -> PathBuf {
+        let mut dir = Db::get_data_dir();
+        dir.push("digests");
+        dir
+    }
+
+    pub fn get_file_name(first_day: NaiveDate) -> String {
+        first_day.format(FILE_NAME_FORMAT).to_string()
+    }
+
+    /// Loads the week starting with `first_day`.
+    pub fn load(db: &Db, calendar: &Calendar, first_day: NaiveDate) -> Result<Digest, Box<dyn Error>> {
+        let days: Vec<NaiveDate> = first_day.iter_days().take(7).collect();
+        let period = calendar.period(first_day, first_day + Days::new(6));
+        let previous_period = calendar.period(first_day - Days::new(7), first_day - Days::new(1));
+        let query = StatsQuery::new()
+            .period(Some(period))
+            .calendar(*calendar);
+        let apps_per_day = db.get_stats(&query.clone().group_by(GroupBy::App).per_day())?;
+        let previous_apps = db.get_stats(&query.clone().period(Some(previous_period)).group_by(GroupBy::App))?;
+        let categories = db.get_stats(&query.group_by(GroupBy::Category))?;
+
+        let day_totals = days.iter()
+            .map(|day| apps_per_day.iter().filter(|stat| stat.day == Some(*day)).map(|stat| stat.millis).sum())
+            .collect();
+        let previous_millis: HashMap<String, i64> = previous_apps.iter()
+            .map(|stat| (stat.key.to_string(), stat.millis))
+            .collect();
+        let app_colors = db.get_app_colors()?;
+        let mut apps: Vec<AppTotal> = Vec::new();
+        for stat in &apps_per_day {
+            let StatKey::App(name) = &stat.key else {
+                continue;
+            };
+            match apps.iter_mut().find(|app| app.name == *name) {
+                Some(app) => app.millis += stat.millis,
+                None => apps.push(AppTotal {
+                    name: name.clone(),
+                    millis: stat.millis,
+                    previous_millis: previous_millis.get(name).copied().unwrap_or(0),
+                    color: to_css(get_app_color(name, &app_colors)),
+                }),
+            }
+        }
+        apps.sort_by(|a, b| b.millis.cmp(&a.millis).then_with(|| a.name.cmp(&b.name)));
+        apps.truncate(TOP_APPS);
+
+        Ok(Digest {
+            days,
+            day_totals,
+            previous_total: previous_apps.iter().map(|stat| stat.millis).sum(),
+            apps,
+            categories: categories.iter().map(|stat| (stat.key.to_string(), stat.millis)).collect(),
+            focus_sessions: Self::get_focus_sessions(db, period)?,
+        })
+    }
+
+    /// Writes the digest of the last week complete by `today` into `dir`, unless it is there
+    /// already or nothing was recorded that week. Returns the new digest if one was written.
+    pub fn create_weekly(db: &Db, calendar: &Calendar, dir: &Path, today: NaiveDate) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let first_day = calendar.week_of(today) - Days::new(7);
+        let path = dir.join(Self::get_file_name(first_day));
+        if path.exists() {
+            return Ok(None);
+        }
+        let digest = Self::load(db, calendar, first_day)?;
+        if digest.get_total() == 0 {
+            return Ok(None);
+        }
+        digest.save(&path)?;
+        Ok(Some(path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_html())?;
+        Ok(())
+    }
+
+    pub fn get_total(&self) -> i64 {
+        self.day_totals.iter().sum()
+    }
+
+    fn get_focus_sessions(db: &Db, (from, to): (i64, i64)) -> Result<Vec<FocusSession>, Box<dyn Error>> {
+        let mut sessions: Vec<FocusSession> = Vec::new();
+        for interval in db.get_intervals(Some((from, to)))?.into_iter().rev() {
+            let (time_from, time_to) = (interval.time_from.max(from), interval.time_to.min(to));
+            match sessions.last_mut() {
+                Some(session) if session.app == interval.window_name
+                    && time_from - session.time_to <= MAX_FOCUS_GAP_MILLIS => {
+                    session.time_to = session.time_to.max(time_to);
+                }
+                _ => sessions.push(FocusSession { app: interval.window_name, time_from, time_to }),
+            }
+        }
+        sessions.sort_by_key(|session| -session.get_millis());
+        sessions.truncate(FOCUS_SESSIONS);
+        Ok(sessions)
+    }
+
+    pub fn to_html(&self) -> String {
+        let first_day = self.days.first().copied().unwrap_or_default();
+        let last_day = self.days.last().copied().unwrap_or_default();
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Week of {}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n",
+            first_day.format("%Y-%m-%d"),
+        );
+        html.push_str(&format!(
+            "<h1>Week of {} to {}</h1>\n<p>{} in total",
+            first_day.format("%d.%m.%Y"),
+            last_day.format("%d.%m.%Y"),
+            format_duration(self.get_total()),
+        ));
+        if self.previous_total > 0 {
+            html.push_str(&format!(
+                ", {} on the previous week <span class=\"muted\">({})</span>",
+                format_change(self.get_total(), self.previous_total),
+                format_duration(self.previous_total),
+            ));
+        }
+        html.push_str("</p>\n");
+        html.push_str("<h2>Per day</h2>\n");
+        html.push_str(&self.days_svg());
+        html.push_str("<h2>Top apps</h2>\n");
+        html.push_str(&self.apps_table());
+        html.push_str("<h2>Categories</h2>\n");
+        html.push_str(&self.categories_svg());
+        html.push_str("<h2>Longest focus sessions</h2>\n");
+        html.push_str(&self.focus_table());
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// A column per day, scaled to the longest day.
+    fn days_svg(&self) -> String {
+        let max = self.day_totals.iter().copied().max().unwrap_or(0).max(1);
+        let (width, height) = (100, 160);
+        let mut svg = format!("<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\" font-size=\"12\">\n", width * 7, height + 40);
+        for (i, (day, &millis)) in self.days.iter().zip(&self.day_totals).enumerate() {
+            let bar = (millis as f64 / max as f64 * height as f64).round() as i64;
+            let x = i as i64 * width;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{bar}\" fill=\"{}\"><title>{}</title></rect>\n",
+                x + 20, height - bar + 20, width - 40, to_css(PALETTE[0]), format_duration(millis),
+            ));
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"#777\">{}</text>\n",
+                x + width / 2, height - bar + 14, format_hours(millis),
+                x + width / 2, height + 36, day.format("%a %d.%m"),
+            ));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn apps_table(&self) -> String {
+        if self.apps.is_empty() {
+            return "<p class=\"muted\">Nothing recorded</p>\n".to_string();
+        }
+        let max = self.apps[0].millis.max(1);
+        let mut table = "<table>\n<tr><th>App</th><th></th><th class=\"num\">Time</th><th class=\"num\">Previous week</th><th class=\"num\">Change</th></tr>\n".to_string();
+        for app in &self.apps {
+            table.push_str(&format!(
+                "<tr><td>{}</td><td style=\"width:35%\"><div class=\"bar\" style=\"width:{:.1}%;background:{}\"></div></td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+                escape_html(&app.name),
+                app.millis as f64 / max as f64 * 100.0,
+                app.color,
+                format_duration(app.millis),
+                format_duration(app.previous_millis),
+                format_change(app.millis, app.previous_millis),
+            ));
+        }
+        table.push_str("</table>\n");
+        table
+    }
+
+    /// A single bar split into the categories, with a legend below.
+    fn categories_svg(&self) -> String {
+        let total: i64 = self.categories.iter().map(|(_, millis)| millis).sum();
+        if total == 0 {
+            return "<p class=\"muted\">Nothing recorded</p>\n".to_string();
+        }
+        let width = 700.0;
+        let mut svg = format!("<svg width=\"{width}\" height=\"24\" xmlns=\"http://www.w3.org/2000/svg\">\n");
+        let mut legend = "<p class=\"legend\">".to_string();
+        let mut x = 0.0;
+        for (i, (name, millis)) in self.categories.iter().enumerate() {
+            let color = to_css(PALETTE[i % PALETTE.len()]);
+            let part = *millis as f64 / total as f64;
+            svg.push_str(&format!(
+                "<rect x=\"{x:.1}\" y=\"0\" width=\"{:.1}\" height=\"24\" fill=\"{color}\"><title>{}</title></rect>\n",
+                part * width,
+                escape_html(name),
+            ));
+            legend.push_str(&format!(
+                "<span style=\"background:{color}\"></span>{} {:.0}%",
+                escape_html(name),
+                part * 100.0,
+            ));
+            x += part * width;
+        }
+        svg.push_str("</svg>\n");
+        legend.push_str("</p>\n");
+        svg + &legend
+    }
+
+    fn focus_table(&self) -> String {
+        if self.focus_sessions.is_empty() {
+            return "<p class=\"muted\">Nothing recorded</p>\n".to_string();
+        }
+        let mut table = "<table>\n<tr><th>App</th><th>Started</th><th class=\"num\">Duration</th></tr>\n".to_string();
+        for session in &self.focus_sessions {
+            table.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>\n",
+                escape_html(&session.app),
+                format_local_time(session.time_from),
+                format_duration(session.get_millis()),
+            ));
+        }
+        table.push_str("</table>\n");
+        table
+    }
+}
+
+fn format_hours(millis: i64) -> String {
+    format!("{:.1}h", millis as f64 / 3_600_000.0)
+}
+
+/// The change on the week before, e.g. "+12%", marked up in green or red.
+fn format_change(millis: i64, previous_millis: i64) -> String {
+    if previous_millis == 0 {
+        return if millis == 0 { String::new() } else { "<span class=\"up\">new</span>".to_string() };
+    }
+    let change = ((millis - previous_millis) as f64 / previous_millis as f64 * 100.0).round();
+    let class = if change < 0.0 { "down" } else { "up" };
+    format!("<span class=\"{class}\">{change:+.0}%</span>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datetime::local_offset_at;
+    use crate::db::Interval;
+    use chrono::{Local, Weekday};
+    use tempfile::TempDir;
+
+    const MILLIS_PER_HOUR: i64 = 3_600_000;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    /// Records an hour of `app` at noon of the day of June 2024, in local time like the tracker.
+    fn record(db: &Db, app: &str, day: u32) {
+        let time_from = date(day).and_hms_opt(12, 0, 0).unwrap().and_local_timezone(Local).unwrap().timestamp_millis();
+        db.add_interval(&Interval {
+            rowid: 0,
+            window_name: app.to_string(),
+            title: None,
+            project: None,
+            time_from,
+            time_to: time_from + MILLIS_PER_HOUR,
+            utc_offset: Some(local_offset_at(time_from)),
+            manual: true,
+        }, &Calendar::default()).unwrap();
+    }
+
+    fn calendar(week_start: Weekday) -> Calendar {
+        Calendar { week_start, ..Calendar::default() }
+    }
+
+    #[test]
+    fn week_starts_on_the_week_start() {
+        let db = Db::open_in_memory().unwrap();
+        // Sunday and Sunday a week later
+        record(&db, "code.exe", 2);
+        record(&db, "code.exe", 9);
+        let dir = TempDir::new().unwrap();
+
+        // on Wednesday 12th
+        let path = Digest::create_weekly(&db, &calendar(Weekday::Sun), dir.path(), date(12)).unwrap();
+        assert_eq!(path, Some(dir.path().join("digest-2024-06-02.html")));
+        let path = Digest::create_weekly(&db, &calendar(Weekday::Mon), dir.path(), date(12)).unwrap();
+        assert_eq!(path, Some(dir.path().join("digest-2024-06-03.html")));
+        // the week starting on Sunday 9th is not over yet on that day
+        let path = Digest::create_weekly(&db, &calendar(Weekday::Sun), dir.path(), date(9)).unwrap();
+        assert_eq!(path, None);
+
+        let digest = Digest::load(&db, &calendar(Weekday::Sun), date(2)).unwrap();
+        assert_eq!(digest.days.first(), Some(&date(2)));
+        assert_eq!(digest.days.last(), Some(&date(8)));
+        assert_eq!(digest.day_totals, vec![MILLIS_PER_HOUR, 0, 0, 0, 0, 0, 0]);
+        let digest = Digest::load(&db, &calendar(Weekday::Mon), date(3)).unwrap();
+        assert_eq!(digest.day_totals, vec![0, 0, 0, 0, 0, 0, MILLIS_PER_HOUR]);
+        assert_eq!(digest.previous_total, MILLIS_PER_HOUR);
+    }
+
+    #[test]
+    fn digest_is_written_once_a_week() {
+        let db = Db::open_in_memory().unwrap();
+        let dir = TempDir::new().unwrap();
+        let calendar = Calendar::default();
+        // nothing recorded, so nothing to write
+        assert_eq!(Digest::create_weekly(&db, &calendar, dir.path(), date(12)).unwrap(), None);
+        assert!(!dir.path().join("digest-2024-06-03.html").exists());
+
+        record(&db, "code.exe", 4);
+        let path = Digest::create_weekly(&db, &calendar, dir.path(), date(12)).unwrap().unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        record(&db, "firefox.exe", 5);
+        for today in [date(12), date(16)] {
+            assert_eq!(Digest::create_weekly(&db, &calendar, dir.path(), today).unwrap(), None);
+        }
+        // left as it was written
+        assert_eq!(fs::read_to_string(&path).unwrap(), html);
+        // until the next week is over
+        record(&db, "code.exe", 11);
+        let path = Digest::create_weekly(&db, &calendar, dir.path(), date(17)).unwrap();
+        assert_eq!(path, Some(dir.path().join("digest-2024-06-10.html")));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let db = Db::open_in_memory().unwrap();
+        let app = "<script>alert(\"a & b\")</script>".to_string();
+        record(&db, &app, 4);
+        db.set_category(&app, Some(&"<b>Tom & Jerry</b>".to_string())).unwrap();
+        let html = Digest::load(&db, &Calendar::default(), date(3)).unwrap().to_html();
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("<td>&lt;script&gt;alert(&quot;a &amp; b&quot;)&lt;/script&gt;</td>"));
+        assert!(html.contains("&lt;b&gt;Tom &amp; Jerry&lt;/b&gt;"));
+    }
+}
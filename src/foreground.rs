@@ -0,0 +1,91 @@
+#[cfg(windows)]
+use {
+    std::cell::RefCell,
+    windows::Win32::Foundation::{HMODULE, HWND},
+    windows::Win32::UI::Accessibility::{SetWinEventHook, HWINEVENTHOOK},
+    windows::Win32::UI::WindowsAndMessaging::{EVENT_SYSTEM_FOREGROUND, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS},
+};
+#[cfg(target_os = "linux")]
+use {
+    std::error::Error,
+    std::thread,
+    x11rb::connection::Connection,
+    x11rb::protocol::xproto::{ChangeWindowAttributesAux, ConnectionExt, EventMask},
+    x11rb::protocol::Event,
+};
+
+#[cfg(windows)]
+thread_local! {
+    // the hook procedure gets no user data, the callback is kept for the thread it is called on
+    static CALLBACK: RefCell<Option<Box<dyn Fn()>>> = RefCell::new(None);
+}
+
+/// Reports every change of the foreground window as it happens.
+pub struct ForegroundListener;
+
+/// Like `SessionListener`, the hook is served by the thread's message loop, so it has to be
+/// installed on the thread running `Tray::handle_win_messages`.
+#[cfg(windows)]
+impl ForegroundListener {
+    /// If the hook cannot be installed nothing is reported and the tracker keeps relying on polling.
+    pub fn init(callback: impl Fn() + 'static) {
+        CALLBACK.with(|cell| *cell.borrow_mut() = Some(Box::new(callback)));
+        unsafe {
+            SetWinEventHook(
+                EVENT_SYSTEM_FOREGROUND,
+                EVENT_SYSTEM_FOREGROUND,
+                HMODULE::default(),
+                Some(Self::event_proc),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+            );
+        }
+    }
+
+    unsafe extern "system" fn event_proc(
+        _hook: HWINEVENTHOOK,
+        _event: u32,
+        _hwnd: HWND,
+        _id_object: i32,
+        _id_child: i32,
+        _id_event_thread: u32,
+        _event_time: u32,
+    ) {
+        CALLBACK.with(|cell| {
+            if let Some(callback) = cell.borrow().as_ref() {
+                callback();
+            }
+        });
+    }
+}
+
+/// On Linux the window manager announces the active window in the `_NET_ACTIVE_WINDOW`
+/// property of the root window, whose changes are followed on a thread of their own.
+#[cfg(target_os = "linux")]
+impl ForegroundListener {
+    /// Without an X server, e.g. under Wayland, nothing is reported and the tracker keeps
+    /// relying on polling.
+    pub fn init(callback: impl Fn() + Send + 'static) {
+        thread::spawn(move || {
+            if let Err(err) = Self::listen(callback) {
+                println!("Could not follow the active window: {err}");
+            }
+        });
+    }
+
+    fn listen(callback: impl Fn()) -> Result<(), Box<dyn Error>> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let root = connection.setup().roots[screen].root;
+        let active_window = connection.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+        let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        connection.change_window_attributes(root, &attributes)?.check()?;
+        loop {
+            if let Event::PropertyNotify(event) = connection.wait_for_event()? {
+                if event.atom == active_window {
+                    callback();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// What a key does in the UI.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Quit,
+    Help,
+    NextFocus,
+    PreviousFocus,
+    Left,
+    Right,
+    Up,
+    Down,
+    NextView,
+    DeviceFilter,
+    Baseline,
+    RangePreset,
+    Add,
+    Edit,
+    Split,
+    Delete,
+    Undo,
+    /// Jumps to the period tab with this index.
+    Tab(usize),
+}
+
+/// How many tabs can be jumped to.
+const TAB_ACTIONS: usize = 9;
+
+impl Action {
+    /// All actions in the order they are listed in the help.
+    pub fn all() -> Vec<Action> {
+        let mut actions = vec![
+            Action::Quit,
+            Action::Help,
+            Action::NextFocus,
+            Action::PreviousFocus,
+            Action::Left,
+            Action::Right,
+            Action::Up,
+            Action::Down,
+            Action::NextView,
+            Action::DeviceFilter,
+            Action::Baseline,
+            Action::RangePreset,
+            Action::Add,
+            Action::Edit,
+            Action::Split,
+            Action::Delete,
+            Action::Undo,
+        ];
+        actions.extend((0..TAB_ACTIONS).map(Action::Tab));
+        actions
+    }
+
+    /// The name the action is bound by in the `[keys]` section of the config.
+    pub fn as_name(&self) -> String {
+        let name = match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::NextFocus => "next_focus",
+            Action::PreviousFocus => "previous_focus",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::NextView => "next_view",
+            Action::DeviceFilter => "device_filter",
+            Action::Baseline => "baseline",
+            Action::RangePreset => "range_preset",
+            Action::Add => "add",
+            Action::Edit => "edit",
+            Action::Split => "split",
+            Action::Delete => "delete",
+            Action::Undo => "undo",
+            Action::Tab(index) => return format!("tab_{}", index + 1),
+        };
+        name.to_string()
+    }
+
+    pub fn describe(&self) -> String {
+        let description = match self {
+            Action::Quit => "Quit, or close what is open",
+            Action::Help => "Show or hide this help",
+            Action::NextFocus => "Focus the next of periods, dates and table",
+            Action::PreviousFocus => "Focus the previous of periods, dates and table",
+            Action::Left => "Previous period, date part or view",
+            Action::Right => "Next period, date part or view",
+            Action::Up => "Previous row, or a later date",
+            Action::Down => "Next row, or an earlier date",
+            Action::NextView => "Next view",
+            Action::DeviceFilter => "Cycle the devices counted",
+            Action::Baseline => "Cycle what the period is compared with",
+            Action::RangePreset => "Set the custom period to the next preset range",
+            Action::Add => "Add an interval",
+            Action::Edit => "Edit the selected interval",
+            Action::Split => "Split the selected interval",
+            Action::Delete => "Delete the selected interval",
+            Action::Undo => "Undo the last change to the intervals",
+            Action::Tab(index) => return format!("Go to period {}", index + 1),
+        };
+        description.to_string()
+    }
+
+    fn get_default_keys(&self) -> Vec<String> {
+        let keys: &[&str] = match self {
+            Action::Quit => &["q", "esc", "ctrl+c"],
+            Action::Help => &["?"],
+            Action::NextFocus => &["tab"],
+            Action::PreviousFocus => &["shift+tab"],
+            Action::Left => &["left"],
+            Action::Right => &["right"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::NextView => &["v"],
+            Action::DeviceFilter => &["d"],
+            Action::Baseline => &["c"],
+            Action::RangePreset => &["p"],
+            Action::Add => &["a"],
+            Action::Edit => &["e"],
+            Action::Split => &["s"],
+            Action::Delete => &["x", "delete"],
+            Action::Undo => &["u"],
+            Action::Tab(index) => return vec![(index + 1).to_string()],
+        };
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+}
+
+/// A key with the modifiers held down, written like "ctrl+c", "shift+tab" or "?".
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeyBinding {
+    pub name: String,
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn parse(value: &str) -> Option<KeyBinding> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = value.split('+').collect();
+        // "+" on its own, or at the end as in "ctrl++", is the key itself
+        let key = match parts.pop()? {
+            "" if parts.last() == Some(&"") => {
+                parts.pop();
+                "+"
+            }
+            key => key,
+        };
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "esc" => KeyCode::Esc,
+                "enter" => KeyCode::Enter,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                function => KeyCode::F(function.strip_prefix('f')?.parse().ok()?),
+            },
+        };
+        Some(KeyBinding { name: value.to_string(), code, modifiers: Self::without_implied_shift(code, modifiers) })
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        self.code == key.code && self.modifiers == Self::without_implied_shift(key.code, key.modifiers)
+    }
+
+    /// Shift is part of the character typed, "?" or "A", and of back tab, so it is not compared.
+    fn without_implied_shift(code: KeyCode, modifiers: KeyModifiers) -> KeyModifiers {
+        match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        }
+    }
+}
+
+/// The keys bound to each action. The defaults can be changed in the `[keys]` section of the
+/// config, where the keys listed for an action replace its default ones:
+///
+/// ```toml
+/// [keys]
+/// quit = ["q", "ctrl+c"]
+/// next_view = ["v", "f2"]
+/// ```
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeyBinding>)>,
+}
+
+impl Keymap {
+    pub fn new(overrides: &HashMap<String, Vec<String>>) -> Result<Keymap, Box<dyn Error>> {
+        let actions = Action::all();
+        for name in overrides.keys() {
+            if !actions.iter().any(|action| action.as_name() == *name) {
+                return Err(format!("Unknown action in [keys]: {name}").into());
+            }
+        }
+        let mut overridden: Vec<KeyBinding> = Vec::new();
+        let mut bindings = Vec::with_capacity(actions.len());
+        for action in actions {
+            let keys = match overrides.get(&action.as_name()) {
+                Some(keys) => {
+                    let keys = keys.iter()
+                        .map(|key| KeyBinding::parse(key).ok_or(format!("Unknown key in [keys]: {key}")))
+                        .collect::<Result<Vec<KeyBinding>, String>>()?;
+                    overridden.extend(keys.iter().cloned());
+                    keys
+                }
+                None => action.get_default_keys().iter().filter_map(|key| KeyBinding::parse(key)).collect(),
+            };
+            bindings.push((action, keys));
+        }
+        // a key bound in the config is taken away from the action it was bound to by default
+        for (action, keys) in &mut bindings {
+            if !overrides.contains_key(&action.as_name()) {
+                keys.retain(|key| !overridden.iter().any(|taken| taken.code == key.code && taken.modifiers == key.modifiers));
+            }
+        }
+        Ok(Keymap { bindings })
+    }
+
+    pub fn get_action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings.iter()
+            .find(|(_, keys)| keys.iter().any(|binding| binding.matches(key)))
+            .map(|(action, _)| *action)
+    }
+
+    /// Every action with the keys bound to it, for the help.
+    pub fn get_bindings(&self) -> &[(Action, Vec<KeyBinding>)] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn overrides(keys: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        keys.iter()
+            .map(|(action, keys)| (action.to_string(), keys.iter().map(|key| key.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn keys_are_parsed_with_their_modifiers() {
+        let parse = |value: &str| KeyBinding::parse(value).map(|binding| (binding.code, binding.modifiers));
+        assert_eq!(parse("q"), Some((KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert_eq!(parse("ctrl+c"), Some((KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert_eq!(parse("Ctrl+Alt+Delete"), Some((KeyCode::Delete, KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert_eq!(parse("shift+tab"), Some((KeyCode::BackTab, KeyModifiers::NONE)));
+        assert_eq!(parse("f12"), Some((KeyCode::F(12), KeyModifiers::NONE)));
+        assert_eq!(parse("space"), Some((KeyCode::Char(' '), KeyModifiers::NONE)));
+        assert_eq!(parse("+"), Some((KeyCode::Char('+'), KeyModifiers::NONE)));
+        assert_eq!(parse("ctrl++"), Some((KeyCode::Char('+'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn unknown_keys_are_not_parsed() {
+        for value in ["", "ctrl+", "hyper+a", "enterr", "fx", "f-1", "ctrl+shift"] {
+            assert_eq!(KeyBinding::parse(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn shift_of_characters_is_not_compared() {
+        let help = KeyBinding::parse("?").unwrap();
+        assert!(help.matches(&key(KeyCode::Char('?'), KeyModifiers::SHIFT)));
+        assert!(help.matches(&key(KeyCode::Char('?'), KeyModifiers::NONE)));
+        assert!(!help.matches(&key(KeyCode::Char('?'), KeyModifiers::CONTROL)));
+
+        let previous = KeyBinding::parse("shift+tab").unwrap();
+        assert!(previous.matches(&key(KeyCode::BackTab, KeyModifiers::SHIFT)));
+        assert!(!previous.matches(&key(KeyCode::Tab, KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn default_keys() {
+        let keymap = Keymap::new(&HashMap::new()).unwrap();
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)), Some(Action::Quit));
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('c'), KeyModifiers::NONE)), Some(Action::Baseline));
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('3'), KeyModifiers::NONE)), Some(Action::Tab(2)));
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('z'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn overridden_keys_replace_the_defaults() {
+        let keymap = Keymap::new(&overrides(&[("next_view", &["f2"])])).unwrap();
+        assert_eq!(keymap.get_action(&key(KeyCode::F(2), KeyModifiers::NONE)), Some(Action::NextView));
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('v'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn overridden_key_is_taken_from_its_default_action() {
+        let keymap = Keymap::new(&overrides(&[("delete", &["q"])])).unwrap();
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('q'), KeyModifiers::NONE)), Some(Action::Delete));
+        assert_eq!(keymap.get_action(&key(KeyCode::Esc, KeyModifiers::NONE)), Some(Action::Quit));
+        let (_, quit) = keymap.get_bindings().iter().find(|(action, _)| *action == Action::Quit).unwrap();
+        let names: Vec<&str> = quit.iter().map(|binding| binding.name.as_str()).collect();
+        assert_eq!(names, vec!["esc", "ctrl+c"]);
+        // the default keys of the action bound in the config are free again
+        assert_eq!(keymap.get_action(&key(KeyCode::Char('x'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_errors() {
+        let error = Keymap::new(&overrides(&[("jump", &["j"])])).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown action in [keys]: jump");
+        let error = Keymap::new(&overrides(&[("quit", &["q", "meta+q"])])).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown key in [keys]: meta+q");
+    }
+}
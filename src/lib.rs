@@ -1,7 +1,22 @@
+#[cfg(any(windows, target_os = "linux"))]
 pub mod window;
 pub mod db;
 pub mod datetime;
+#[cfg(windows)]
 pub mod tray;
 pub mod date_input_widget;
 pub mod date_range_input_widget;
-
+pub mod config;
+pub mod privacy;
+pub mod query;
+pub mod session;
+#[cfg(any(windows, target_os = "linux"))]
+pub mod foreground;
+pub mod sync;
+pub mod backup;
+pub mod project;
+pub mod timesheet;
+pub mod productivity;
+pub mod digest;
+pub mod keymap;
+pub mod theme;
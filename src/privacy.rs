@@ -0,0 +1,315 @@
+use crate::db::{Db, Interval};
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+
+const DEFAULT_PLACEHOLDER: &str = "Hidden";
+
+#[derive(Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyAction {
+    #[default]
+    Drop,
+    Anonymize,
+}
+
+/// A rule from the `[[privacy]]` section of the config. `exe` is a glob matched against
+/// the executable file name, `title` is a regex matched against the window title;
+/// when both are set, both have to match.
+#[derive(Deserialize, Default, Clone)]
+pub struct PrivacyRule {
+    pub exe: Option<String>,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub action: PrivacyAction,
+    pub placeholder: Option<String>,
+}
+
+/// What the tracker sees of the active window before it is stored.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Sample {
+    pub exe_name: String,
+    pub title: String,
+    pub display_name: String,
+}
+
+impl Sample {
+    /// The sample of a recorded window. The exe name is not stored, the window name stands in
+    /// for it, and for the title when there is none.
+    pub fn from_stored(window_name: &String, title: Option<&String>) -> Sample {
+        Sample {
+            exe_name: window_name.clone(),
+            title: title.unwrap_or(window_name).clone(),
+            display_name: window_name.clone(),
+        }
+    }
+}
+
+struct CompiledRule {
+    exe: Option<Pattern>,
+    title: Option<Regex>,
+    action: PrivacyAction,
+    placeholder: String,
+}
+
+impl CompiledRule {
+    fn matches(&self, exe_name: &str, title: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::default()
+        };
+        let exe_matches = match &self.exe {
+            Some(pattern) => pattern.matches_with(exe_name, options),
+            None => true,
+        };
+        let title_matches = match &self.title {
+            Some(regex) => regex.is_match(title),
+            None => true,
+        };
+        exe_matches && title_matches
+    }
+}
+
+pub struct PrivacyFilter {
+    rules: Vec<CompiledRule>,
+}
+
+impl PrivacyFilter {
+    pub fn new(rules: &[PrivacyRule]) -> Result<PrivacyFilter, Box<dyn Error>> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            if rule.exe.is_none() && rule.title.is_none() {
+                return Err("Privacy rule must have an exe or a title pattern".into());
+            }
+            compiled.push(CompiledRule {
+                exe: rule.exe.as_deref().map(Pattern::new).transpose()?,
+                title: rule.title.as_deref().map(Regex::new).transpose()?,
+                action: rule.action,
+                placeholder: rule.placeholder.clone()
+                    .unwrap_or_else(|| String::from(DEFAULT_PLACEHOLDER)),
+            });
+        }
+        Ok(PrivacyFilter { rules: compiled })
+    }
+
+    /// Returns the sample as it should be stored, or `None` if it has to be dropped.
+    /// The first matching rule wins, an anonymized sample gets the placeholder as its name and title.
+    pub fn apply(&self, sample: Sample) -> Option<Sample> {
+        match self.rules.iter().find(|rule| rule.matches(&sample.exe_name, &sample.title)) {
+            Some(rule) if rule.action == PrivacyAction::Drop => None,
+            Some(rule) => Some(Sample {
+                exe_name: sample.exe_name,
+                title: rule.placeholder.clone(),
+                display_name: rule.placeholder.clone(),
+            }),
+            None => Some(sample),
+        }
+    }
+
+    /// Returns the interval as it should be stored, or `None` if it has to be dropped. Used for
+    /// intervals entered by hand or recorded before the rules, whose window name stands in for
+    /// the exe name that is not stored. An anonymized interval loses its project, which may
+    /// give away what it was.
+    pub fn apply_to_interval(&self, interval: &Interval) -> Option<Interval> {
+        let sample = Sample::from_stored(&interval.window_name, interval.title.as_ref());
+        match self.apply(sample.clone())? {
+            new_sample if new_sample == sample => Some(interval.clone()),
+            new_sample => Some(Interval {
+                window_name: new_sample.display_name,
+                title: Some(new_sample.title),
+                project: None,
+                ..interval.clone()
+            }),
+        }
+    }
+
+    /// Applies the rules to what has been recorded, like `apply_to_interval` does to a single
+    /// interval, and to the summaries of pruned days and the edit log. Tells `report` what it
+    /// changed, returns whether any interval changed, which leaves the summary to be rebuilt.
+    pub fn purge(&self, db: &Db, report: impl Fn(String)) -> Result<bool, Box<dyn Error>> {
+        let mut changed = false;
+        for (window_name, title) in db.get_samples()? {
+            let sample = Sample::from_stored(&window_name, title.as_ref());
+            match self.apply(sample.clone()) {
+                None => {
+                    let deleted = db.delete_samples(&window_name, &title)?;
+                    report(format!("Dropped {deleted} intervals of {window_name}"));
+                    changed = true;
+                }
+                Some(new_sample) if new_sample != sample => {
+                    let updated = db.rename_samples(&window_name, &title, &new_sample.display_name, &new_sample.title)?;
+                    report(format!("Anonymized {updated} intervals of {window_name}"));
+                    changed = true;
+                }
+                Some(_) => {}
+            }
+        }
+        // pruned days are only left in the summary, which knows the apps but not their titles
+        for window_name in db.get_pruned_apps()? {
+            let sample = Sample::from_stored(&window_name, None);
+            match self.apply(sample.clone()) {
+                None => {
+                    let deleted = db.delete_pruned_summaries(&window_name)?;
+                    report(format!("Dropped {deleted} summaries of {window_name}"));
+                }
+                Some(new_sample) if new_sample != sample => {
+                    let updated = db.rename_pruned_summaries(&window_name, &new_sample.display_name)?;
+                    report(format!("Anonymized {updated} summaries of {window_name}"));
+                }
+                Some(_) => {}
+            }
+        }
+        let filtered = db.filter_edit_log(|interval| self.apply_to_interval(interval))?;
+        if filtered > 0 {
+            report(format!("Removed the privacy rule matches from {filtered} edits that can be undone"));
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datetime::Calendar;
+
+    fn sample(exe_name: &str, title: &str) -> Sample {
+        Sample {
+            exe_name: exe_name.to_string(),
+            title: title.to_string(),
+            display_name: exe_name.to_string(),
+        }
+    }
+
+    fn rule(exe: Option<&str>, title: Option<&str>, action: PrivacyAction) -> PrivacyRule {
+        PrivacyRule {
+            exe: exe.map(String::from),
+            title: title.map(String::from),
+            action,
+            placeholder: None,
+        }
+    }
+
+    fn is_dropped(rule: PrivacyRule, exe_name: &str, title: &str) -> bool {
+        PrivacyFilter::new(&[rule]).unwrap().apply(sample(exe_name, title)).is_none()
+    }
+
+    #[test]
+    fn exe_is_a_glob_and_title_a_regex() {
+        let exe = |pattern| rule(Some(pattern), None, PrivacyAction::Drop);
+        assert!(is_dropped(exe("chrome.exe"), "Chrome.EXE", ""));
+        assert!(is_dropped(exe("*bank*"), "mybanking.exe", ""));
+        // a dot is just a dot and the whole name has to match
+        assert!(!is_dropped(exe("chrome.exe"), "chromexexe", ""));
+        assert!(!is_dropped(exe("chrome"), "chrome.exe", ""));
+
+        let title = |pattern| rule(None, Some(pattern), PrivacyAction::Drop);
+        assert!(is_dropped(title("^Private"), "firefox.exe", "Private Browsing"));
+        assert!(is_dropped(title("bank"), "firefox.exe", "My bank - Firefox"));
+        // unanchored, but case sensitive unless the regex says otherwise
+        assert!(!is_dropped(title("bank"), "firefox.exe", "Bank - Firefox"));
+        assert!(is_dropped(title("(?i)bank"), "firefox.exe", "Bank - Firefox"));
+
+        let both = rule(Some("firefox.exe"), Some("Private"), PrivacyAction::Drop);
+        assert!(is_dropped(both.clone(), "firefox.exe", "Private Browsing"));
+        assert!(!is_dropped(both.clone(), "chrome.exe", "Private Browsing"));
+        assert!(!is_dropped(both, "firefox.exe", "News"));
+    }
+
+    #[test]
+    fn rules_need_a_valid_pattern() {
+        assert!(PrivacyFilter::new(&[rule(None, None, PrivacyAction::Drop)]).is_err());
+        assert!(PrivacyFilter::new(&[rule(None, Some("(unclosed"), PrivacyAction::Drop)]).is_err());
+        assert!(PrivacyFilter::new(&[rule(Some("[unclosed"), None, PrivacyAction::Drop)]).is_err());
+    }
+
+    #[test]
+    fn drop_and_anonymize() {
+        let filter = PrivacyFilter::new(&[
+            rule(Some("keepass.exe"), None, PrivacyAction::Drop),
+            rule(None, Some("Inbox"), PrivacyAction::Anonymize),
+            PrivacyRule {
+                placeholder: Some("Banking".to_string()),
+                ..rule(None, Some("bank"), PrivacyAction::Anonymize)
+            },
+        ]).unwrap();
+        assert_eq!(filter.apply(sample("keepass.exe", "Passwords")), None);
+        // the exe name is kept for the project rules, it is not stored
+        assert_eq!(filter.apply(sample("outlook.exe", "Inbox - Outlook")), Some(Sample {
+            exe_name: "outlook.exe".to_string(),
+            title: "Hidden".to_string(),
+            display_name: "Hidden".to_string(),
+        }));
+        assert_eq!(filter.apply(sample("firefox.exe", "My bank")), Some(Sample {
+            exe_name: "firefox.exe".to_string(),
+            title: "Banking".to_string(),
+            display_name: "Banking".to_string(),
+        }));
+        assert_eq!(filter.apply(sample("code.exe", "main.rs")), Some(sample("code.exe", "main.rs")));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let anonymize = rule(None, Some("Private"), PrivacyAction::Anonymize);
+        let drop = rule(Some("firefox.exe"), None, PrivacyAction::Drop);
+        let private = sample("firefox.exe", "Private Browsing");
+
+        let filter = PrivacyFilter::new(&[anonymize.clone(), drop.clone()]).unwrap();
+        assert_eq!(filter.apply(private.clone()).map(|sample| sample.display_name), Some("Hidden".to_string()));
+        assert_eq!(filter.apply(sample("firefox.exe", "News")), None);
+
+        let filter = PrivacyFilter::new(&[drop, anonymize]).unwrap();
+        assert_eq!(filter.apply(private), None);
+        assert!(filter.apply(sample("chrome.exe", "Private Browsing")).is_some());
+    }
+
+    #[test]
+    fn purge_does_what_apply_to_interval_does() {
+        let calendar = Calendar::default();
+        let db = Db::open_in_memory().unwrap();
+        let recorded = [
+            ("keepass.exe", "Passwords", None),
+            ("outlook.exe", "Inbox - Outlook", Some("Mail")),
+            ("outlook.exe", "Calendar - Outlook", Some("Mail")),
+            ("firefox.exe", "My bank", None),
+            ("code.exe", "main.rs - app-time", Some("app-time")),
+        ];
+        for (i, (window_name, title, project)) in recorded.into_iter().enumerate() {
+            let time_from = i as i64 * 60_000;
+            let rowid = db.insert(&window_name.to_string(), &title.to_string(), project.map(String::from).as_ref(), time_from, 0).unwrap();
+            db.update_time_to(rowid, time_from + 60_000).unwrap();
+        }
+        // intervals entered by hand have no title, the window name stands in for it
+        for (i, window_name) in ["keepass.exe", "Inbox"].into_iter().enumerate() {
+            let time_from = 600_000 + i as i64 * 60_000;
+            db.add_interval(&Interval {
+                rowid: 0,
+                window_name: window_name.to_string(),
+                title: None,
+                project: Some("Manual".to_string()),
+                time_from,
+                time_to: time_from + 60_000,
+                utc_offset: Some(0),
+                manual: true,
+            }, &calendar).unwrap();
+        }
+        let filter = PrivacyFilter::new(&[
+            rule(Some("keepass.exe"), None, PrivacyAction::Drop),
+            rule(None, Some("Inbox"), PrivacyAction::Anonymize),
+            rule(None, Some("bank"), PrivacyAction::Anonymize),
+        ]).unwrap();
+
+        let sort = |mut intervals: Vec<Interval>| {
+            intervals.sort_by_key(|interval| interval.rowid);
+            intervals
+        };
+        let expected = sort(db.get_intervals(None).unwrap().iter()
+            .filter_map(|interval| filter.apply_to_interval(interval))
+            .collect());
+        assert!(filter.purge(&db, |_| {}).unwrap());
+        assert_eq!(sort(db.get_intervals(None).unwrap()), expected);
+        assert_eq!(expected.iter().filter(|interval| interval.window_name == "Hidden" && interval.project.is_none()).count(), 3);
+        // nothing is left to purge
+        assert!(!filter.purge(&db, |_| {}).unwrap());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::datetime::Calendar;
+use crate::db::Db;
+use crate::query::{GroupBy, StatKey, StatsQuery};
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// How far back the streak is counted.
+const MAX_STREAK_DAYS: u64 = 365;
+const MILLIS_PER_HOUR: f64 = 3_600_000.0;
+
+#[derive(Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Productivity {
+    Productive,
+    #[default]
+    Neutral,
+    Distracting,
+}
+
+impl Productivity {
+    pub fn as_title(&self) -> &str {
+        match self {
+            Productivity::Productive => "productive",
+            Productivity::Neutral => "neutral",
+            Productivity::Distracting => "distracting",
+        }
+    }
+}
+
+/// The `[productivity]` section of the config. Apps are productive or distracting when they
+/// are listed themselves or their category is, apps take precedence over categories and
+/// everything else is neutral.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ProductivityConfig {
+    pub productive_apps: Vec<String>,
+    pub productive_categories: Vec<String>,
+    pub distracting_apps: Vec<String>,
+    pub distracting_categories: Vec<String>,
+    pub weights: Weights,
+}
+
+impl ProductivityConfig {
+    pub fn classify(&self, app: &str, category: Option<&str>) -> Productivity {
+        let contains = |names: &[String], name: &str| names.iter().any(|listed| listed.eq_ignore_ascii_case(name));
+        if contains(&self.productive_apps, app) {
+            Productivity::Productive
+        } else if contains(&self.distracting_apps, app) {
+            Productivity::Distracting
+        } else if category.is_some_and(|category| contains(&self.productive_categories, category)) {
+            Productivity::Productive
+        } else if category.is_some_and(|category| contains(&self.distracting_categories, category)) {
+            Productivity::Distracting
+        } else {
+            Productivity::Neutral
+        }
+    }
+}
+
+/// What an hour of each kind is worth for the score, which is the weighted average scaled to 0-100.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct Weights {
+    pub productive: f64,
+    pub neutral: f64,
+    pub distracting: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            productive: 1.0,
+            neutral: 0.5,
+            distracting: 0.0,
+        }
+    }
+}
+
+impl Weights {
+    fn get(&self, productivity: Productivity) -> f64 {
+        match productivity {
+            Productivity::Productive => self.productive,
+            Productivity::Neutral => self.neutral,
+            Productivity::Distracting => self.distracting,
+        }
+    }
+}
+
+/// A daily goal from the `[[goals]]` section of the config, e.g. 4 hours of productive time.
+/// The time of the apps matching everything that is set counts towards it, so a goal with
+/// none of them set counts all time. A `limit` is met by staying below the hours instead.
+///
+/// ```toml
+/// [[goals]]
+/// productivity = "productive"
+/// hours = 4
+///
+/// [[goals]]
+/// category = "Games"
+/// hours = 1
+/// limit = true
+/// ```
+#[derive(Deserialize, Default, Clone)]
+pub struct Goal {
+    pub productivity: Option<Productivity>,
+    pub app: Option<String>,
+    pub category: Option<String>,
+    pub hours: f64,
+    #[serde(default)]
+    pub limit: bool,
+}
+
+impl Goal {
+    pub fn as_title(&self) -> String {
+        let mut title = format!("{}{}h", if self.limit { "At most " } else { "" }, self.hours);
+        let targets = [self.productivity.as_ref().map(Productivity::as_title), self.category.as_deref(), self.app.as_deref()];
+        for target in targets.into_iter().flatten() {
+            title.push(' ');
+            title.push_str(target);
+        }
+        title
+    }
+
+    pub fn get_target_millis(&self) -> i64 {
+        (self.hours * MILLIS_PER_HOUR) as i64
+    }
+
+    fn counts(&self, app: &AppTime) -> bool {
+        self.productivity.is_none_or(|productivity| productivity == app.productivity)
+            && self.app.as_ref().is_none_or(|name| name.eq_ignore_ascii_case(&app.name))
+            && self.category.as_ref().is_none_or(|category| app.category.as_ref() == Some(category))
+    }
+
+    fn is_met(&self, millis: i64) -> bool {
+        if self.limit {
+            millis <= self.get_target_millis()
+        } else {
+            millis >= self.get_target_millis()
+        }
+    }
+}
+
+struct AppTime {
+    name: String,
+    category: Option<String>,
+    productivity: Productivity,
+    millis: i64,
+}
+
+pub struct GoalProgress {
+    pub goal: Goal,
+    pub millis: i64,
+    pub is_met: bool,
+}
+
+/// Today's time by productivity, its score, how far the goals are and for how many days in a
+/// row all of them have been met.
+pub struct Dashboard {
+    pub productive_millis: i64,
+    pub neutral_millis: i64,
+    pub distracting_millis: i64,
+    /// None until something is recorded today.
+    pub score: Option<u32>,
+    pub goals: Vec<GoalProgress>,
+    /// Days in a row up to today, today only counts once its goals are met.
+    pub streak: u32,
+}
+
+impl Dashboard {
+    /// Sums up `today` and the streak of days before it.
+    pub fn load(
+        db: &Db,
+        config: &ProductivityConfig,
+        goals: &[Goal],
+        calendar: &Calendar,
+        today: NaiveDate,
+    ) -> Result<Dashboard, Box<dyn Error>> {
+        let first_day = today - Days::new(MAX_STREAK_DAYS);
+        let query = StatsQuery::new()
+            .period(Some(calendar.period(first_day, today)))
+            .group_by(GroupBy::App)
+            .per_day()
+            .calendar(*calendar);
+        let categories = db.get_categories()?;
+        let mut days: HashMap<NaiveDate, Vec<AppTime>> = HashMap::new();
+        for stat in db.get_stats(&query)? {
+            let (StatKey::App(name), Some(day)) = (stat.key, stat.day) else {
+                continue;
+            };
+            let category = categories.get(&name).cloned();
+            days.entry(day).or_default().push(AppTime {
+                productivity: config.classify(&name, category.as_deref()),
+                name,
+                category,
+                millis: stat.millis,
+            });
+        }
+        let get_goal_millis = |goal: &Goal, day: &NaiveDate| -> i64 {
+            days.get(day).map_or(0, |apps| apps.iter().filter(|app| goal.counts(app)).map(|app| app.millis).sum())
+        };
+        // a day without any time recorded does not count, or limits would be met before tracking began
+        let all_met = |day: &NaiveDate| {
+            days.contains_key(day) && goals.iter().all(|goal| goal.is_met(get_goal_millis(goal, day)))
+        };
+
+        let mut streak = 0;
+        if !goals.is_empty() {
+            if all_met(&today) {
+                streak += 1;
+            }
+            let mut day = today - Days::new(1);
+            while day >= first_day && all_met(&day) {
+                streak += 1;
+                day = day - Days::new(1);
+            }
+        }
+
+        let apps = days.get(&today).map(Vec::as_slice).unwrap_or_default();
+        let get_millis = |productivity: Productivity| -> i64 {
+            apps.iter().filter(|app| app.productivity == productivity).map(|app| app.millis).sum()
+        };
+        let total_millis: i64 = apps.iter().map(|app| app.millis).sum();
+        let weighted_millis: f64 = apps.iter()
+            .map(|app| config.weights.get(app.productivity) * app.millis as f64)
+            .sum();
+        let max_weight = [config.weights.productive, config.weights.neutral, config.weights.distracting]
+            .into_iter()
+            .fold(f64::MIN, f64::max);
+        let score = (total_millis > 0 && max_weight > 0.0)
+            .then(|| (weighted_millis / total_millis as f64 / max_weight * 100.0).clamp(0.0, 100.0).round() as u32);
+        Ok(Dashboard {
+            productive_millis: get_millis(Productivity::Productive),
+            neutral_millis: get_millis(Productivity::Neutral),
+            distracting_millis: get_millis(Productivity::Distracting),
+            score,
+            goals: goals.iter()
+                .map(|goal| {
+                    let millis = get_goal_millis(goal, &today);
+                    GoalProgress { goal: goal.clone(), millis, is_met: goal.is_met(millis) }
+                })
+                .collect(),
+            streak,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Interval;
+
+    const MILLIS_PER_MINUTE: i64 = 60_000;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 10).unwrap()
+    }
+
+    /// Records `minutes` of `app` on the day `days_ago` days before today, after what is
+    /// already recorded on it.
+    fn record(db: &Db, app: &str, days_ago: u64, minutes: i64) {
+        let day_start = (today() - Days::new(days_ago)).and_hms_opt(2, 0, 0).unwrap().and_utc().timestamp_millis();
+        let time_from = db.get_intervals(None).unwrap().iter()
+            .filter(|interval| interval.time_from >= day_start && interval.time_from < day_start + 20 * 60 * MILLIS_PER_MINUTE)
+            .map(|interval| interval.time_to)
+            .max()
+            .unwrap_or(day_start);
+        db.add_interval(&Interval {
+            rowid: 0,
+            window_name: app.to_string(),
+            title: None,
+            project: None,
+            time_from,
+            time_to: time_from + minutes * MILLIS_PER_MINUTE,
+            utc_offset: Some(0),
+            manual: true,
+        }, &Calendar::default()).unwrap();
+    }
+
+    fn config() -> ProductivityConfig {
+        ProductivityConfig {
+            productive_apps: vec!["code.exe".to_string()],
+            productive_categories: vec!["Work".to_string()],
+            distracting_apps: vec!["steam.exe".to_string()],
+            distracting_categories: vec!["Games".to_string()],
+            weights: Weights::default(),
+        }
+    }
+
+    fn goal(productivity: Productivity, hours: f64, limit: bool) -> Goal {
+        Goal { productivity: Some(productivity), app: None, category: None, hours, limit }
+    }
+
+    fn load(db: &Db, goals: &[Goal]) -> Dashboard {
+        Dashboard::load(db, &config(), goals, &Calendar::default(), today()).unwrap()
+    }
+
+    #[test]
+    fn apps_are_classified_before_their_categories() {
+        let config = config();
+        assert_eq!(config.classify("Code.exe", Some("Games")), Productivity::Productive);
+        assert_eq!(config.classify("steam.exe", Some("work")), Productivity::Distracting);
+        assert_eq!(config.classify("outlook.exe", Some("Work")), Productivity::Productive);
+        assert_eq!(config.classify("minesweeper.exe", Some("Games")), Productivity::Distracting);
+        assert_eq!(config.classify("explorer.exe", Some("Files")), Productivity::Neutral);
+        assert_eq!(config.classify("explorer.exe", None), Productivity::Neutral);
+    }
+
+    #[test]
+    fn score_weights_the_time_of_today() {
+        let db = Db::open_in_memory().unwrap();
+        assert_eq!(load(&db, &[]).score, None);
+
+        // unclassified apps are neutral
+        record(&db, "explorer.exe", 0, 60);
+        let dashboard = load(&db, &[]);
+        assert_eq!(dashboard.neutral_millis, 60 * MILLIS_PER_MINUTE);
+        assert_eq!(dashboard.score, Some(50));
+
+        record(&db, "code.exe", 0, 90);
+        record(&db, "steam.exe", 0, 30);
+        db.set_category(&"outlook.exe".to_string(), Some(&"Work".to_string())).unwrap();
+        record(&db, "outlook.exe", 0, 60);
+        // other days do not count
+        record(&db, "steam.exe", 1, 600);
+        let dashboard = load(&db, &[]);
+        assert_eq!(dashboard.productive_millis, 150 * MILLIS_PER_MINUTE);
+        assert_eq!(dashboard.distracting_millis, 30 * MILLIS_PER_MINUTE);
+        // (150 + 0.5 * 60) / 240
+        assert_eq!(dashboard.score, Some(75));
+
+        let config = ProductivityConfig {
+            weights: Weights { productive: 0.0, neutral: 0.0, distracting: 0.0 },
+            ..config()
+        };
+        assert_eq!(Dashboard::load(&db, &config, &[], &Calendar::default(), today()).unwrap().score, None);
+    }
+
+    #[test]
+    fn goals_are_met_at_their_hours() {
+        let db = Db::open_in_memory().unwrap();
+        record(&db, "code.exe", 0, 120);
+        record(&db, "steam.exe", 0, 60);
+        let goals = [
+            goal(Productivity::Productive, 2.0, false),
+            goal(Productivity::Productive, 2.5, false),
+            goal(Productivity::Distracting, 1.0, true),
+            goal(Productivity::Distracting, 0.5, true),
+            Goal { app: Some("steam.exe".to_string()), hours: 1.0, ..Goal::default() },
+            Goal { hours: 3.0, ..Goal::default() },
+        ];
+        let progress: Vec<(i64, bool)> = load(&db, &goals).goals.iter()
+            .map(|progress| (progress.millis / MILLIS_PER_MINUTE, progress.is_met))
+            .collect();
+        assert_eq!(progress, vec![(120, true), (120, false), (60, true), (60, false), (60, true), (180, true)]);
+    }
+
+    #[test]
+    fn streak_counts_the_days_all_goals_are_met() {
+        let db = Db::open_in_memory().unwrap();
+        let goals = [goal(Productivity::Productive, 1.0, false), goal(Productivity::Distracting, 1.0, true)];
+        assert_eq!(load(&db, &goals).streak, 0);
+
+        for days_ago in 1..=3 {
+            record(&db, "code.exe", days_ago, 60);
+        }
+        // a limit exceeded ends the streak
+        record(&db, "code.exe", 4, 60);
+        record(&db, "steam.exe", 4, 61);
+        for days_ago in 5..=7 {
+            record(&db, "code.exe", days_ago, 60);
+        }
+        // today is not over yet, so it does not end the streak either
+        record(&db, "code.exe", 0, 30);
+        assert_eq!(load(&db, &goals).streak, 3);
+        record(&db, "code.exe", 0, 30);
+        assert_eq!(load(&db, &goals).streak, 4);
+        assert_eq!(load(&db, &[]).streak, 0);
+    }
+
+    #[test]
+    fn day_without_data_ends_the_streak() {
+        let db = Db::open_in_memory().unwrap();
+        // the limit would be met on a day nothing was recorded
+        let goals = [goal(Productivity::Distracting, 1.0, true)];
+        record(&db, "code.exe", 1, 60);
+        record(&db, "code.exe", 3, 60);
+        record(&db, "code.exe", 4, 60);
+        assert_eq!(load(&db, &goals).streak, 1);
+        record(&db, "code.exe", 2, 60);
+        assert_eq!(load(&db, &goals).streak, 4);
+    }
+}
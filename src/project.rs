@@ -0,0 +1,117 @@
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+
+/// A rule from the `[[projects]]` section of the config. `exe` is a glob matched against the
+/// executable file name, `title` is a regex matched against the window title and `dir` is a
+/// folder the title has to show a path within, as terminals and Explorer do for their working
+/// directory. All that are set have to match.
+///
+/// Without a `name` the project is named after the first group captured by `title`, so a single
+/// rule covers every project of an editor:
+///
+/// ```toml
+/// # VS Code: "main.rs — app-time — Visual Studio Code"
+/// [[projects]]
+/// exe = "Code.exe"
+/// title = " — (.+?) — Visual Studio Code$"
+///
+/// # JetBrains IDEs: "app-time – main.rs"
+/// [[projects]]
+/// exe = "idea64.exe"
+/// title = "^(.+?) – "
+///
+/// [[projects]]
+/// name = "Invoices"
+/// dir = "C:\\work\\invoices"
+/// ```
+#[derive(Deserialize, Default, Clone)]
+pub struct ProjectRule {
+    pub name: Option<String>,
+    pub exe: Option<String>,
+    pub title: Option<String>,
+    pub dir: Option<String>,
+}
+
+struct CompiledRule {
+    name: Option<String>,
+    exe: Option<Pattern>,
+    title: Option<Regex>,
+    dir: Option<String>,
+}
+
+impl CompiledRule {
+    /// Returns the project if the rule matches.
+    fn get_project(&self, exe_name: &str, title: &str) -> Option<String> {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::default()
+        };
+        if let Some(pattern) = &self.exe {
+            if !pattern.matches_with(exe_name, options) {
+                return None;
+            }
+        }
+        if let Some(dir) = &self.dir {
+            if !shows_path_within(title, dir) {
+                return None;
+            }
+        }
+        let captured = match &self.title {
+            Some(regex) => {
+                let captures = regex.captures(title)?;
+                captures.get(1).map(|group| group.as_str().trim().to_string())
+            }
+            None => None,
+        };
+        self.name.clone().or(captured).filter(|name| !name.is_empty())
+    }
+}
+
+/// Attributes samples to the projects of the configured rules.
+pub struct ProjectMatcher {
+    rules: Vec<CompiledRule>,
+}
+
+impl ProjectMatcher {
+    pub fn new(rules: &[ProjectRule]) -> Result<ProjectMatcher, Box<dyn Error>> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            if rule.exe.is_none() && rule.title.is_none() && rule.dir.is_none() {
+                return Err("Project rule must have an exe, a title or a dir pattern".into());
+            }
+            let title = rule.title.as_deref().map(Regex::new).transpose()?;
+            if rule.name.is_none() && title.as_ref().is_none_or(|regex| regex.captures_len() < 2) {
+                return Err("Project rule must have a name or a title pattern capturing it".into());
+            }
+            compiled.push(CompiledRule {
+                name: rule.name.clone(),
+                exe: rule.exe.as_deref().map(Pattern::new).transpose()?,
+                title,
+                dir: rule.dir.as_deref().map(normalize_path),
+            });
+        }
+        Ok(ProjectMatcher { rules: compiled })
+    }
+
+    /// Returns the project of the first matching rule, `None` if no rule matches.
+    pub fn get_project(&self, exe_name: &str, title: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| rule.get_project(exe_name, title))
+    }
+}
+
+/// Paths are compared ignoring case and the kind of slashes, as Windows does.
+fn normalize_path(path: &str) -> String {
+    path.to_lowercase().replace('/', "\\").trim_end_matches('\\').to_string()
+}
+
+/// Whether `title` contains `dir` or a path within it. `dir` has to be normalized.
+fn shows_path_within(title: &str, dir: &str) -> bool {
+    let title = normalize_path(title);
+    title.match_indices(dir).any(|(start, _)| {
+        // "c:\work\invoices" must not match "c:\work\invoices-old"
+        let rest = &title[start + dir.len()..];
+        !rest.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+    })
+}
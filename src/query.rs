@@ -0,0 +1,612 @@
+use crate::datetime::{Calendar, MAX_UTC_OFFSET, MIN_UTC_OFFSET};
+use chrono::{Days, NaiveDate, NaiveTime};
+use rusqlite::types::Value;
+use std::fmt::{Display, Formatter};
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum GroupBy {
+    #[default]
+    App,
+    Title,
+    Category,
+    Project,
+    Device,
+    Day,
+    Hour,
+}
+
+impl GroupBy {
+    pub fn parse(value: &str) -> Option<GroupBy> {
+        match value {
+            "app" => Some(GroupBy::App),
+            "title" => Some(GroupBy::Title),
+            "category" => Some(GroupBy::Category),
+            "project" => Some(GroupBy::Project),
+            "device" => Some(GroupBy::Device),
+            "day" => Some(GroupBy::Day),
+            "hour" => Some(GroupBy::Hour),
+            _ => None,
+        }
+    }
+
+    fn key_sql(&self) -> &str {
+        match self {
+            GroupBy::App => "window_name",
+            GroupBy::Title => "title",
+            GroupBy::Category => "category",
+            GroupBy::Project => "project",
+            GroupBy::Device => "device",
+            GroupBy::Day => "date((time_from + day_offset) / 1000, 'unixepoch')",
+            GroupBy::Hour => "(time_from + utc_offset) % 86400000 / 3600000",
+        }
+    }
+
+    /// The key of the `daily_summary` rows, None when they cannot be grouped this way.
+    fn summary_key_sql(&self) -> Option<&str> {
+        match self {
+            GroupBy::App | GroupBy::Title => Some("daily_summary.window_name"),
+            GroupBy::Category => Some("app.category"),
+            GroupBy::Project => Some("daily_summary.project"),
+            GroupBy::Device => Some("daily_summary.device"),
+            GroupBy::Day => Some("day"),
+            GroupBy::Hour => None,
+        }
+    }
+
+    /// Returns the CTEs clipping the intervals to each of the `ranges` and splitting them into
+    /// buckets when grouping by local time, then the expression to sum and the table to sum it from.
+    /// With `split_days` the buckets are cut at day starts as well, which need not be on the hour.
+    fn pieces_sql(
+        &self,
+        params: &mut Vec<Value>,
+        calendar: &Calendar,
+        ranges: &[(i64, i64)],
+        conditions: &str,
+        split_days: bool,
+    ) -> (String, String, &str) {
+        let day_start = bind(params, calendar.day_start.signed_duration_since(NaiveTime::MIN).num_milliseconds());
+        let clipped = ranges.iter()
+            .map(|(from, to)| {
+                let (from, to) = (bind(params, *from), bind(params, *to));
+                format!(
+                    "select activity.window_name,
+                            coalesce(activity.title, activity.window_name),
+                            app.category,
+                            activity.project,
+                            activity.device,
+                            max(activity.time_from, {from}),
+                            min(activity.time_to, {to}),
+                            activity.utc_offset * 1000,
+                            activity.utc_offset * 1000 - {day_start}
+                    from activity
+                    left join app on app.window_name = activity.window_name
+                    where activity.time_from < {to} and activity.time_to > {from}{conditions}"
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" union all ");
+        // intervals overlapping a range are clipped to its bounds, offsets are turned into milliseconds.
+        // Once inlined, the planner would rather walk the whole table in the order of the app
+        // index than search the time index, so the clipped intervals are materialized first
+        let mut sql = format!(
+            "with recursive clipped(window_name, title, category, project, device, time_from, time_to, utc_offset, day_offset) as materialized (
+                {clipped}
+            )"
+        );
+        match self.bucket_sql() {
+            Some((width, shift)) => {
+                // intervals are split at bucket boundaries so each piece is counted where it belongs
+                let mut next = format!("((time_from + {shift}) / {width} + 1) * {width} - ({shift})");
+                if let (true, Some((width, shift))) = (split_days, GroupBy::Day.bucket_sql()) {
+                    next = format!("min({next}, ((time_from + {shift}) / {width} + 1) * {width} - ({shift}))");
+                }
+                sql.push_str(&format!(
+                    ", pieces(window_name, title, category, project, device, time_from, time_to, utc_offset, day_offset) as (
+                        select * from clipped
+                        union all
+                        select window_name, title, category, project, device, {next}, time_to, utc_offset, day_offset
+                        from pieces
+                        where {next} < time_to
+                    )"
+                ));
+                (sql, format!("min(time_to, {next}) - time_from"), "pieces")
+            }
+            None => (sql, "time_to - time_from".to_string(), "clipped"),
+        }
+    }
+
+    /// Width and shift of the local time buckets the intervals have to be split into.
+    fn bucket_sql(&self) -> Option<(&str, &str)> {
+        match self {
+            GroupBy::Day => Some(("86400000", "day_offset")),
+            GroupBy::Hour => Some(("3600000", "utc_offset")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum StatKey {
+    App(String),
+    Title(String),
+    Category(Option<String>),
+    Project(Option<String>),
+    /// The device the time was recorded on, None for this one.
+    Device(Option<String>),
+    Day(NaiveDate),
+    Hour(u32),
+}
+
+impl Display for StatKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatKey::App(name) | StatKey::Title(name) => write!(f, "{name}"),
+            StatKey::Category(category) => write!(f, "{}", category.as_deref().unwrap_or("Uncategorized")),
+            StatKey::Project(project) => write!(f, "{}", project.as_deref().unwrap_or("No project")),
+            StatKey::Device(device) => write!(f, "{}", device.as_deref().unwrap_or("This device")),
+            StatKey::Day(date) => write!(f, "{}", date.format("%d.%m.%Y")),
+            StatKey::Hour(hour) => write!(f, "{hour:02}:00"),
+        }
+    }
+}
+
+pub struct Stat {
+    pub key: StatKey,
+    /// The day the time was spent on when the stats are split per day.
+    pub day: Option<NaiveDate>,
+    pub millis: i64,
+}
+
+/// Builds the query behind `Db::get_stats`. Every value ends up as a bound parameter,
+/// only the fixed fragments picked by the builder are put into the SQL text.
+#[derive(Clone, Default)]
+pub struct StatsQuery {
+    period: Option<(i64, i64)>,
+    apps: Vec<String>,
+    categories: Vec<String>,
+    projects: Vec<String>,
+    devices: Vec<Option<String>>,
+    group_by: GroupBy,
+    per_day: bool,
+    min_millis: Option<i64>,
+    limit: Option<u32>,
+    calendar: Calendar,
+    skip_summary: bool,
+}
+
+impl StatsQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn period(mut self, period: Option<(i64, i64)>) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.apps.push(app.into());
+        self
+    }
+
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.categories.push(category.into());
+        self
+    }
+
+    pub fn project(mut self, project: impl Into<String>) -> Self {
+        self.projects.push(project.into());
+        self
+    }
+
+    /// Only counts time recorded on `device`, None stands for this device.
+    pub fn device(mut self, device: Option<String>) -> Self {
+        self.devices.push(device);
+        self
+    }
+
+    pub fn group_by(mut self, group_by: GroupBy) -> Self {
+        self.group_by = group_by;
+        self
+    }
+
+    /// Splits every group into the days its time was spent on, as for a timesheet.
+    pub fn per_day(mut self) -> Self {
+        self.per_day = true;
+        self
+    }
+
+    pub fn min_millis(mut self, min_millis: i64) -> Self {
+        self.min_millis = Some(min_millis);
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the day start used to group by day.
+    pub fn calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Reads the intervals instead of `daily_summary` for the whole period, which leaves out
+    /// pruned days. The stats are otherwise the same, only slower for long periods.
+    #[cfg(test)]
+    pub(crate) fn without_summary(mut self) -> Self {
+        self.skip_summary = true;
+        self
+    }
+
+    pub fn get_period(&self) -> Option<(i64, i64)> {
+        self.period
+    }
+
+    pub fn get_group_by(&self) -> GroupBy {
+        self.group_by
+    }
+
+    pub fn is_per_day(&self) -> bool {
+        self.per_day
+    }
+
+    pub(crate) fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let filters = self.bind_filters(&mut params);
+        let (ranges, summary) = self.split_period();
+        let mut ctes = String::new();
+        let mut totals = Vec::new();
+        // the days read from the summary are left out of the intervals, which are cut at day
+        // starts to tell them apart
+        let summarized = summary.as_ref()
+            .filter(|days| !days.pruned_only)
+            .and_then(|days| days.first_day.zip(days.end_day));
+        let mut filters_sql = filters.to_sql("activity");
+        if let Some((first_day, end_day)) = summarized {
+            // whatever its offset, time from here to there is on the summarized days, so the
+            // intervals lying entirely in between are not read at all
+            let inner_from = self.calendar.day_start_millis(first_day, MIN_UTC_OFFSET);
+            let inner_to = self.calendar.day_start_millis(end_day, MAX_UTC_OFFSET);
+            if inner_from < inner_to {
+                filters_sql.push_str(&format!(
+                    " and (activity.time_from < {} or activity.time_to > {})",
+                    bind(&mut params, inner_from),
+                    bind(&mut params, inner_to),
+                ));
+            }
+        }
+        if !ranges.is_empty() {
+            // time split per day has to be cut at day starts, hours as well when grouping by them
+            let pieces_by = match self.group_by {
+                GroupBy::Hour => GroupBy::Hour,
+                _ if self.per_day || summarized.is_some() => GroupBy::Day,
+                group_by => group_by,
+            };
+            let split_days = self.per_day && pieces_by == GroupBy::Hour;
+            let (sql, time, pieces) = pieces_by.pieces_sql(
+                &mut params,
+                &self.calendar,
+                &ranges,
+                &filters_sql,
+                split_days,
+            );
+            let key = self.group_by.key_sql();
+            let day_key = GroupBy::Day.key_sql();
+            let day = if self.per_day { day_key } else { "null" };
+            let mut conditions = String::new();
+            if let Some((first_day, end_day)) = summarized {
+                conditions = format!(
+                    " where {day_key} not between {} and {}",
+                    bind(&mut params, first_day.to_string()),
+                    bind(&mut params, (end_day - Days::new(1)).to_string()),
+                );
+            }
+            ctes = sql;
+            totals.push(format!(
+                "select {key} as group_key, {day} as day_key, sum({time}) as time from {pieces}{conditions} group by 1, 2"
+            ));
+        }
+        if let (Some(key), Some(days)) = (self.group_by.summary_key_sql(), summary) {
+            let mut conditions = String::new();
+            if let Some(first_day) = days.first_day {
+                conditions.push_str(&format!(" and day >= {}", bind(&mut params, first_day.to_string())));
+            }
+            if let Some(end_day) = days.end_day {
+                conditions.push_str(&format!(" and day < {}", bind(&mut params, end_day.to_string())));
+            }
+            if days.pruned_only {
+                conditions.push_str(" and pruned");
+            }
+            conditions.push_str(&filters.to_sql("daily_summary"));
+            let day = if self.per_day { "day" } else { "null" };
+            totals.push(format!(
+                "select {key} as group_key, {day} as day_key, sum(time) as time
+                from daily_summary
+                left join app on app.window_name = daily_summary.window_name
+                where 1{conditions}
+                group by 1, 2"
+            ));
+        }
+        let totals = totals.join(" union all ");
+        let mut sql = format!(
+            "{ctes} select group_key, sum(time) as time, day_key from ({totals})
+            group by group_key, day_key
+            having sum(time) > 0"
+        );
+        if let Some(min_millis) = self.min_millis {
+            sql.push_str(&format!(" and sum(time) >= {}", bind(&mut params, min_millis)));
+        }
+        sql.push_str(if self.per_day { " order by day_key, " } else { " order by " });
+        match self.group_by {
+            GroupBy::Day | GroupBy::Hour => sql.push_str("group_key"),
+            _ => sql.push_str("time desc"),
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" limit {}", bind(&mut params, limit)));
+        }
+        (sql, params)
+    }
+
+    /// Sums the intervals within the period per app, project, device and day, whatever the grouping
+    /// and the filters are. Used to build `daily_summary`.
+    pub(crate) fn to_summary_sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let range = self.period.unwrap_or((0, i64::MAX));
+        let (mut sql, time, pieces) = GroupBy::Day.pieces_sql(&mut params, &self.calendar, &[range], "", false);
+        let day = GroupBy::Day.key_sql();
+        sql.push_str(&format!(
+            " select window_name, project, device, {day}, sum({time}) from {pieces} group by 1, 2, 3, 4"
+        ));
+        (sql, params)
+    }
+
+    /// Divides the period between the intervals and `daily_summary`. Summaries hold the whole
+    /// time of a day, so they are used for the days lying entirely within the period and the
+    /// intervals only for the rest, at the edges of the period. Titles and hours are not summed
+    /// up, when grouping by them summaries are only read for the days whose intervals were pruned.
+    fn split_period(&self) -> (Vec<(i64, i64)>, Option<SummaryDays>) {
+        let period = self.period.unwrap_or((0, i64::MAX));
+        if self.skip_summary || self.group_by.summary_key_sql().is_none() {
+            return (vec![period], None);
+        }
+        let days = match self.period {
+            // the summary of the current day is kept up to date as well
+            None => SummaryDays { first_day: None, end_day: None, pruned_only: false },
+            Some(period) => match self.get_full_days(period) {
+                Some((first_day, end_day)) => SummaryDays {
+                    first_day: Some(first_day),
+                    end_day: Some(end_day),
+                    pruned_only: false,
+                },
+                None => return (vec![period], None),
+            },
+        };
+        if self.group_by == GroupBy::Title {
+            return (vec![period], Some(SummaryDays { pruned_only: true, ..days }));
+        }
+        (self.period.into_iter().collect(), Some(days))
+    }
+
+    fn bind_filters(&self, params: &mut Vec<Value>) -> Filters {
+        Filters {
+            apps: Self::bind_all(params, &self.apps),
+            categories: Self::bind_all(params, &self.categories),
+            projects: Self::bind_all(params, &self.projects),
+            devices: Self::bind_all(params, &self.devices),
+        }
+    }
+
+    /// Returns the first day that lies entirely within `period` and the day after the last one,
+    /// None if there is no such day. Intervals are summed up by the day of the offset they were
+    /// recorded with, so a day has to lie within the period in every offset.
+    fn get_full_days(&self, (from, to): (i64, i64)) -> Option<(NaiveDate, NaiveDate)> {
+        let mut first_day = self.calendar.date_of_millis(from, Some(MAX_UTC_OFFSET));
+        if self.calendar.day_start_millis(first_day, MAX_UTC_OFFSET) < from {
+            first_day = first_day + Days::new(1);
+        }
+        let end_day = self.calendar.date_of_millis(to, Some(MIN_UTC_OFFSET));
+        (first_day < end_day).then_some((first_day, end_day))
+    }
+
+    fn bind_all<T: Clone + Into<Value>>(params: &mut Vec<Value>, values: &[T]) -> Vec<String> {
+        values.iter()
+            .map(|value| bind(params, value.clone()))
+            .collect()
+    }
+}
+
+/// Adds a parameter and returns its placeholder.
+fn bind(params: &mut Vec<Value>, value: impl Into<Value>) -> String {
+    params.push(value.into());
+    format!("?{}", params.len())
+}
+
+/// The days `daily_summary` is read for, unbounded where a day is None.
+struct SummaryDays {
+    first_day: Option<NaiveDate>,
+    end_day: Option<NaiveDate>,
+    pruned_only: bool,
+}
+
+/// Placeholders of the values the rows are filtered by.
+struct Filters {
+    apps: Vec<String>,
+    categories: Vec<String>,
+    projects: Vec<String>,
+    devices: Vec<String>,
+}
+
+impl Filters {
+    /// Returns the conditions for rows of `table` joined with `app`.
+    fn to_sql(&self, table: &str) -> String {
+        let mut conditions = String::new();
+        if !self.apps.is_empty() {
+            conditions.push_str(&format!(" and {table}.window_name in ({})", self.apps.join(", ")));
+        }
+        if !self.categories.is_empty() {
+            conditions.push_str(&format!(" and app.category in ({})", self.categories.join(", ")));
+        }
+        if !self.projects.is_empty() {
+            conditions.push_str(&format!(" and {table}.project in ({})", self.projects.join(", ")));
+        }
+        if !self.devices.is_empty() {
+            // `in` never matches the null of this device, so every device is compared with `is`
+            let devices = self.devices.iter()
+                .map(|placeholder| format!("{table}.device is {placeholder}"))
+                .collect::<Vec<String>>()
+                .join(" or ");
+            conditions.push_str(&format!(" and ({devices})"));
+        }
+        conditions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Db;
+    use crate::db::tests::generate_intervals;
+    use chrono::{DateTime, Local};
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    /// 2024-06-03 00:00 UTC, the days around it have no DST transition in any time zone.
+    const START_SECONDS: i64 = 1_717_372_800;
+    const RANGE_SECONDS: i64 = 6 * 86_400;
+    const APPS: [&str; 3] = ["code.exe", "firefox.exe", "slack.exe"];
+
+    type Totals = HashMap<(StatKey, Option<NaiveDate>), i64>;
+
+    /// An interval as (app, start and length in seconds, offset in quarter hours), recorded with
+    /// the offset of the local time zone unless another one is given.
+    fn record(db: &Db, (app, start, length, quarters): (usize, i64, i64, Option<i32>)) -> (usize, i64, i64, i32) {
+        let time_from = (START_SECONDS + start) * 1000;
+        let offset = quarters.map(|quarters| quarters * 900).unwrap_or_else(|| {
+            DateTime::from_timestamp_millis(time_from).unwrap()
+                .with_timezone(&Local).offset().local_minus_utc()
+        });
+        let rowid = db.insert(&APPS[app].to_string(), &APPS[app].to_string(), None, time_from, offset).unwrap();
+        db.update_time_to(rowid, time_from + length * 1000).unwrap();
+        (app, START_SECONDS + start, START_SECONDS + start + length, offset)
+    }
+
+    /// Counts every second of the intervals within the period on its own.
+    fn count_seconds(
+        intervals: &[(usize, i64, i64, i32)],
+        period: Option<(i64, i64)>,
+        calendar: &Calendar,
+        group_by: GroupBy,
+        per_day: bool,
+    ) -> Totals {
+        let (from, to) = period.unwrap_or((0, i64::MAX));
+        let mut totals = Totals::new();
+        for &(app, time_from, time_to, offset) in intervals {
+            for second in time_from..time_to {
+                let millis = second * 1000;
+                if millis < from || millis >= to {
+                    continue;
+                }
+                let day = calendar.date_of_millis(millis, Some(offset));
+                let key = match group_by {
+                    GroupBy::App => StatKey::App(APPS[app].to_string()),
+                    GroupBy::Day => StatKey::Day(day),
+                    GroupBy::Hour => StatKey::Hour(((second + offset as i64).rem_euclid(86_400) / 3600) as u32),
+                    _ => unreachable!(),
+                };
+                *totals.entry((key, per_day.then_some(day))).or_default() += 1000;
+            }
+        }
+        totals
+    }
+
+    fn get_totals(db: &Db, query: &StatsQuery) -> Totals {
+        db.get_stats(query).unwrap().into_iter()
+            .map(|stat| ((stat.key, stat.day), stat.millis))
+            .collect()
+    }
+
+    fn intervals() -> impl Strategy<Value = Vec<(usize, i64, i64, Option<i32>)>> {
+        // up to a day and a half long, so most of them cross a day start, some recorded while
+        // travelling through any offset from UTC-12 to UTC+14
+        let quarters = prop::option::weighted(0.5, -48..=56i32);
+        prop::collection::vec((0..APPS.len(), 0..RANGE_SECONDS, 1..36 * 3600i64, quarters), 1..5)
+    }
+
+    fn period() -> impl Strategy<Value = Option<(i64, i64)>> {
+        prop::option::of((-86_400..RANGE_SECONDS + 86_400, 1..4 * 86_400i64)
+            .prop_map(|(from, length)| ((START_SECONDS + from) * 1000, (START_SECONDS + from + length) * 1000)))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(48))]
+
+        #[test]
+        fn stats_match_counting_seconds(
+            intervals in intervals(),
+            period in period(),
+            day_start_minutes in 0..24 * 60u32,
+            group_by in prop::sample::select(vec![GroupBy::App, GroupBy::Day, GroupBy::Hour]),
+            per_day in any::<bool>(),
+        ) {
+            let calendar = Calendar {
+                day_start: NaiveTime::from_num_seconds_from_midnight_opt(day_start_minutes * 60, 0).unwrap(),
+                ..Calendar::default()
+            };
+            let db = Db::open_in_memory().unwrap();
+            let intervals: Vec<_> = intervals.into_iter().map(|interval| record(&db, interval)).collect();
+            let query = StatsQuery::new().period(period).calendar(calendar).group_by(group_by);
+            let query = if per_day { query.per_day() } else { query };
+            // full days are read from the summary, the rest of the period from the intervals
+            db.check_summary(&calendar).unwrap();
+            let expected = count_seconds(&intervals, period, &calendar, group_by, per_day);
+            prop_assert_eq!(get_totals(&db, &query.clone().without_summary()), expected.clone());
+            prop_assert_eq!(get_totals(&db, &query), expected);
+        }
+    }
+
+    /// Returns the stats and how long the query took at best in a few runs.
+    fn time_stats(db: &Db, query: &StatsQuery) -> (Totals, Duration) {
+        let mut fastest = Duration::MAX;
+        let mut totals = Totals::new();
+        for _ in 0..3 {
+            let started = Instant::now();
+            totals = get_totals(db, query);
+            fastest = fastest.min(started.elapsed());
+        }
+        (totals, fastest)
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`, generating the intervals takes
+    /// a while.
+    #[test]
+    #[ignore]
+    fn summary_speeds_up_long_periods() {
+        let db = Db::open_in_memory().unwrap();
+        let calendar = Calendar::default();
+        // 3M minutes of intervals, almost six years up to 2024-06-03
+        let time_to = START_SECONDS * 1000;
+        let count = 3_000_000;
+        generate_intervals(&db, count, time_to - count * 60_000, 60_000);
+        let started = Instant::now();
+        db.check_summary(&calendar).unwrap();
+        println!("summary of {count} intervals built in {:?}", started.elapsed());
+        let periods = [
+            ("total", None),
+            ("last 365 days", Some((time_to - 365 * 86_400_000 - 3_600_000, time_to))),
+            ("last 30 days", Some((time_to - 30 * 86_400_000 - 3_600_000, time_to))),
+        ];
+        for (name, period) in periods {
+            let query = StatsQuery::new().period(period).calendar(calendar);
+            let (summed, with_summary) = time_stats(&db, &query);
+            let (counted, without_summary) = time_stats(&db, &query.clone().without_summary());
+            println!("{name}: {with_summary:?} with the summary, {without_summary:?} without");
+            assert_eq!(summed, counted, "{name}");
+            if period.is_none() {
+                assert!(with_summary * 10 < without_summary, "{name}");
+            }
+        }
+    }
+}
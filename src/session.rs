@@ -0,0 +1,289 @@
+use crate::db::DowntimeKind;
+use std::error::Error;
+#[cfg(windows)]
+use chrono::Local;
+#[cfg(target_os = "linux")]
+use {
+    chrono::Local,
+    std::collections::HashMap,
+    std::sync::Arc,
+    std::thread,
+    zbus::blocking::{Connection, MessageIterator},
+    zbus::message::Type,
+    zbus::zvariant::{OwnedObjectPath, OwnedValue},
+    zbus::MatchRule,
+};
+#[cfg(windows)]
+use windows::{
+    core::w,
+    Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+    Win32::System::LibraryLoader::GetModuleHandleW,
+    Win32::System::RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
+    Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, GetWindowLongPtrW, RegisterClassW, SetWindowLongPtrW,
+        GWLP_USERDATA, HMENU, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WINDOW_EX_STYLE, WINDOW_STYLE,
+        WM_POWERBROADCAST, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+    },
+};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SessionEvent {
+    Lock,
+    Unlock,
+    Suspend,
+    Resume,
+}
+
+/// A source of lock and power notifications.
+pub trait SessionEvents {
+    /// Starts passing each event with the time it happened at to `callback`, which may be
+    /// called from another thread.
+    fn listen(self, callback: impl Fn(SessionEvent, i64) + Send + Sync + 'static) -> Result<(), Box<dyn Error>>;
+}
+
+/// Replays the given events and times right away, for tests.
+pub struct FakeSessionEvents(pub Vec<(SessionEvent, i64)>);
+
+impl SessionEvents for FakeSessionEvents {
+    fn listen(self, callback: impl Fn(SessionEvent, i64) + Send + Sync + 'static) -> Result<(), Box<dyn Error>> {
+        for (event, time) in self.0 {
+            callback(event, time);
+        }
+        Ok(())
+    }
+}
+
+/// Follows session events and tells when nothing should be recorded. Sleep takes precedence
+/// over the lock screen, so the spans it reports never overlap.
+#[derive(Default)]
+pub struct SessionState {
+    locked: bool,
+    pause: Option<(DowntimeKind, i64)>,
+}
+
+impl SessionState {
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    /// Applies an event that happened at `time`. Returns the pause it ended as the kind,
+    /// start and end of the span, which the tracker records as downtime.
+    pub fn handle(&mut self, event: SessionEvent, time: i64) -> Option<(DowntimeKind, i64, i64)> {
+        let ended = match (event, self.pause) {
+            (SessionEvent::Lock, None) => {
+                self.pause = Some((DowntimeKind::Lock, time));
+                None
+            }
+            (SessionEvent::Suspend, Some((DowntimeKind::Sleep, _))) => None,
+            (SessionEvent::Suspend, pause) => {
+                self.pause = Some((DowntimeKind::Sleep, time));
+                pause
+            }
+            (SessionEvent::Resume, Some((DowntimeKind::Sleep, since))) => {
+                self.pause = self.locked.then_some((DowntimeKind::Lock, time));
+                Some((DowntimeKind::Sleep, since))
+            }
+            (SessionEvent::Unlock, Some((DowntimeKind::Lock, since))) => {
+                self.pause = None;
+                Some((DowntimeKind::Lock, since))
+            }
+            _ => None,
+        };
+        match event {
+            SessionEvent::Lock => self.locked = true,
+            SessionEvent::Unlock => self.locked = false,
+            _ => {}
+        }
+        ended.map(|(kind, since)| (kind, since, time))
+    }
+}
+
+#[cfg(windows)]
+type Callback = Box<dyn Fn(SessionEvent, i64)>;
+
+/// Receives lock and power notifications through a hidden window. The notifications are
+/// dispatched by the thread's message loop, so it has to be created on the thread running
+/// `Tray::handle_win_messages`.
+#[cfg(windows)]
+pub struct SessionListener;
+
+#[cfg(windows)]
+impl SessionEvents for SessionListener {
+    fn listen(self, callback: impl Fn(SessionEvent, i64) + Send + Sync + 'static) -> Result<(), Box<dyn Error>> {
+        Self::init(callback);
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl SessionListener {
+    pub fn init(callback: impl Fn(SessionEvent, i64) + 'static) {
+        let class_name = w!("AppTimeSessionListener");
+        let hwnd = unsafe {
+            let instance: HINSTANCE = GetModuleHandleW(None).unwrap().into();
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(Self::window_proc),
+                hInstance: instance,
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+            RegisterClassW(&class);
+            // a message-only window would not receive power broadcasts, so this one is just never shown
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                class_name,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND::default(),
+                HMENU::default(),
+                instance,
+                None,
+            ).unwrap()
+        };
+        let callback: Box<Callback> = Box::new(Box::new(callback));
+        unsafe {
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(callback) as isize);
+            WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION).unwrap();
+        }
+    }
+
+    unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        let event = match (msg, wparam.0 as u32) {
+            (WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK) => Some(SessionEvent::Lock),
+            (WM_WTSSESSION_CHANGE, WTS_SESSION_UNLOCK) => Some(SessionEvent::Unlock),
+            (WM_POWERBROADCAST, PBT_APMSUSPEND) => Some(SessionEvent::Suspend),
+            (WM_POWERBROADCAST, PBT_APMRESUMEAUTOMATIC) => Some(SessionEvent::Resume),
+            _ => None,
+        };
+        if let Some(event) = event {
+            let callback = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const Callback;
+            if !callback.is_null() {
+                (*callback)(event, Local::now().timestamp_millis());
+            }
+        }
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+}
+
+#[cfg(target_os = "linux")]
+const LOGIND: &str = "org.freedesktop.login1";
+#[cfg(target_os = "linux")]
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+/// Follows logind on the system bus: the `LockedHint` of the current session, which screen
+/// lockers set, and `PrepareForSleep`, sent before a suspend or hibernate and after the resume.
+#[cfg(target_os = "linux")]
+pub struct LogindSessionEvents;
+
+#[cfg(target_os = "linux")]
+impl SessionEvents for LogindSessionEvents {
+    fn listen(self, callback: impl Fn(SessionEvent, i64) + Send + Sync + 'static) -> Result<(), Box<dyn Error>> {
+        let connection = Connection::system()?;
+        let session: OwnedObjectPath = connection
+            .call_method(Some(LOGIND), "/org/freedesktop/login1", Some(LOGIND_MANAGER), "GetSession", &("auto",))?
+            .body()
+            .deserialize()?;
+        let sleep = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender(LOGIND)?
+            .interface(LOGIND_MANAGER)?
+            .member("PrepareForSleep")?
+            .build();
+        let lock = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender(LOGIND)?
+            .path(session)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .build();
+        let sleep = MessageIterator::for_match_rule(sleep, &connection, None)?;
+        let lock = MessageIterator::for_match_rule(lock, &connection, None)?;
+        let callback = Arc::new(callback);
+        let sleep_callback = callback.clone();
+        thread::spawn(move || {
+            for message in sleep.flatten() {
+                if let Ok(start) = message.body().deserialize::<bool>() {
+                    let event = if start { SessionEvent::Suspend } else { SessionEvent::Resume };
+                    sleep_callback(event, Local::now().timestamp_millis());
+                }
+            }
+        });
+        thread::spawn(move || {
+            for message in lock.flatten() {
+                let Ok((_, changed, _)) = message.body().deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>() else {
+                    continue;
+                };
+                if let Some(Ok(locked)) = changed.get("LockedHint").map(|value| value.downcast_ref::<bool>()) {
+                    let event = if locked { SessionEvent::Lock } else { SessionEvent::Unlock };
+                    callback(event, Local::now().timestamp_millis());
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use SessionEvent::*;
+
+    /// Passes the events through the fake source, returns the state and the downtime it ended.
+    fn replay(events: &[(SessionEvent, i64)]) -> (SessionState, Vec<(DowntimeKind, i64, i64)>) {
+        let (sender, receiver) = mpsc::channel();
+        FakeSessionEvents(events.to_vec()).listen(move |event, time| {
+            let _ = sender.send((event, time));
+        }).unwrap();
+        let mut state = SessionState::default();
+        let ended = receiver.iter().filter_map(|(event, time)| state.handle(event, time)).collect();
+        (state, ended)
+    }
+
+    #[test]
+    fn lock_is_ended_by_unlock() {
+        let (state, ended) = replay(&[(Lock, 10), (Unlock, 30)]);
+        assert!(!state.is_paused());
+        assert_eq!(ended, [(DowntimeKind::Lock, 10, 30)]);
+        let (state, ended) = replay(&[(Lock, 10)]);
+        assert!(state.is_paused());
+        assert!(ended.is_empty());
+    }
+
+    #[test]
+    fn repeated_and_unmatched_events_are_ignored() {
+        let (state, ended) = replay(&[(Unlock, 5), (Resume, 6), (Lock, 10), (Lock, 20), (Unlock, 30), (Unlock, 40)]);
+        assert!(!state.is_paused());
+        assert_eq!(ended, [(DowntimeKind::Lock, 10, 30)]);
+        let (state, ended) = replay(&[(Suspend, 10), (Suspend, 20), (Resume, 30), (Resume, 40)]);
+        assert!(!state.is_paused());
+        assert_eq!(ended, [(DowntimeKind::Sleep, 10, 30)]);
+    }
+
+    #[test]
+    fn sleep_interrupts_the_lock_screen() {
+        // the screen stays locked after the resume until it is unlocked
+        let (state, ended) = replay(&[(Lock, 10), (Suspend, 20), (Resume, 50), (Unlock, 60)]);
+        assert!(!state.is_paused());
+        assert_eq!(ended, [
+            (DowntimeKind::Lock, 10, 20),
+            (DowntimeKind::Sleep, 20, 50),
+            (DowntimeKind::Lock, 50, 60),
+        ]);
+    }
+
+    #[test]
+    fn locking_while_asleep_is_recorded_after_the_resume() {
+        // lockers often lock the screen as the system goes to sleep
+        let (state, ended) = replay(&[(Suspend, 10), (Lock, 11), (Resume, 50)]);
+        assert!(state.is_paused());
+        assert_eq!(ended, [(DowntimeKind::Sleep, 10, 50)]);
+        let (state, ended) = replay(&[(Suspend, 10), (Lock, 11), (Unlock, 12), (Resume, 50)]);
+        assert!(!state.is_paused());
+        assert_eq!(ended, [(DowntimeKind::Sleep, 10, 50)]);
+    }
+}
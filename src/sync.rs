@@ -0,0 +1,314 @@
+use crate::config::Config;
+use crate::datetime::Calendar;
+use crate::db::{Change, Db};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const LOG_EXTENSION: &str = "jsonl";
+
+/// A folder shared between devices. Every device appends the changes to the intervals it records
+/// to its own change log there, one JSON object per line, and merges the logs of the others. A log is
+/// only ever written by its own device, so a folder synced by a cloud drive never conflicts.
+pub struct SyncFolder {
+    dir: PathBuf,
+    device: String,
+    /// The days merged intervals are in are summed up again.
+    calendar: Calendar,
+}
+
+impl SyncFolder {
+    pub fn new(dir: PathBuf, device: String, calendar: Calendar) -> Self {
+        SyncFolder { dir, device, calendar }
+    }
+
+    /// Returns None when no folder is configured.
+    pub fn from_config(config: &Config, db: &Db) -> Result<Option<SyncFolder>, Box<dyn Error>> {
+        let Some(dir) = config.sync.dir.clone() else {
+            return Ok(None);
+        };
+        Ok(Some(Self::new(dir, config.sync.get_device(db)?, config.calendar)))
+    }
+
+    pub fn get_device(&self) -> &String {
+        &self.device
+    }
+
+    /// Writes out the intervals recorded here, then merges the ones of the other devices.
+    /// Returns how many intervals were exported and imported.
+    pub fn sync(&self, db: &Db) -> Result<(usize, usize), Box<dyn Error>> {
+        Ok((self.export(db)?, self.import(db)?))
+    }
+
+    /// Appends the intervals recorded here that were added, changed or deleted since the last
+    /// export to this device's change log. A deleted interval is written as its rowid only.
+    pub fn export(&self, db: &Db) -> Result<usize, Box<dyn Error>> {
+        let changes = db.get_unexported_changes()?;
+        let Some((last_id, _)) = changes.last() else {
+            return Ok(0);
+        };
+        let mut lines = String::new();
+        for (_, change) in &changes {
+            lines.push_str(&serde_json::to_string(change)?);
+            lines.push('\n');
+        }
+        fs::create_dir_all(&self.dir)?;
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.get_log_path())?;
+        log.write_all(lines.as_bytes())?;
+        db.delete_exported_changes(*last_id)?;
+        Ok(changes.len())
+    }
+
+    /// Merges what the other devices appended to their change logs since the last import.
+    pub fn import(&self, db: &Db) -> Result<usize, Box<dyn Error>> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let mut merged = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new(LOG_EXTENSION)) {
+                continue;
+            }
+            let Some(device) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if device != self.device {
+                merged += self.import_log(db, &path, &device)?;
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Only complete lines are merged, a line that is still being written is read next time.
+    fn import_log(&self, db: &Db, path: &Path, device: &String) -> Result<usize, Box<dyn Error>> {
+        let mut position = db.get_sync_import(device)?;
+        let mut log = File::open(path)?;
+        // a log that got shorter was replaced, so it is read again from the start
+        if log.metadata()?.len() < position {
+            position = 0;
+        }
+        log.seek(SeekFrom::Start(position))?;
+        let mut reader = BufReader::new(log);
+        let mut line = String::new();
+        let mut merged = 0;
+        let mut touched = Vec::new();
+        db.in_transaction(|| {
+            loop {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 || !line.ends_with('\n') {
+                    break;
+                }
+                position += read as u64;
+                // a line that cannot be parsed is skipped rather than holding up the rest of the log
+                if let Ok(change) = serde_json::from_str::<Change>(&line) {
+                    touched.extend(db.merge_interval(device, &change)?);
+                    merged += 1;
+                }
+            }
+            db.refresh_summary(touched.iter(), &self.calendar)?;
+            db.save_sync_import(device, position)
+        })?;
+        Ok(merged)
+    }
+
+    fn get_log_path(&self) -> PathBuf {
+        let mut path = self.dir.clone();
+        path.push(format!("{}.{LOG_EXTENSION}", self.device));
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SyncConfig;
+    use crate::datetime::from_local;
+    use crate::db::Interval;
+    use crate::query::{GroupBy, StatKey, StatsQuery};
+    use chrono::{Local, NaiveDate, NaiveDateTime};
+    use std::env;
+    use tempfile::TempDir;
+
+    /// A device with its database and sync folder in a directory of its own.
+    struct Device {
+        db: Db,
+        folder: SyncFolder,
+        _dir: TempDir,
+    }
+
+    impl Device {
+        fn new(name: &str) -> Device {
+            let dir = TempDir::new().unwrap();
+            let db = Db::open(&dir.path().join("db.sqlite")).unwrap();
+            db.check_summary(&Calendar::default()).unwrap();
+            let folder = SyncFolder::new(dir.path().join("sync"), name.to_string(), Calendar::default());
+            Device { db, folder, _dir: dir }
+        }
+
+        /// Copies the change logs of the other device over, as a cloud drive would.
+        fn receive(&self, other: &Device) {
+            fs::create_dir_all(&self.folder.dir).unwrap();
+            fs::copy(other.folder.get_log_path(), self.folder.dir.join(other.folder.get_log_path().file_name().unwrap())).unwrap();
+        }
+
+        fn add(&self, app: &str, from: &str, to: &str) -> i64 {
+            let interval = Interval {
+                rowid: 0,
+                window_name: app.to_string(),
+                title: Some(format!("{app} title")),
+                project: None,
+                time_from: at(from),
+                time_to: at(to),
+                utc_offset: Some(offset(from)),
+                manual: true,
+            };
+            self.db.add_interval(&interval, &Calendar::default()).unwrap()
+        }
+
+        /// Time per app and day spent here, or on `device` when given, read once from the summary
+        /// and once from the intervals, which have to agree.
+        fn get_stats(&self, device: Option<&str>) -> Vec<(StatKey, Option<NaiveDate>, i64)> {
+            let query = StatsQuery::new().device(device.map(String::from)).group_by(GroupBy::App).per_day();
+            let read = |query: &StatsQuery| self.db.get_stats(query).unwrap().into_iter()
+                .map(|stat| (stat.key, stat.day, stat.millis))
+                .collect::<Vec<_>>();
+            let stats = read(&query);
+            assert_eq!(stats, read(&query.without_summary()), "summary of {device:?}");
+            stats
+        }
+    }
+
+    fn local(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn at(time: &str) -> i64 {
+        from_local(&Local, local(time)).timestamp_millis()
+    }
+
+    fn offset(time: &str) -> i32 {
+        from_local(&Local, local(time)).offset().local_minus_utc()
+    }
+
+    /// Exports what `from` changed and imports it into `to`.
+    fn sync(from: &Device, to: &Device) -> usize {
+        from.folder.export(&from.db).unwrap();
+        to.receive(from);
+        to.folder.import(&to.db).unwrap()
+    }
+
+    fn assert_in_sync(a: &Device, b: &Device) {
+        assert_eq!(b.get_stats(Some("a")), a.get_stats(None));
+    }
+
+    #[test]
+    fn edits_deletions_and_undos_are_merged() {
+        let (a, b) = (Device::new("a"), Device::new("b"));
+        let x = a.add("x.exe", "2024-06-10 09:00", "2024-06-10 10:00");
+        let y = a.add("y.exe", "2024-06-10 10:00", "2024-06-10 11:00");
+        assert_eq!(sync(&a, &b), 2);
+        assert_in_sync(&a, &b);
+
+        // the rowid of the deleted interval is not given to the next one
+        a.db.delete_interval(y, &Calendar::default()).unwrap();
+        let z = a.add("z.exe", "2024-06-10 12:00", "2024-06-10 12:30");
+        assert_ne!(z, y);
+        let edited = Interval { time_to: at("2024-06-10 09:30"), ..a.db.get_interval(x).unwrap().unwrap() };
+        a.db.edit_interval(&edited, &Calendar::default()).unwrap();
+        assert_eq!(sync(&a, &b), 3);
+        assert_in_sync(&a, &b);
+
+        a.db.undo(&Calendar::default()).unwrap();
+        a.db.undo(&Calendar::default()).unwrap();
+        a.db.undo(&Calendar::default()).unwrap();
+        sync(&a, &b);
+        assert_in_sync(&a, &b);
+        assert_eq!(b.get_stats(Some("a")).len(), 2);
+    }
+
+    #[test]
+    fn logs_are_read_from_where_the_last_import_stopped() {
+        let (a, b) = (Device::new("a"), Device::new("b"));
+        a.add("x.exe", "2024-06-10 09:00", "2024-06-10 10:00");
+        assert_eq!(sync(&a, &b), 1);
+        // importing the same log again merges nothing new
+        assert_eq!(b.folder.import(&b.db).unwrap(), 0);
+        a.add("y.exe", "2024-06-10 10:00", "2024-06-10 11:00");
+        a.folder.export(&a.db).unwrap();
+        assert_eq!(a.folder.export(&a.db).unwrap(), 0);
+
+        // a line still being copied is left for the next import
+        let log = fs::read_to_string(a.folder.get_log_path()).unwrap();
+        let copied = b.folder.dir.join("a.jsonl");
+        fs::write(&copied, &log[..log.len() - 10]).unwrap();
+        assert_eq!(b.folder.import(&b.db).unwrap(), 0);
+        assert_eq!(b.db.get_sync_import(&"a".to_string()).unwrap(), log.find('\n').unwrap() as u64 + 1);
+        fs::write(&copied, &log).unwrap();
+        assert_eq!(b.folder.import(&b.db).unwrap(), 1);
+        assert_eq!(b.db.get_sync_import(&"a".to_string()).unwrap(), log.len() as u64);
+        assert_in_sync(&a, &b);
+
+        // reading the whole log again, or a shorter one that replaced it, gives the same intervals
+        b.db.save_sync_import(&"a".to_string(), 0).unwrap();
+        assert_eq!(b.folder.import(&b.db).unwrap(), 2);
+        assert_in_sync(&a, &b);
+        fs::write(&copied, &log[log.find('\n').unwrap() + 1..]).unwrap();
+        assert_eq!(b.folder.import(&b.db).unwrap(), 1);
+        assert_in_sync(&a, &b);
+    }
+
+    #[test]
+    fn merged_intervals_are_summed_up_per_day() {
+        let (a, b) = (Device::new("a"), Device::new("b"));
+        let rowid = a.add("x.exe", "2024-06-10 22:00", "2024-06-11 02:00");
+        sync(&a, &b);
+        let hours = |day: &str, hours: i64| (StatKey::App("x.exe".to_string()), Some(day.parse().unwrap()), hours * 3_600_000);
+        assert_eq!(b.get_stats(Some("a")), [hours("2024-06-10", 2), hours("2024-06-11", 2)]);
+
+        // the days the interval was moved away from are summed up again as well
+        let moved = Interval {
+            time_from: at("2024-06-11 23:00"),
+            time_to: at("2024-06-12 04:00"),
+            ..a.db.get_interval(rowid).unwrap().unwrap()
+        };
+        a.db.edit_interval(&moved, &Calendar::default()).unwrap();
+        sync(&a, &b);
+        assert_eq!(b.get_stats(Some("a")), [hours("2024-06-11", 1), hours("2024-06-12", 4)]);
+        assert_in_sync(&a, &b);
+
+        a.db.delete_interval(rowid, &Calendar::default()).unwrap();
+        sync(&a, &b);
+        assert!(b.get_stats(Some("a")).is_empty());
+        assert!(b.db.get_devices().unwrap().is_empty());
+    }
+
+    #[test]
+    fn device_without_a_name_keeps_the_one_made_up_for_its_database() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let device = SyncConfig::default();
+        if env::var_os("COMPUTERNAME").is_none() {
+            let name = device.get_device(&Db::open(&path).unwrap()).unwrap();
+            assert_eq!(name.len(), 16);
+            // the same after the database is opened again, and another one for another database
+            assert_eq!(device.get_device(&Db::open(&path).unwrap()).unwrap(), name);
+            assert_ne!(device.get_device(&Db::open_in_memory().unwrap()).unwrap(), name);
+        }
+
+        let mut config = Config::default();
+        config.sync.dir = Some(dir.path().join("sync"));
+        config.sync.device = Some("laptop".to_string());
+        let folder = SyncFolder::from_config(&config, &Db::open(&path).unwrap()).unwrap().unwrap();
+        assert_eq!(folder.get_device(), "laptop");
+        config.sync.dir = None;
+        assert!(SyncFolder::from_config(&config, &Db::open(&path).unwrap()).unwrap().is_none());
+    }
+
+}
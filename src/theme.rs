@@ -0,0 +1,247 @@
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+
+/// Colors apps and categories are told apart by, in the UI as well as in the digest.
+pub const PALETTE: [Color; 8] = [
+    Color::Rgb(0x4e, 0x79, 0xa7),
+    Color::Rgb(0xf2, 0x8e, 0x2b),
+    Color::Rgb(0x59, 0xa1, 0x4f),
+    Color::Rgb(0xe1, 0x57, 0x59),
+    Color::Rgb(0x76, 0xb7, 0xb2),
+    Color::Rgb(0xed, 0xc9, 0x48),
+    Color::Rgb(0xb0, 0x7a, 0xa1),
+    Color::Rgb(0x9c, 0x75, 0x5f),
+];
+
+/// The built-in themes, picked by `theme` in the `[ui]` section of the config.
+#[derive(Deserialize, Copy, Clone, PartialEq, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Styles of the parts of the UI.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub text: Style,
+    /// The selected tab, view, row or field while it has the focus.
+    pub highlight: Style,
+    /// The selected tab or row while something else has the focus.
+    pub inactive: Style,
+    /// The keys in the help and the part of a date being changed.
+    pub accent: Style,
+    pub muted: Style,
+    /// More time than before, productive time and goals that are met.
+    pub positive: Style,
+    /// Less time than before, distracting time and limits that are exceeded.
+    pub negative: Style,
+    /// Goals not met yet.
+    pub pending: Style,
+    /// Bars of the rows that are not apps, or of all rows without app colors.
+    pub bar: Style,
+    /// Whether the bars of apps are drawn in the colors of the apps.
+    pub app_colors: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new(ThemeName::Dark)
+    }
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Theme {
+        match name {
+            ThemeName::Dark => Theme {
+                text: Style::default().white(),
+                highlight: Style::default().on_yellow().black(),
+                inactive: Style::default().on_dark_gray().white(),
+                accent: Style::default().yellow(),
+                muted: Style::default().dark_gray(),
+                positive: Style::default().green(),
+                negative: Style::default().red(),
+                pending: Style::default().yellow(),
+                bar: Style::default().yellow(),
+                app_colors: true,
+            },
+            ThemeName::Light => Theme {
+                text: Style::default().black(),
+                highlight: Style::default().on_blue().white(),
+                inactive: Style::default().on_gray().black(),
+                accent: Style::default().blue(),
+                muted: Style::default().gray(),
+                positive: Style::default().green(),
+                negative: Style::default().red(),
+                pending: Style::default().blue(),
+                bar: Style::default().blue(),
+                app_colors: true,
+            },
+            ThemeName::HighContrast => Theme {
+                text: Style::default().white(),
+                highlight: Style::default().on_white().black().bold(),
+                inactive: Style::default().white().bold().underlined(),
+                accent: Style::default().light_yellow().bold(),
+                muted: Style::default().white(),
+                positive: Style::default().light_green().bold(),
+                negative: Style::default().light_red().bold(),
+                pending: Style::default().light_yellow(),
+                bar: Style::default().white(),
+                app_colors: false,
+            },
+        }
+    }
+
+    /// Only bold, underlined and reversed text, for terminals and people that do without colors.
+    pub fn no_color() -> Theme {
+        Theme {
+            text: Style::default(),
+            highlight: Style::default().add_modifier(Modifier::REVERSED),
+            inactive: Style::default().underlined(),
+            accent: Style::default().bold(),
+            muted: Style::default(),
+            positive: Style::default(),
+            negative: Style::default(),
+            pending: Style::default(),
+            bar: Style::default(),
+            app_colors: false,
+        }
+    }
+
+    /// The theme with the given name, or the one without colors when the `NO_COLOR`
+    /// environment variable is set to anything, see https://no-color.org.
+    pub fn load(name: ThemeName) -> Theme {
+        match env::var_os("NO_COLOR") {
+            Some(value) if !value.is_empty() => Theme::no_color(),
+            _ => Theme::new(name),
+        }
+    }
+
+    /// The style of the bar of `app`, in its color from `colors` if the theme has app colors.
+    pub fn get_app_bar(&self, app: &str, colors: &HashMap<String, String>) -> Style {
+        if self.app_colors { self.bar.fg(get_app_color(app, colors)) } else { self.bar }
+    }
+}
+
+/// Reads a color like "red", "lightblue", "#4e79a7" or an index of the 256 terminal colors.
+pub fn parse_color(value: &str) -> Option<Color> {
+    Color::from_str(value).ok()
+}
+
+/// The color of `app` set in `colors`, or one of the palette picked by its name so it stays
+/// the same everywhere.
+pub fn get_app_color(app: &str, colors: &HashMap<String, String>) -> Color {
+    if let Some(color) = colors.get(app).and_then(|color| parse_color(color)) {
+        return color;
+    }
+    // FNV-1a, unlike the hasher of the standard library it does not change between versions
+    let hash = app.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    PALETTE[hash as usize % PALETTE.len()]
+}
+
+/// The color as CSS, for the digest. The named terminal colors are given the values of the
+/// default Windows console.
+pub fn to_css(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0x0c, 0x0c, 0x0c),
+        Color::Red => (0xc5, 0x0f, 0x1f),
+        Color::Green => (0x13, 0xa1, 0x0e),
+        Color::Yellow => (0xc1, 0x9c, 0x00),
+        Color::Blue => (0x00, 0x37, 0xda),
+        Color::Magenta => (0x88, 0x17, 0x98),
+        Color::Cyan => (0x3a, 0x96, 0xdd),
+        Color::Gray => (0xcc, 0xcc, 0xcc),
+        Color::DarkGray => (0x76, 0x76, 0x76),
+        Color::LightRed => (0xe7, 0x48, 0x56),
+        Color::LightGreen => (0x16, 0xc6, 0x0c),
+        Color::LightYellow => (0xf9, 0xf1, 0xa5),
+        Color::LightBlue => (0x3b, 0x78, 0xff),
+        Color::LightMagenta => (0xb4, 0x00, 0x9e),
+        Color::LightCyan => (0x61, 0xd6, 0xd6),
+        Color::White => (0xf2, 0xf2, 0xf2),
+        _ => return "#777".to_string(),
+    };
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Ui {
+        #[serde(default)]
+        theme: ThemeName,
+    }
+
+    #[test]
+    fn no_color_is_set_by_any_value() {
+        // the only test that touches the environment, so it cannot race with another one
+        env::set_var("NO_COLOR", "");
+        assert!(Theme::load(ThemeName::Light).app_colors);
+        env::set_var("NO_COLOR", "1");
+        let theme = Theme::load(ThemeName::Light);
+        assert!(!theme.app_colors);
+        assert_eq!(theme.text, Style::default());
+        assert_eq!(theme.highlight, Style::default().add_modifier(Modifier::REVERSED));
+        env::remove_var("NO_COLOR");
+        assert_eq!(Theme::load(ThemeName::Light).text, Style::default().black());
+    }
+
+    #[test]
+    fn theme_is_picked_by_its_name() {
+        let name = |toml: &str| toml::from_str::<Ui>(toml).unwrap().theme;
+        assert_eq!(name(""), ThemeName::Dark);
+        assert_eq!(name("theme = 'light'"), ThemeName::Light);
+        assert_eq!(name("theme = 'high-contrast'"), ThemeName::HighContrast);
+        assert!(toml::from_str::<Ui>("theme = 'solarized'").is_err());
+
+        assert_eq!(Theme::new(ThemeName::Dark).bar, Style::default().yellow());
+        assert_eq!(Theme::new(ThemeName::Light).bar, Style::default().blue());
+        assert!(!Theme::new(ThemeName::HighContrast).app_colors);
+    }
+
+    #[test]
+    fn app_colors_can_be_overridden() {
+        let colors = HashMap::from([
+            ("firefox.exe".to_string(), "#ff0000".to_string()),
+            ("code.exe".to_string(), "lightblue".to_string()),
+            ("cmd.exe".to_string(), "42".to_string()),
+        ]);
+        assert_eq!(get_app_color("firefox.exe", &colors), Color::Rgb(0xff, 0, 0));
+        assert_eq!(get_app_color("code.exe", &colors), Color::LightBlue);
+        assert_eq!(get_app_color("cmd.exe", &colors), Color::Indexed(42));
+
+        // the same color from the palette every time
+        let color = get_app_color("explorer.exe", &colors);
+        assert!(PALETTE.contains(&color));
+        assert_eq!(get_app_color("explorer.exe", &HashMap::new()), color);
+
+        let dark = Theme::new(ThemeName::Dark);
+        assert_eq!(dark.get_app_bar("firefox.exe", &colors), dark.bar.fg(Color::Rgb(0xff, 0, 0)));
+        let high_contrast = Theme::new(ThemeName::HighContrast);
+        assert_eq!(high_contrast.get_app_bar("firefox.exe", &colors), high_contrast.bar);
+    }
+
+    #[test]
+    fn invalid_colors_fall_back_to_the_palette() {
+        for value in ["", "#12345", "#gggggg", "not a color", "256"] {
+            assert_eq!(parse_color(value), None, "{value:?}");
+        }
+        let colors = HashMap::from([("firefox.exe".to_string(), "#gggggg".to_string())]);
+        assert_eq!(get_app_color("firefox.exe", &colors), get_app_color("firefox.exe", &HashMap::new()));
+    }
+
+    #[test]
+    fn colors_are_written_as_css() {
+        assert_eq!(to_css(Color::Rgb(0x4e, 0x79, 0xa7)), "#4e79a7");
+        assert_eq!(to_css(Color::Red), "#c50f1f");
+        assert_eq!(to_css(Color::Indexed(42)), "#777");
+    }
+}
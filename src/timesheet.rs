@@ -0,0 +1,279 @@
+use crate::query::Stat;
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+const MILLIS_PER_MINUTE: i64 = 60_000;
+const MILLIS_PER_HOUR: i64 = 60 * MILLIS_PER_MINUTE;
+
+#[derive(Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+impl RoundingMode {
+    pub fn parse(value: &str) -> Option<RoundingMode> {
+        match value {
+            "nearest" => Some(RoundingMode::Nearest),
+            "up" => Some(RoundingMode::Up),
+            "down" => Some(RoundingMode::Down),
+            _ => None,
+        }
+    }
+}
+
+/// How the time of every cell of a timesheet is rounded for billing, from the `[timesheet]`
+/// section of the config. Both values are in minutes.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct Rounding {
+    /// Time is rounded to a multiple of it, 0 keeps it to the minute.
+    pub increment: i64,
+    pub mode: RoundingMode,
+    /// Any time at all is billed at least this long.
+    pub minimum: i64,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding {
+            increment: 15,
+            mode: RoundingMode::Nearest,
+            minimum: 0,
+        }
+    }
+}
+
+impl Rounding {
+    pub fn apply(&self, millis: i64) -> i64 {
+        if millis <= 0 {
+            return 0;
+        }
+        let increment = self.increment.max(1) * MILLIS_PER_MINUTE;
+        let rounded = match self.mode {
+            RoundingMode::Nearest => (millis + increment / 2) / increment * increment,
+            RoundingMode::Up => (millis + increment - 1) / increment * increment,
+            RoundingMode::Down => millis / increment * increment,
+        };
+        rounded.max(self.minimum * MILLIS_PER_MINUTE)
+    }
+}
+
+/// Time per row and day, rounded cell by cell so the totals add up to what is billed.
+pub struct Timesheet {
+    /// What the rows are, e.g. "Project".
+    pub key_title: String,
+    pub days: Vec<NaiveDate>,
+    pub rows: Vec<TimesheetRow>,
+}
+
+pub struct TimesheetRow {
+    pub name: String,
+    /// Rounded time per day of `Timesheet::days`.
+    pub millis: Vec<i64>,
+}
+
+impl TimesheetRow {
+    pub fn get_total(&self) -> i64 {
+        self.millis.iter().sum()
+    }
+}
+
+impl Timesheet {
+    /// Builds the grid from stats split per day. Rows with no time left after rounding are
+    /// left out, the others are sorted by their total.
+    pub fn new(key_title: &str, days: Vec<NaiveDate>, stats: &[Stat], rounding: &Rounding) -> Timesheet {
+        let mut rows: Vec<TimesheetRow> = Vec::new();
+        for stat in stats {
+            let Some(column) = stat.day.and_then(|day| days.iter().position(|&date| date == day)) else {
+                continue;
+            };
+            let name = stat.key.to_string();
+            let row = match rows.iter().position(|row| row.name == name) {
+                Some(position) => &mut rows[position],
+                None => {
+                    rows.push(TimesheetRow { name, millis: vec![0; days.len()] });
+                    rows.last_mut().unwrap()
+                }
+            };
+            row.millis[column] += stat.millis;
+        }
+        for row in &mut rows {
+            row.millis = row.millis.iter().map(|&millis| rounding.apply(millis)).collect();
+        }
+        rows.retain(|row| row.get_total() > 0);
+        rows.sort_by(|a, b| b.get_total().cmp(&a.get_total()).then_with(|| a.name.cmp(&b.name)));
+        Timesheet { key_title: key_title.to_string(), days, rows }
+    }
+
+    pub fn get_day_totals(&self) -> Vec<i64> {
+        (0..self.days.len())
+            .map(|column| self.rows.iter().map(|row| row.millis[column]).sum())
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let header = self.get_header("%Y-%m-%d");
+        let mut csv = String::new();
+        for line in std::iter::once(header).chain(self.get_lines(true)) {
+            let fields: Vec<String> = line.iter().map(|field| escape_csv(field)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let header = self.get_header("%a %d.%m");
+        let mut markdown = format!("| {} |\n", header.join(" | "));
+        markdown.push_str(&format!("|---|{}\n", "---:|".repeat(header.len() - 1)));
+        for line in self.get_lines(false) {
+            let cells: Vec<String> = line.iter().map(|cell| cell.replace('|', "\\|")).collect();
+            markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+        markdown
+    }
+
+    pub fn to_text(&self) -> String {
+        let lines: Vec<Vec<String>> = std::iter::once(self.get_header("%a %d.%m"))
+            .chain(self.get_lines(false))
+            .collect();
+        let widths: Vec<usize> = (0..lines[0].len())
+            .map(|column| lines.iter().map(|line| line[column].chars().count()).max().unwrap_or(0))
+            .collect();
+        let mut text = String::new();
+        for line in &lines {
+            let cells: Vec<String> = line.iter().zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, &width))| {
+                    if column == 0 { format!("{cell:<width$}") } else { format!("{cell:>width$}") }
+                })
+                .collect();
+            text.push_str(cells.join("  ").trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    fn get_header(&self, day_format: &str) -> Vec<String> {
+        std::iter::once(self.key_title.clone())
+            .chain(self.days.iter().map(|day| day.format(day_format).to_string()))
+            .chain(std::iter::once("Total".to_string()))
+            .collect()
+    }
+
+    /// The rows and the totals as cells, empty days are left blank unless `zeros`.
+    fn get_lines(&self, zeros: bool) -> Vec<Vec<String>> {
+        let totals = TimesheetRow { name: "Total".to_string(), millis: self.get_day_totals() };
+        self.rows.iter()
+            .chain(std::iter::once(&totals))
+            .map(|row| {
+                let mut line = vec![row.name.clone()];
+                line.extend(row.millis.iter().map(|&millis| format_hours(millis, zeros)));
+                line.push(format_hours(row.get_total(), true));
+                line
+            })
+            .collect()
+    }
+}
+
+/// Formats the time as decimal hours, which invoices are usually written in.
+fn format_hours(millis: i64, zero: bool) -> String {
+    if millis == 0 && !zero {
+        return String::new();
+    }
+    format!("{:.2}", millis as f64 / MILLIS_PER_HOUR as f64)
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::StatKey;
+
+    fn minutes(minutes: i64) -> i64 {
+        minutes * MILLIS_PER_MINUTE
+    }
+
+    fn rounding(increment: i64, mode: RoundingMode, minimum: i64) -> Rounding {
+        Rounding { increment, mode, minimum }
+    }
+
+    #[test]
+    fn rounds_to_the_increment() {
+        let nearest = rounding(15, RoundingMode::Nearest, 0);
+        assert_eq!(nearest.apply(minutes(7)), 0);
+        assert_eq!(nearest.apply(minutes(7) + 30_000), minutes(15));
+        assert_eq!(nearest.apply(minutes(22)), minutes(15));
+        assert_eq!(nearest.apply(minutes(23)), minutes(30));
+
+        let up = rounding(15, RoundingMode::Up, 0);
+        assert_eq!(up.apply(1), minutes(15));
+        assert_eq!(up.apply(minutes(16)), minutes(30));
+
+        let down = rounding(15, RoundingMode::Down, 0);
+        assert_eq!(down.apply(minutes(14) + 59_999), 0);
+        assert_eq!(down.apply(minutes(29)), minutes(15));
+    }
+
+    #[test]
+    fn exact_multiples_stay_as_they_are() {
+        for mode in [RoundingMode::Nearest, RoundingMode::Up, RoundingMode::Down] {
+            for millis in [minutes(15), minutes(60), minutes(135)] {
+                assert_eq!(rounding(15, mode, 0).apply(millis), millis, "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn zero_increment_rounds_to_the_minute() {
+        assert_eq!(rounding(0, RoundingMode::Nearest, 0).apply(minutes(7) + 29_999), minutes(7));
+        assert_eq!(rounding(0, RoundingMode::Up, 0).apply(minutes(7) + 1), minutes(8));
+        assert_eq!(rounding(0, RoundingMode::Down, 0).apply(minutes(7) + 59_999), minutes(7));
+    }
+
+    #[test]
+    fn minimum_is_billed_for_any_time() {
+        let rounding = rounding(15, RoundingMode::Down, 30);
+        assert_eq!(rounding.apply(1), minutes(30));
+        assert_eq!(rounding.apply(minutes(44)), minutes(30));
+        assert_eq!(rounding.apply(minutes(45)), minutes(45));
+        // no time is not billed
+        assert_eq!(rounding.apply(0), 0);
+    }
+
+    #[test]
+    fn rows_add_up_the_rounded_days() {
+        let days: Vec<NaiveDate> = (3..=9).map(|day| NaiveDate::from_ymd_opt(2024, 6, day).unwrap()).collect();
+        let stat = |project: &str, day: usize, millis: i64| Stat {
+            key: StatKey::Project(Some(project.to_string())),
+            day: Some(days[day]),
+            millis,
+        };
+        let stats = [
+            stat("app-time", 0, minutes(50)),
+            stat("app-time", 2, minutes(20)),
+            stat("Invoices", 0, minutes(5)),
+            stat("Invoices", 4, minutes(100)),
+            // rounded away, so the row is left out
+            stat("Mail", 1, minutes(3)),
+            // outside of the week
+            Stat { key: StatKey::Project(Some("app-time".to_string())), day: NaiveDate::from_ymd_opt(2024, 6, 10), millis: minutes(60) },
+        ];
+        let timesheet = Timesheet::new("Project", days, &stats, &rounding(15, RoundingMode::Nearest, 0));
+        let rows: Vec<(&str, i64)> = timesheet.rows.iter().map(|row| (row.name.as_str(), row.get_total())).collect();
+        assert_eq!(rows, vec![("Invoices", minutes(105)), ("app-time", minutes(60))]);
+        assert_eq!(timesheet.rows[1].millis, vec![minutes(45), 0, minutes(15), 0, 0, 0, 0]);
+        assert_eq!(timesheet.get_day_totals(), vec![minutes(45), 0, minutes(15), 0, minutes(105), 0, 0]);
+    }
+}
@@ -1,28 +1,39 @@
-use std::{
-    error::Error,
-    ffi::OsString,
-    os::windows::ffi::OsStringExt,
-    path::PathBuf,
-    ptr::null_mut
-};
-
-use windows::Win32::{
-    Foundation::{HMODULE, HWND},
-    System::{
-        ProcessStatus::GetModuleFileNameExW,
-        Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
-    },
-    UI::WindowsAndMessaging::{
-        GetWindowTextW,
-        GetWindowThreadProcessId,
+#[cfg(windows)]
+use {
+    std::error::Error,
+    std::ffi::OsString,
+    std::os::windows::ffi::OsStringExt,
+    std::path::PathBuf,
+    std::ptr::null_mut,
+    windows::Win32::{
+        Foundation::{HMODULE, HWND},
+        System::{
+            ProcessStatus::GetModuleFileNameExW,
+            Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+        },
+        UI::WindowsAndMessaging::{
+            GetForegroundWindow,
+            GetWindowTextW,
+            GetWindowThreadProcessId,
+        },
     },
 };
-use windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+#[cfg(target_os = "linux")]
+use {
+    std::error::Error,
+    std::fs,
+    std::sync::OnceLock,
+    x11rb::connection::Connection,
+    x11rb::protocol::xproto::{self, Atom, AtomEnum, ConnectionExt},
+    x11rb::rust_connection::RustConnection,
+};
 
+#[cfg(windows)]
 pub struct Window {
     handle: HWND,
 }
 
+#[cfg(windows)]
 impl Window {
     pub fn get_active() -> Window {
         Window {
@@ -30,7 +41,7 @@ impl Window {
         }
     }
 
-    fn get_title(&self) -> Result<String, Box<dyn Error>> {
+    pub fn get_title(&self) -> Result<String, Box<dyn Error>> {
         let mut buffer = [0; 0x400];
         let title_length = unsafe { GetWindowTextW(self.handle, &mut buffer) };
         if title_length == 0 {
@@ -65,7 +76,7 @@ impl Window {
         Ok(file_name.to_string_lossy().into_owned())
     }
 
-    pub fn get_display_name(&self) -> Option<String> {
+    pub fn get_exe_name(&self) -> Option<String> {
         let exec_path = self
             .get_exec_path()
             .map_err(|err| {
@@ -82,14 +93,130 @@ impl Window {
             println!("Could not get file name from path: {}", exec_path_unwrapped);
             return None;
         }
-        if exe_name.unwrap().eq("ApplicationFrameHost.exe") {
+        Some(String::from(exe_name.unwrap()))
+    }
+
+    pub fn get_display_name(&self) -> Option<String> {
+        let exe_name = self.get_exe_name()?;
+        if exe_name.eq("ApplicationFrameHost.exe") {
             self.get_title()
                 .map_err(|err| {
                     println!("Could not get window title: {}", err);
                 })
                 .ok()
         } else {
-            Some(String::from(exe_name.unwrap()))
+            Some(exe_name)
         }
     }
 }
+
+/// The window the window manager announces as active, `None` on the desktop or without an
+/// X server.
+#[cfg(target_os = "linux")]
+pub struct Window {
+    handle: Option<xproto::Window>,
+}
+
+/// The connection to the X server and the atoms of the properties a window is sampled by.
+#[cfg(target_os = "linux")]
+struct X11 {
+    connection: RustConnection,
+    root: xproto::Window,
+    active_window: Atom,
+    wm_name: Atom,
+    wm_pid: Atom,
+    utf8_string: Atom,
+}
+
+#[cfg(target_os = "linux")]
+impl X11 {
+    /// Connects once, a tracker started without an X server records nothing.
+    fn get() -> Option<&'static X11> {
+        static X11: OnceLock<Option<X11>> = OnceLock::new();
+        X11.get_or_init(|| {
+            Self::connect()
+                .map_err(|err| {
+                    println!("Could not connect to the X server: {}", err);
+                })
+                .ok()
+        }).as_ref()
+    }
+
+    fn connect() -> Result<X11, Box<dyn Error>> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let root = connection.setup().roots[screen].root;
+        let intern = |name: &[u8]| -> Result<Atom, Box<dyn Error>> {
+            Ok(connection.intern_atom(false, name)?.reply()?.atom)
+        };
+        let active_window = intern(b"_NET_ACTIVE_WINDOW")?;
+        let wm_name = intern(b"_NET_WM_NAME")?;
+        let wm_pid = intern(b"_NET_WM_PID")?;
+        let utf8_string = intern(b"UTF8_STRING")?;
+        Ok(X11 { connection, root, active_window, wm_name, wm_pid, utf8_string })
+    }
+
+    fn get_property(&self, window: xproto::Window, property: impl Into<Atom>, type_: impl Into<Atom>) -> Result<xproto::GetPropertyReply, Box<dyn Error>> {
+        Ok(self.connection.get_property(false, window, property, type_, 0, 0x400)?.reply()?)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Window {
+    pub fn get_active() -> Window {
+        let handle = X11::get().and_then(|x11| {
+            let reply = x11.get_property(x11.root, x11.active_window, AtomEnum::WINDOW)
+                .map_err(|err| {
+                    println!("Could not get the active window: {}", err);
+                })
+                .ok()?;
+            let handle = reply.value32()?.next();
+            handle.filter(|&handle| handle != x11rb::NONE)
+        });
+        Window { handle }
+    }
+
+    pub fn get_title(&self) -> Result<String, Box<dyn Error>> {
+        let (Some(x11), Some(handle)) = (X11::get(), self.handle) else {
+            return Err("No active window".into());
+        };
+        let mut title = x11.get_property(handle, x11.wm_name, x11.utf8_string)?.value;
+        // windows not following EWMH only have the legacy name
+        if title.is_empty() {
+            title = x11.get_property(handle, AtomEnum::WM_NAME, AtomEnum::STRING)?.value;
+        }
+        if title.is_empty() {
+            return Err("Failed to get window title".into());
+        }
+        Ok(String::from_utf8_lossy(&title).into_owned())
+    }
+
+    fn get_process_id(&self) -> Result<u32, Box<dyn Error>> {
+        let (Some(x11), Some(handle)) = (X11::get(), self.handle) else {
+            return Err("No active window".into());
+        };
+        x11.get_property(handle, x11.wm_pid, AtomEnum::CARDINAL)?
+            .value32()
+            .and_then(|mut values| values.next())
+            .ok_or_else(|| "Could not get process id".into())
+    }
+
+    pub fn get_exe_name(&self) -> Option<String> {
+        // nothing is active on the desktop
+        self.handle?;
+        let exe_path = self.get_process_id()
+            .and_then(|process_id| Ok(fs::read_link(format!("/proc/{}/exe", process_id))?))
+            .map_err(|err| {
+                println!("Could not get exec path: {}", err);
+            })
+            .ok()?;
+        let exe_name = exe_path.file_name().and_then(|file_name| file_name.to_str());
+        if exe_name.is_none() {
+            println!("Could not get file name from path: {}", exe_path.display());
+        }
+        exe_name.map(String::from)
+    }
+
+    pub fn get_display_name(&self) -> Option<String> {
+        self.get_exe_name()
+    }
+}